@@ -0,0 +1,131 @@
+//! Compares `yuxa::convert` against the scalar loops the backends used before it, on a 4K
+//! frame. Run with `cargo bench --bench convert`.
+
+use std::io::{BufWriter, Write};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use yuxa::{convert, PixelFormat};
+
+const WIDTH: usize = 3840;
+const HEIGHT: usize = 2160;
+const PIXELS: usize = WIDTH * HEIGHT;
+
+fn source(bytes_per_pixel: usize) -> Vec<u8> {
+    (0..PIXELS * bytes_per_pixel)
+        .map(|i| (i * 7 % 251) as u8)
+        .collect()
+}
+
+/// The Wayland backend wrote every swizzled pixel through a `BufWriter`
+fn argb_bytes_buf_writer(source: &[u8], target: &mut Vec<u8>) {
+    target.clear();
+    let mut writer = BufWriter::new(target);
+    for pixel in source.chunks_exact(4) {
+        writer
+            .write_all(&[pixel[3], pixel[2], pixel[1], pixel[0]])
+            .unwrap();
+    }
+    writer.flush().unwrap();
+}
+
+/// The X11 backend assembled every pixel with shifts into a `Vec`
+fn argb_bytes_shifts(source: &[u8]) -> Vec<u32> {
+    let mut target = Vec::with_capacity(source.len() / 4);
+    for i in (0..source.len()).step_by(4) {
+        target.push(
+            u32::from(source[i]) << 24
+                | u32::from(source[i + 1]) << 16
+                | u32::from(source[i + 2]) << 8
+                | u32::from(source[i + 3]),
+        )
+    }
+    target
+}
+
+/// Straight alpha frames were premultiplied one pixel at a time
+fn premultiply_loop(pixels: &mut [u32]) {
+    for pixel in pixels {
+        let alpha = *pixel >> 24;
+        *pixel = match alpha {
+            0xFF => *pixel,
+            0 => 0,
+            _ => {
+                let scale = |channel: u32| {
+                    let product = channel * alpha + 0x80;
+                    (product + (product >> 8)) >> 8
+                };
+                (alpha << 24)
+                    | (scale((*pixel >> 16) & 0xFF) << 16)
+                    | (scale((*pixel >> 8) & 0xFF) << 8)
+                    | scale(*pixel & 0xFF)
+            }
+        };
+    }
+}
+
+fn argb_bytes(c: &mut Criterion) {
+    let source = source(4);
+    let mut bytes = Vec::with_capacity(source.len());
+    let mut target = vec![0; PIXELS];
+
+    let mut group = c.benchmark_group("argb8888 bytes 4K");
+    group.throughput(Throughput::Elements(PIXELS as u64));
+    group.bench_function("BufWriter loop", |b| {
+        b.iter(|| argb_bytes_buf_writer(black_box(&source), &mut bytes))
+    });
+    group.bench_function("shift loop", |b| {
+        b.iter(|| argb_bytes_shifts(black_box(&source)))
+    });
+    group.bench_function("convert", |b| {
+        b.iter(|| convert::argb_bytes_to_argb32(black_box(&source), &mut target))
+    });
+    group.finish();
+}
+
+fn formats(c: &mut Criterion) {
+    let formats = [
+        PixelFormat::Argb8888,
+        PixelFormat::Xrgb8888,
+        PixelFormat::Rgba8888,
+        PixelFormat::Bgra8888,
+        PixelFormat::Rgb888,
+        PixelFormat::Rgb565,
+        PixelFormat::Gray8,
+    ];
+    let mut target = vec![0; PIXELS];
+
+    for &format in &formats {
+        let source = source(format.bytes_per_pixel());
+        let mut group = c.benchmark_group(format!("{:?} 4K", format));
+        group.throughput(Throughput::Elements(PIXELS as u64));
+        group.bench_function("convert", |b| {
+            b.iter(|| convert::to_argb32(format, black_box(&source), &mut target))
+        });
+        group.finish();
+    }
+}
+
+fn premultiply(c: &mut Criterion) {
+    let mut straight = vec![0; PIXELS];
+    convert::to_argb32(PixelFormat::Argb8888, &source(4), &mut straight);
+    let mut pixels = straight.clone();
+
+    let mut group = c.benchmark_group("premultiply 4K");
+    group.throughput(Throughput::Elements(PIXELS as u64));
+    group.bench_function("scalar loop", |b| {
+        b.iter(|| {
+            pixels.copy_from_slice(&straight);
+            premultiply_loop(black_box(&mut pixels))
+        })
+    });
+    group.bench_function("convert", |b| {
+        b.iter(|| {
+            pixels.copy_from_slice(&straight);
+            convert::premultiply_argb32(black_box(&mut pixels))
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, argb_bytes, formats, premultiply);
+criterion_main!(benches);
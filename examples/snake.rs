@@ -1,12 +1,10 @@
-use std::io::prelude::*;
 use std::time::{Duration, Instant};
 
-use andrew::Endian;
 use rand::prelude::*;
 
 use yuxa::{
-    ElementState, Event, EventsLoop, KeyboardInput, VirtualKeyCode, WindowBuilder, WindowEvent,
-    YuxaWindow,
+    text, Canvas, ElementState, Event, EventsLoop, KeyboardInput, VirtualKeyCode, WindowBuilder,
+    WindowEvent, YuxaWindow,
 };
 
 const TILES: usize = 15;
@@ -28,25 +26,10 @@ struct Snake {
     grow: bool,
     last_move: Instant,
     game_over: bool,
-    font_data: Vec<u8>,
 }
 
 impl Snake {
     pub fn new() -> Snake {
-        let mut font_data = Vec::new();
-        std::fs::File::open(
-            andrew::text::fontconfig::FontConfig::new()
-                .unwrap()
-                .get_regular_family_fonts("sans")
-                .unwrap()
-                .iter()
-                .filter(|p| p.extension().unwrap() == "ttf")
-                .nth(0)
-                .unwrap(),
-        )
-        .unwrap()
-        .read_to_end(&mut font_data)
-        .unwrap();
         let middle = (TILES / 2 - 1, TILES / 2 - 1);
         let mut snake = Snake {
             pos: vec![middle, (middle.0 - 1, middle.1), (middle.0 - 2, middle.1)],
@@ -56,7 +39,6 @@ impl Snake {
             grow: false,
             last_move: Instant::now(),
             game_over: false,
-            font_data,
         };
         snake.spawn_apple();
         snake
@@ -116,82 +98,58 @@ impl Snake {
     }
 
     pub fn draw(&mut self, window: &mut YuxaWindow) {
-        let dimensions: (u32, u32) = window.get_inner_size().unwrap().to_physical(1.).into();
-        let dimensions = (dimensions.0 as usize, dimensions.1 as usize);
-        let mut buffer = vec![0; dimensions.0 * dimensions.1 * 4];
-        let mut canvas = andrew::Canvas::new(
-            &mut buffer,
-            dimensions.0,
-            dimensions.1,
-            dimensions.0 * 4,
-            Endian::Big,
-        );
+        let dimensions = window.physical_size().unwrap();
+        let mut pixels = vec![0; dimensions.0 * dimensions.1];
+        let mut canvas = Canvas::new(&mut pixels, dimensions.0, dimensions.1).unwrap();
         let scale = (
             dimensions.0 as f32 / TILES as f32,
             dimensions.1 as f32 / TILES as f32,
         );
+        let tile = |pos: (usize, usize)| {
+            (
+                (pos.0 as f32 * scale.0) as i32,
+                (pos.1 as f32 * scale.1) as i32,
+            )
+        };
 
         // Draw background
-        let background = andrew::shapes::rectangle::Rectangle::new(
-            (0, 0),
-            dimensions,
-            None,
-            Some([255, 0, 0, 0]),
-        );
-        canvas.draw(&background);
+        canvas.clear(0xFF00_0000);
 
         // Draw apple
-        let apple = andrew::shapes::rectangle::Rectangle::new(
-            (
-                (self.apple_pos.0 as f32 * scale.0) as usize,
-                (self.apple_pos.1 as f32 * scale.1) as usize,
-            ),
-            (scale.0 as usize, scale.1 as usize),
-            None,
-            Some([255, 255, 0, 0]),
-        );
-        canvas.draw(&apple);
+        let (x, y) = tile(self.apple_pos);
+        canvas.fill_rect(x, y, scale.0 as i32, scale.1 as i32, 0xFFFF_0000);
 
         // Draw snake
-        for pos in &self.pos {
-            let body = andrew::shapes::rectangle::Rectangle::new(
-                (
-                    (pos.0 as f32 * scale.0) as usize,
-                    (pos.1 as f32 * scale.1) as usize,
-                ),
-                (scale.0 as usize, scale.1 as usize),
-                None,
-                Some([255, 0, 255, 0]),
-            );
-            canvas.draw(&body);
+        for &pos in &self.pos {
+            let (x, y) = tile(pos);
+            canvas.fill_rect(x, y, scale.0 as i32, scale.1 as i32, 0xFF00_FF00);
         }
 
         // Draw score
-        let text = andrew::text::Text::new(
-            (dimensions.0 / 20, dimensions.1 / 20),
-            [255, 0, 0, 255],
-            &self.font_data,
-            dimensions.0 as f32 / 30.,
-            1.0,
-            self.score.to_string(),
+        let scale = (dimensions.0 / 200).max(1) as u32;
+        canvas.draw_text(
+            ((dimensions.0 / 20) as i32, (dimensions.1 / 20) as i32),
+            &self.score.to_string(),
+            scale,
+            0xFF00_00FF,
         );
-        canvas.draw(&text);
 
         // Draw game over
         if self.game_over {
-            let mut text = andrew::text::Text::new(
-                (400, 50),
-                [255, 0, 0, 255],
-                &self.font_data,
-                dimensions.0 as f32 / 20.,
-                1.0,
-                "Game Over".to_string(),
+            let scale = scale * 2;
+            let width = text::measure("Game Over", scale).0;
+            canvas.draw_text(
+                (
+                    (dimensions.0 / 2 - width / 2) as i32,
+                    (dimensions.1 / 10) as i32,
+                ),
+                "Game Over",
+                scale,
+                0xFF00_00FF,
             );
-            text.pos = (dimensions.0 / 2 - text.get_width() / 2, dimensions.0 / 10);
-            canvas.draw(&text);
         }
 
-        window.draw_argb8888_bytes(&canvas.buffer);
+        window.draw_canvas(&canvas).unwrap();
     }
 
     pub fn update(&mut self, window: &mut YuxaWindow) {
@@ -241,25 +199,17 @@ fn main() {
                         ..
                     } => match virtual_code {
                         // Get user input
-                        VirtualKeyCode::W => {
-                            if snake.pos[1].1 != snake.pos[0].1 - 1 {
-                                snake.dir = Direction::Up
-                            }
+                        VirtualKeyCode::W if snake.pos[1].1 != snake.pos[0].1 - 1 => {
+                            snake.dir = Direction::Up
                         }
-                        VirtualKeyCode::S => {
-                            if snake.pos[1].1 != snake.pos[0].1 + 1 {
-                                snake.dir = Direction::Down
-                            }
+                        VirtualKeyCode::S if snake.pos[1].1 != snake.pos[0].1 + 1 => {
+                            snake.dir = Direction::Down
                         }
-                        VirtualKeyCode::A => {
-                            if snake.pos[1].0 != snake.pos[0].0 - 1 {
-                                snake.dir = Direction::Left
-                            }
+                        VirtualKeyCode::A if snake.pos[1].0 != snake.pos[0].0 - 1 => {
+                            snake.dir = Direction::Left
                         }
-                        VirtualKeyCode::D => {
-                            if snake.pos[1].0 != snake.pos[0].0 + 1 {
-                                snake.dir = Direction::Right
-                            }
+                        VirtualKeyCode::D if snake.pos[1].0 != snake.pos[0].0 + 1 => {
+                            snake.dir = Direction::Right
                         }
                         VirtualKeyCode::Space => snake.dir = Direction::Static,
                         _ => {}
@@ -1,5 +1,3 @@
-use yuxa;
-
 fn main() {
     let mut events_loop = winit::EventsLoop::new();
 
@@ -15,13 +13,12 @@ fn main() {
             event: winit::WindowEvent::Refresh,
             ..
         } => {
-            let dimensions: (u32, u32) = window.get_inner_size().unwrap().to_physical(1.).into();
+            let dimensions = window.physical_size().unwrap();
             let mut pixels = Vec::new();
 
             for y in 0..dimensions.1 {
                 for x in 0..dimensions.0 {
-                    let color = if ((x as f32 / 20.) as usize + (y as f32 / 20.) as usize) % 2 == 0
-                    {
+                    let color = if (x / 20 + y / 20) & 1 == 0 {
                         0xFF_1E_1E_1E
                     } else {
                         0xFF_3C_3C_3C
@@ -31,7 +28,7 @@ fn main() {
                 }
             }
 
-            window.draw_argb32(&pixels);
+            window.draw_argb32(&pixels).unwrap();
             winit::ControlFlow::Continue
         }
         _ => winit::ControlFlow::Continue,
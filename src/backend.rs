@@ -0,0 +1,397 @@
+use std::path::Path;
+
+use crate::blend::unpremultiply;
+use crate::convert;
+use crate::{DrawError, Image, ImageError, ImageFormat, Rect};
+
+/// Pixel layouts frames can be provided in
+///
+/// 32 and 16 bit formats are native endian integers with channels named from the most
+/// significant bits down, 24 and 8 bit formats are named in byte order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// `0xAARRGGBB`
+    Argb8888,
+    /// `0xXXRRGGBB`, the top byte is ignored and the pixel is opaque
+    Xrgb8888,
+    /// `0xRRGGBBAA`
+    Rgba8888,
+    /// `0xBBGGRRAA`
+    Bgra8888,
+    /// Bytes red, green, blue, the pixel is opaque
+    Rgb888,
+    /// 5 bits red, 6 bits green and 5 bits blue, the pixel is opaque
+    Rgb565,
+    /// A single byte of luminance, the pixel is opaque
+    Gray8,
+}
+
+impl PixelFormat {
+    /// Number of bytes a single pixel occupies
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Argb8888
+            | PixelFormat::Xrgb8888
+            | PixelFormat::Rgba8888
+            | PixelFormat::Bgra8888 => 4,
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Gray8 => 1,
+        }
+    }
+
+    /// Whether pixels of this format carry an alpha channel
+    pub fn has_alpha(self) -> bool {
+        matches!(
+            self,
+            PixelFormat::Argb8888 | PixelFormat::Rgba8888 | PixelFormat::Bgra8888
+        )
+    }
+}
+
+/// How the alpha channel of presented pixels is interpreted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// Alpha is ignored and every pixel is fully opaque
+    Opaque,
+    /// Color channels are independent of alpha and are premultiplied before presenting
+    Straight,
+    /// Color channels are already multiplied by alpha, as compositors expect
+    Premultiplied,
+}
+
+/// What happened to a frame handed to a backend
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStatus {
+    /// The display server has processed the frame
+    Presented,
+    /// The frame was sent but the display server has not processed it yet
+    Queued,
+    /// The frame was discarded, because every buffer was still in use by the display server
+    /// or because it has no pixels to show
+    Dropped,
+}
+
+/// How presenting a frame is paced against the display server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Every draw waits until the display server has processed the frame
+    Blocking,
+    /// Draws return immediately, `Backend::frame_ready` reports when the next frame should be
+    /// drawn
+    FrameCallback,
+}
+
+/// Describes how the pixels of a frame are laid out in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Width of the frame in pixels
+    pub width: usize,
+    /// Height of the frame in pixels
+    pub height: usize,
+    /// Number of bytes from the start of one row to the start of the next
+    pub stride: usize,
+    /// Format of every pixel
+    pub format: PixelFormat,
+}
+
+impl Layout {
+    /// Layout of a frame whose rows are tightly packed
+    ///
+    /// Rows too wide to address get a stride of `usize::MAX`, which no buffer can hold.
+    pub fn packed(width: usize, height: usize, format: PixelFormat) -> Layout {
+        Layout {
+            width,
+            height,
+            stride: width.saturating_mul(format.bytes_per_pixel()),
+            format,
+        }
+    }
+
+    /// Number of bytes in a row that hold pixel data, excluding any padding
+    ///
+    /// Saturates at `usize::MAX` for rows too wide to address.
+    pub fn row_bytes(&self) -> usize {
+        self.width.saturating_mul(self.format.bytes_per_pixel())
+    }
+
+    /// Smallest buffer length in bytes that holds a frame with this layout
+    ///
+    /// Padding after the last row is not required to be present. Saturates at `usize::MAX`
+    /// for frames too large to address, so no buffer passes `check` for them.
+    pub fn min_len(&self) -> usize {
+        if self.height == 0 {
+            0
+        } else {
+            self.stride
+                .checked_mul(self.height - 1)
+                .and_then(|rows| rows.checked_add(self.row_bytes()))
+                .unwrap_or(usize::MAX)
+        }
+    }
+
+    /// Check that the layout is consistent and that `buffer` is large enough for it
+    pub(crate) fn check(&self, buffer: &[u8]) -> Result<(), DrawError> {
+        if self.stride < self.row_bytes() {
+            return Err(DrawError::InvalidStride {
+                stride: self.stride,
+                minimum: self.row_bytes(),
+            });
+        }
+        if buffer.len() < self.min_len() {
+            return Err(DrawError::BufferSizeMismatch {
+                expected: self.min_len(),
+                actual: buffer.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Convert the pixels in `buffer` into tightly packed argb `u32`s
+    pub(crate) fn pack_argb32(&self, buffer: &[u8]) -> Vec<u32> {
+        let mut packed = vec![0; self.width * self.height];
+        for (y, row) in packed.chunks_exact_mut(self.width.max(1)).enumerate() {
+            let source = &buffer[y * self.stride..y * self.stride + self.row_bytes()];
+            convert::to_argb32(self.format, source, row);
+        }
+        packed
+    }
+}
+
+/// A presented frame as it was handed to the display server
+///
+/// Pixels of formats with an alpha channel are premultiplied, as compositors expect them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PresentedFrame {
+    pub layout: Layout,
+    pub data: Vec<u8>,
+}
+
+impl PresentedFrame {
+    /// Copy the frame described by `layout` from `data`, which must be large enough for it
+    pub(crate) fn new(layout: Layout, data: &[u8]) -> PresentedFrame {
+        PresentedFrame {
+            layout,
+            data: data[..layout.min_len()].to_vec(),
+        }
+    }
+
+    /// A tightly packed argb frame of `dimensions`
+    pub(crate) fn from_argb32(dimensions: (usize, usize), pixels: &[u32]) -> PresentedFrame {
+        PresentedFrame {
+            layout: Layout::packed(dimensions.0, dimensions.1, PixelFormat::Argb8888),
+            data: pixels
+                .iter()
+                .flat_map(|pixel| pixel.to_ne_bytes())
+                .collect(),
+        }
+    }
+
+    /// The pixels of the frame as native endian argb `u32`, row by row
+    ///
+    /// Pixels are premultiplied if the format has alpha, and the alpha channel of formats
+    /// without it is undefined.
+    pub fn to_argb32(&self) -> Vec<u32> {
+        self.layout.pack_argb32(&self.data)
+    }
+
+    /// The frame as an image with straight alpha
+    pub fn to_image(&self) -> Image {
+        let mut pixels = self.layout.pack_argb32(&self.data);
+        for pixel in &mut pixels {
+            *pixel = if self.layout.format.has_alpha() {
+                unpremultiply(*pixel)
+            } else {
+                *pixel | 0xFF00_0000
+            };
+        }
+        Image::new(self.layout.width, self.layout.height, pixels)
+            .expect("Packed frames hold every pixel")
+    }
+
+    /// Encode the frame as an image file at `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), ImageError> {
+        self.to_image().save(path, format)
+    }
+}
+
+/// A presentation target that frames can be drawn to
+///
+/// Yuxa ships a backend for each supported platform, custom targets can be plugged into
+/// a `YuxaWindow` with `YuxaWindow::with_backend`. Implementations must return
+/// `DrawError::BufferSizeMismatch` rather than present a buffer that does not hold exactly
+/// one frame of the requested dimensions.
+pub trait Backend {
+    /// Present a frame of `dimensions` pixels where each pixel is a native endian argb `u32`
+    fn draw_argb32(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+    ) -> Result<FrameStatus, DrawError>;
+
+    /// Present a frame of `dimensions` pixels where each pixel is `[a, r, g, b]`
+    fn draw_argb8888(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[[u8; 4]],
+    ) -> Result<FrameStatus, DrawError> {
+        self.draw_argb8888_bytes(dimensions, convert::argb_arrays_as_bytes(buffer))
+    }
+
+    /// Present a frame of `dimensions` pixels where every 4 bytes are `a, r, g, b`
+    fn draw_argb8888_bytes(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u8],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 4), buffer.len())?;
+        let mut pixels = vec![0; dimensions.0 * dimensions.1];
+        convert::argb_bytes_to_argb32(buffer, &mut pixels);
+        self.draw_argb32(dimensions, &pixels)
+    }
+
+    /// Present a frame whose size, row stride and format are described by `layout`
+    ///
+    /// The frame does not need to match the size of the presentation target. Formats the
+    /// backend cannot present natively are converted, the default converts every format.
+    fn draw_with_layout(
+        &mut self,
+        buffer: &[u8],
+        layout: Layout,
+    ) -> Result<FrameStatus, DrawError> {
+        layout.check(buffer)?;
+        let packed = layout.pack_argb32(buffer);
+        self.draw_argb32((layout.width, layout.height), &packed)
+    }
+
+    /// Present an argb32 frame of which only the regions in `damage` changed since the last
+    /// presented frame
+    ///
+    /// Backends may copy and report only the damaged regions, the default presents the whole
+    /// frame.
+    fn draw_damaged(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        let _ = damage;
+        self.draw_argb32(dimensions, buffer)
+    }
+
+    /// Prepare memory for a frame of `dimensions` that can be rendered into directly
+    ///
+    /// Returns false when the backend has no memory it can lend out, the default.
+    fn map_frame(&mut self, dimensions: (usize, usize)) -> Result<bool, DrawError> {
+        let _ = dimensions;
+        Ok(false)
+    }
+
+    /// Pixels of the frame prepared by the last successful `map_frame`
+    fn mapped_pixels(&mut self) -> Option<&mut [u32]> {
+        None
+    }
+
+    /// Present the frame prepared by the last successful `map_frame`
+    fn present_mapped(&mut self) -> Result<FrameStatus, DrawError> {
+        Ok(FrameStatus::Dropped)
+    }
+
+    /// Change how presenting frames is paced, backends without frame pacing ignore this
+    fn set_present_mode(&mut self, mode: PresentMode) {
+        let _ = mode;
+    }
+
+    /// Change how the alpha channel of presented pixels is interpreted
+    ///
+    /// Backends present pixels unchanged, as `AlphaMode::Premultiplied`, until this is called.
+    /// Backends whose target has no alpha channel ignore this.
+    fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        let _ = mode;
+    }
+
+    /// Tell the backend how many physical pixels make up a logical pixel of the window
+    ///
+    /// Backends whose display server needs to know the scale of presented frames, like
+    /// Wayland, use this, others ignore it.
+    fn set_scale_factor(&mut self, scale: f64) {
+        let _ = scale;
+    }
+
+    /// Have the display server scale every presented frame to `size` logical pixels, so
+    /// frames of any size fill the window
+    ///
+    /// `None` shows frames at their own size again. Returns false when the backend cannot
+    /// scale frames, the default.
+    fn set_destination_size(&mut self, size: Option<(usize, usize)>) -> bool {
+        size.is_none()
+    }
+
+    /// Change how many buffers frames are cycled through, for example 3 for triple buffering
+    ///
+    /// Backends that do not keep their own buffers ignore this.
+    fn set_buffer_count(&mut self, count: usize) -> Result<(), DrawError> {
+        let _ = count;
+        Ok(())
+    }
+
+    /// Whether a draw waits for the display server to release a buffer instead of dropping
+    /// the frame when every buffer is in use
+    fn set_wait_for_buffer(&mut self, wait: bool) {
+        let _ = wait;
+    }
+
+    /// Whether the display server is ready for another frame
+    ///
+    /// Backends that do not pace frames are always ready.
+    fn frame_ready(&mut self) -> Result<bool, DrawError> {
+        Ok(true)
+    }
+
+    /// Keep a copy of every presented frame as it was handed to the display server, for
+    /// `last_frame`
+    ///
+    /// Backends that cannot retain frames ignore this.
+    fn set_retain_frame(&mut self, retain: bool) {
+        let _ = retain;
+    }
+
+    /// The last frame presented since retaining frames was turned on
+    fn last_frame(&self) -> Option<&PresentedFrame> {
+        None
+    }
+
+    /// Read the current contents of the presentation target back from the display server
+    ///
+    /// Unlike `last_frame` this returns what is actually shown, which is what integration
+    /// tests want to compare against. Returns `None` when the backend cannot read back or the
+    /// target is not visible, the default.
+    fn read_back(&mut self) -> Option<PresentedFrame> {
+        None
+    }
+
+    /// Current size of the presentation target in pixels, if known
+    fn size(&self) -> Option<(usize, usize)>;
+
+    /// Pixel formats the backend can present without conversion
+    fn formats(&self) -> &[PixelFormat];
+}
+
+/// Number of elements in a tightly packed frame of `dimensions` with `per_pixel` elements
+/// per pixel, saturating at `usize::MAX` for frames too large to address
+pub(crate) fn frame_len(dimensions: (usize, usize), per_pixel: usize) -> usize {
+    dimensions
+        .0
+        .checked_mul(dimensions.1)
+        .and_then(|pixels| pixels.checked_mul(per_pixel))
+        .unwrap_or(usize::MAX)
+}
+
+/// Check that a buffer of `actual` elements holds the `expected` number of elements
+pub(crate) fn check_len(expected: usize, actual: usize) -> Result<(), DrawError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(DrawError::BufferSizeMismatch { expected, actual })
+    }
+}
@@ -0,0 +1,471 @@
+use crate::convert::premultiply;
+use crate::AlphaMode;
+
+/// Porter-Duff operator combining what is drawn, the source, with what is already there,
+/// the destination
+///
+/// Only pixels covered by the shape being drawn are changed, so operators like `SourceIn`
+/// leave the rest of the canvas as it was.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompositeOp {
+    /// Both source and destination are removed
+    Clear,
+    /// The source replaces the destination
+    Source,
+    /// The destination is kept, drawing has no effect
+    Destination,
+    /// The source is drawn over the destination
+    SourceOver,
+    /// The source is drawn behind the destination
+    DestinationOver,
+    /// The source is kept where the destination is opaque
+    SourceIn,
+    /// The destination is kept where the source is opaque
+    DestinationIn,
+    /// The source is kept where the destination is transparent
+    SourceOut,
+    /// The destination is kept where the source is transparent, erasing it with the source
+    DestinationOut,
+    /// The source is drawn over the destination, but only where the destination is opaque
+    SourceAtop,
+    /// The destination is drawn over the source, but only where the source is opaque
+    DestinationAtop,
+    /// Source and destination are kept where the other is transparent
+    Xor,
+}
+
+/// Function mixing the colors of the source and destination where both are present
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    /// The source color is used as is
+    Normal,
+    /// The colors are multiplied, darkening the destination
+    Multiply,
+    /// The inverted colors are multiplied, lightening the destination
+    Screen,
+    /// `Multiply` for dark destination colors and `Screen` for light ones
+    Overlay,
+    /// The darker of both colors
+    Darken,
+    /// The lighter of both colors
+    Lighten,
+    /// The colors are added, saturating at full intensity
+    Additive,
+}
+
+impl BlendMode {
+    /// Blend straight `destination` and `source` channels between 0 and 1
+    fn apply(self, destination: f32, source: f32) -> f32 {
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => destination * source,
+            BlendMode::Screen => destination + source - destination * source,
+            BlendMode::Overlay => {
+                if destination <= 0.5 {
+                    2. * destination * source
+                } else {
+                    let doubled = 2. * destination - 1.;
+                    doubled + source - doubled * source
+                }
+            }
+            BlendMode::Darken => destination.min(source),
+            BlendMode::Lighten => destination.max(source),
+            BlendMode::Additive => (destination + source).min(1.),
+        }
+    }
+}
+
+/// Combines colors with the pixels of a canvas stored in `alpha_mode`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Compositor {
+    pub op: CompositeOp,
+    pub mode: BlendMode,
+    pub alpha_mode: AlphaMode,
+}
+
+impl Compositor {
+    /// Whether drawing a premultiplied `source` with full coverage simply replaces pixels
+    pub fn replaces(&self, source: u32) -> bool {
+        self.mode == BlendMode::Normal
+            && match self.op {
+                CompositeOp::Source => true,
+                CompositeOp::SourceOver => source >> 24 == 0xFF,
+                _ => false,
+            }
+    }
+
+    /// `pixel` converted from premultiplied to the alpha mode of the canvas
+    pub fn store(&self, pixel: u32) -> u32 {
+        match self.alpha_mode {
+            // Colors stay premultiplied, which shows them as if drawn over black
+            AlphaMode::Opaque => pixel | 0xFF00_0000,
+            AlphaMode::Straight => unpremultiply(pixel),
+            AlphaMode::Premultiplied => pixel,
+        }
+    }
+
+    /// Composite the premultiplied `source`, covering `coverage` out of 255 of the pixel,
+    /// with the `destination` pixel of the canvas
+    pub fn apply(&self, source: u32, destination: u32, coverage: u32) -> u32 {
+        if coverage == 0 {
+            return destination;
+        }
+        let destination_premultiplied = load(destination, self.alpha_mode);
+        if self.op == CompositeOp::SourceOver && self.mode == BlendMode::Normal {
+            return self.store(over(source, destination_premultiplied, coverage));
+        }
+        let result = self.composite(source, destination_premultiplied);
+        let result = if coverage == 255 {
+            result
+        } else {
+            // Pixels partially covered keep the rest of the destination
+            scale(result, coverage) + scale(destination_premultiplied, 255 - coverage)
+        };
+        self.store(result)
+    }
+
+    /// The separable blending and Porter-Duff composition of the W3C compositing
+    /// specification, on premultiplied pixels
+    fn composite(&self, source: u32, destination: u32) -> u32 {
+        let (source_alpha, destination_alpha) = (channel(source, 24), channel(destination, 24));
+        let (source_factor, destination_factor) = match self.op {
+            CompositeOp::Clear => (0., 0.),
+            CompositeOp::Source => (1., 0.),
+            CompositeOp::Destination => (0., 1.),
+            CompositeOp::SourceOver => (1., 1. - source_alpha),
+            CompositeOp::DestinationOver => (1. - destination_alpha, 1.),
+            CompositeOp::SourceIn => (destination_alpha, 0.),
+            CompositeOp::DestinationIn => (0., source_alpha),
+            CompositeOp::SourceOut => (1. - destination_alpha, 0.),
+            CompositeOp::DestinationOut => (0., 1. - source_alpha),
+            CompositeOp::SourceAtop => (destination_alpha, 1. - source_alpha),
+            CompositeOp::DestinationAtop => (1. - destination_alpha, source_alpha),
+            CompositeOp::Xor => (1. - destination_alpha, 1. - source_alpha),
+        };
+        let alpha = source_alpha * source_factor + destination_alpha * destination_factor;
+        let mut pixel = to_byte(alpha) << 24;
+        for &shift in &[16, 8, 0] {
+            let (source, destination) = (channel(source, shift), channel(destination, shift));
+            // Where both are present the source color is replaced by the blended color
+            let blended = if self.mode == BlendMode::Normal || destination_alpha == 0. {
+                source
+            } else {
+                let straight_source = if source_alpha == 0. {
+                    0.
+                } else {
+                    source / source_alpha
+                };
+                let straight_destination = destination / destination_alpha;
+                (1. - destination_alpha) * source
+                    + source_alpha
+                        * destination_alpha
+                        * self.mode.apply(straight_destination, straight_source)
+            };
+            let color = blended * source_factor + destination * destination_factor;
+            // Rounding may leave a channel a little above the alpha it is premultiplied with
+            pixel |= to_byte(color.min(alpha)) << shift;
+        }
+        pixel
+    }
+}
+
+/// `pixel` stored in `alpha_mode` converted to premultiplied
+pub(crate) fn load(pixel: u32, alpha_mode: AlphaMode) -> u32 {
+    match alpha_mode {
+        AlphaMode::Opaque => pixel | 0xFF00_0000,
+        AlphaMode::Straight => premultiply(pixel),
+        AlphaMode::Premultiplied => pixel,
+    }
+}
+
+/// Composite premultiplied `source` scaled by `coverage` out of 255 over `destination`
+fn over(source: u32, destination: u32, coverage: u32) -> u32 {
+    let source = if coverage == 255 {
+        source
+    } else {
+        scale(source, coverage)
+    };
+    let alpha = source >> 24;
+    if alpha == 0xFF {
+        source
+    } else {
+        // Adding per channel cannot overflow, the source channels never exceed its alpha
+        source + scale(destination, 255 - alpha)
+    }
+}
+
+/// Multiply every channel of `pixel` by `factor` out of 255, with rounding
+fn scale(pixel: u32, factor: u32) -> u32 {
+    let scale = |channel: u32| {
+        let product = channel * factor + 0x80;
+        (product + (product >> 8)) >> 8
+    };
+    scale(pixel >> 24) << 24
+        | scale((pixel >> 16) & 0xFF) << 16
+        | scale((pixel >> 8) & 0xFF) << 8
+        | scale(pixel & 0xFF)
+}
+
+/// Divide the color channels of a premultiplied pixel by its alpha
+pub(crate) fn unpremultiply(pixel: u32) -> u32 {
+    let alpha = pixel >> 24;
+    match alpha {
+        0xFF => pixel,
+        0 => 0,
+        _ => {
+            let scale = |channel: u32| ((channel * 255 + alpha / 2) / alpha).min(255);
+            alpha << 24
+                | scale((pixel >> 16) & 0xFF) << 16
+                | scale((pixel >> 8) & 0xFF) << 8
+                | scale(pixel & 0xFF)
+        }
+    }
+}
+
+fn channel(pixel: u32, shift: u32) -> f32 {
+    ((pixel >> shift) & 0xFF) as f32 / 255.
+}
+
+fn to_byte(value: f32) -> u32 {
+    (value.clamp(0., 1.) * 255. + 0.5) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPS: [CompositeOp; 12] = [
+        CompositeOp::Clear,
+        CompositeOp::Source,
+        CompositeOp::Destination,
+        CompositeOp::SourceOver,
+        CompositeOp::DestinationOver,
+        CompositeOp::SourceIn,
+        CompositeOp::DestinationIn,
+        CompositeOp::SourceOut,
+        CompositeOp::DestinationOut,
+        CompositeOp::SourceAtop,
+        CompositeOp::DestinationAtop,
+        CompositeOp::Xor,
+    ];
+
+    const MODES: [BlendMode; 7] = [
+        BlendMode::Normal,
+        BlendMode::Multiply,
+        BlendMode::Screen,
+        BlendMode::Overlay,
+        BlendMode::Darken,
+        BlendMode::Lighten,
+        BlendMode::Additive,
+    ];
+
+    fn compositor(op: CompositeOp, mode: BlendMode, alpha_mode: AlphaMode) -> Compositor {
+        Compositor {
+            op,
+            mode,
+            alpha_mode,
+        }
+    }
+
+    /// Check the result of every operator for one premultiplied pixel pair, in the order of
+    /// `OPS`
+    fn assert_ops(source: u32, destination: u32, expected: [u32; 12]) {
+        for (&op, &expected) in OPS.iter().zip(&expected) {
+            let compositor = compositor(op, BlendMode::Normal, AlphaMode::Premultiplied);
+            let result = compositor.apply(source, destination, 255);
+            assert_eq!(
+                result, expected,
+                "{:?} of {:08X} and {:08X} is {:08X}, not {:08X}",
+                op, source, destination, result, expected
+            );
+        }
+    }
+
+    #[test]
+    fn composites_opaque_pixels() {
+        let (red, blue) = (0xFFFF_0000, 0xFF00_00FF);
+        assert_ops(
+            red,
+            blue,
+            [0, red, blue, red, blue, red, blue, 0, 0, red, blue, 0],
+        );
+    }
+
+    #[test]
+    fn composites_transparent_pixels() {
+        let blue = 0xFF00_00FF;
+        assert_ops(
+            0,
+            blue,
+            [0, 0, blue, blue, blue, 0, 0, 0, blue, blue, 0, blue],
+        );
+        assert_ops(
+            blue,
+            0,
+            [0, blue, 0, blue, blue, 0, 0, blue, 0, 0, blue, blue],
+        );
+        assert_ops(0, 0, [0; 12]);
+    }
+
+    #[test]
+    fn composites_translucent_pixels() {
+        // Half transparent green and blue
+        assert_ops(
+            0x8000_8000,
+            0x8000_0080,
+            [
+                0,
+                0x8000_8000,
+                0x8000_0080,
+                0xC000_8040,
+                0xC000_4080,
+                0x4000_4000,
+                0x4000_0040,
+                0x4000_4000,
+                0x4000_0040,
+                0x8000_4040,
+                0x8000_4040,
+                0x7F00_4040,
+            ],
+        );
+    }
+
+    #[test]
+    fn blends_colors() {
+        let (source, destination) = (0xFF80_C040, 0xFF40_C080);
+        let expected = [
+            0xFF80_C040,
+            0xFF20_9120,
+            0xFFA0_EFA0,
+            0xFF40_E041,
+            0xFF40_C040,
+            0xFF80_C080,
+            0xFFC0_FFC0,
+        ];
+        for (&mode, &expected) in MODES.iter().zip(&expected) {
+            let compositor = compositor(CompositeOp::SourceOver, mode, AlphaMode::Premultiplied);
+            let result = compositor.apply(source, destination, 255);
+            assert_eq!(result, expected, "{:?} is {:08X}", mode, result);
+        }
+    }
+
+    #[test]
+    fn blends_only_where_both_are_present() {
+        for &mode in &MODES {
+            let compositor = compositor(CompositeOp::SourceOver, mode, AlphaMode::Premultiplied);
+            // Over nothing the source is drawn as is, nothing drawn keeps the destination
+            assert_eq!(
+                compositor.apply(0xFF80_C040, 0, 255),
+                0xFF80_C040,
+                "{:?}",
+                mode
+            );
+            assert_eq!(
+                compositor.apply(0x8040_6020, 0, 255),
+                0x8040_6020,
+                "{:?}",
+                mode
+            );
+            assert_eq!(
+                compositor.apply(0, 0xFF40_C080, 255),
+                0xFF40_C080,
+                "{:?}",
+                mode
+            );
+            // Blending with translucent pixels keeps the alpha of source over
+            let result = compositor.apply(0x8040_6020, 0x8020_6040, 255);
+            assert_eq!(result >> 24, 0xC0, "{:?}", mode);
+            for &shift in &[16, 8, 0] {
+                assert!(
+                    (result >> shift) & 0xFF <= 0xC0,
+                    "{:?} is {:08X}",
+                    mode,
+                    result
+                );
+            }
+        }
+        // Half of the result is blended, the rest is the source or destination alone
+        let compositor = compositor(
+            CompositeOp::SourceOver,
+            BlendMode::Multiply,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(compositor.apply(0x8080_8080, 0x8000_0000, 255), 0xC040_4040);
+        assert_eq!(compositor.apply(0x8000_0000, 0x8080_8080, 255), 0xC040_4040);
+    }
+
+    #[test]
+    fn keeps_uncovered_parts_of_the_destination() {
+        let (red, blue) = (0xFFFF_0000, 0xFF00_00FF);
+        let source = compositor(
+            CompositeOp::Source,
+            BlendMode::Normal,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(source.apply(red, blue, 0), blue);
+        assert_eq!(source.apply(red, blue, 128), 0xFF80_007F);
+        let over = compositor(
+            CompositeOp::SourceOver,
+            BlendMode::Normal,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(over.apply(red, blue, 128), 0xFF80_007F);
+        assert_eq!(over.apply(red, 0, 128), 0x8080_0000);
+        let clear = compositor(
+            CompositeOp::Clear,
+            BlendMode::Normal,
+            AlphaMode::Premultiplied,
+        );
+        assert_eq!(clear.apply(red, blue, 64), 0xBF00_00BF);
+        // Uncovered pixels are left alone, even when not valid in the alpha mode
+        let straight = compositor(CompositeOp::Clear, BlendMode::Normal, AlphaMode::Straight);
+        assert_eq!(straight.apply(red, 0x00FF_FFFF, 0), 0x00FF_FFFF);
+    }
+
+    #[test]
+    fn converts_between_alpha_modes() {
+        assert_eq!(load(0x80FF_8000, AlphaMode::Straight), 0x8080_4000);
+        assert_eq!(load(0x00FF_FFFF, AlphaMode::Straight), 0);
+        assert_eq!(load(0xFF12_3456, AlphaMode::Straight), 0xFF12_3456);
+        assert_eq!(load(0x0012_3456, AlphaMode::Opaque), 0xFF12_3456);
+        assert_eq!(load(0x8012_3456, AlphaMode::Premultiplied), 0x8012_3456);
+
+        let store = |alpha_mode, pixel| {
+            compositor(CompositeOp::SourceOver, BlendMode::Normal, alpha_mode).store(pixel)
+        };
+        assert_eq!(store(AlphaMode::Straight, 0x8080_4000), 0x80FF_8000);
+        assert_eq!(store(AlphaMode::Straight, 0), 0);
+        assert_eq!(store(AlphaMode::Opaque, 0x8080_4000), 0xFF80_4000);
+        assert_eq!(store(AlphaMode::Premultiplied, 0x8080_4000), 0x8080_4000);
+        // Channels above their alpha are clamped
+        assert_eq!(unpremultiply(0x10FF_2010), 0x10FF_FFFF);
+
+        // Every premultiplied pixel survives the trip through straight alpha
+        for alpha in 0..=255 {
+            for channel in 0..=alpha {
+                let pixel = alpha << 24 | channel << 16 | (alpha - channel) << 8 | (channel / 2);
+                assert_eq!(load(unpremultiply(pixel), AlphaMode::Straight), pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn draws_on_straight_canvases() {
+        let compositor = compositor(
+            CompositeOp::SourceOver,
+            BlendMode::Normal,
+            AlphaMode::Straight,
+        );
+        // Half transparent red over nothing and over half transparent blue
+        assert_eq!(compositor.apply(0x8080_0000, 0, 255), 0x80FF_0000);
+        assert_eq!(compositor.apply(0x8080_0000, 0x8000_00FF, 255), 0xC0AA_0055);
+        assert_eq!(compositor.apply(0x8080_0000, 0xFF00_00FF, 255), 0xFF80_007F);
+        let multiply = Compositor {
+            mode: BlendMode::Multiply,
+            ..compositor
+        };
+        // Gray multiplied with white is gray, mixed half and half with gray drawn over nothing
+        assert_eq!(multiply.apply(0xFF80_8080, 0x80FF_FFFF, 255), 0xFF80_8080);
+        assert_eq!(multiply.apply(0xFF80_8080, 0x8080_8080, 255), 0xFF60_6060);
+    }
+}
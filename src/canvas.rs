@@ -0,0 +1,936 @@
+use std::borrow::Cow;
+
+use crate::blend::{load, Compositor};
+use crate::convert::premultiply;
+use crate::path::{rasterize, stroke_polygons};
+use crate::{AlphaMode, BlendMode, CompositeOp, DrawError, FillRule, Path, Stroke};
+
+/// Draws shapes into a frame of argb `u32` pixels
+///
+/// Coordinates may lie outside of the canvas, everything drawn is clipped to its bounds.
+/// Colors are given with straight alpha and composited with the canvas according to its
+/// `CompositeOp` and `BlendMode`, by default drawing them over what is already there.
+/// Paths are anti-aliased, all other shapes are not.
+pub struct Canvas<'a> {
+    pixels: &'a mut [u32],
+    width: usize,
+    height: usize,
+    stride: usize,
+    compositor: Compositor,
+}
+
+impl<'a> Canvas<'a> {
+    /// A canvas over `pixels` holding tightly packed rows of `width` pixels
+    pub fn new(
+        pixels: &'a mut [u32],
+        width: usize,
+        height: usize,
+    ) -> Result<Canvas<'a>, DrawError> {
+        Canvas::with_stride(pixels, width, height, width)
+    }
+
+    /// A canvas over `pixels` whose rows start `stride` pixels apart
+    pub fn with_stride(
+        pixels: &'a mut [u32],
+        width: usize,
+        height: usize,
+        stride: usize,
+    ) -> Result<Canvas<'a>, DrawError> {
+        if stride < width {
+            return Err(DrawError::InvalidStride {
+                stride,
+                minimum: width,
+            });
+        }
+        // Padding after the last row is not required to be present, sizes too large to
+        // address expect more pixels than any slice holds
+        let expected = if height == 0 {
+            0
+        } else {
+            stride
+                .checked_mul(height - 1)
+                .and_then(|rows| rows.checked_add(width))
+                .unwrap_or(usize::MAX)
+        };
+        if pixels.len() < expected {
+            return Err(DrawError::BufferSizeMismatch {
+                expected,
+                actual: pixels.len(),
+            });
+        }
+        Ok(Canvas {
+            pixels,
+            width,
+            height,
+            stride,
+            compositor: Compositor {
+                op: CompositeOp::SourceOver,
+                mode: BlendMode::Normal,
+                alpha_mode: AlphaMode::Premultiplied,
+            },
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of pixels from the start of one row to the start of the next
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// The pixels of the canvas, including any padding between rows
+    pub fn pixels(&self) -> &[u32] {
+        self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        self.pixels
+    }
+
+    /// Whether the rows of the canvas are tightly packed
+    pub fn is_packed(&self) -> bool {
+        self.stride == self.width
+    }
+
+    pub fn composite_op(&self) -> CompositeOp {
+        self.compositor.op
+    }
+
+    /// Set how the shapes drawn next are composited with the canvas
+    pub fn set_composite_op(&mut self, op: CompositeOp) {
+        self.compositor.op = op;
+    }
+
+    pub fn blend_mode(&self) -> BlendMode {
+        self.compositor.mode
+    }
+
+    /// Set how the colors of the shapes drawn next are mixed with the canvas
+    pub fn set_blend_mode(&mut self, mode: BlendMode) {
+        self.compositor.mode = mode;
+    }
+
+    pub fn alpha_mode(&self) -> AlphaMode {
+        self.compositor.alpha_mode
+    }
+
+    /// Set how alpha is stored in the pixels of the canvas, `AlphaMode::Premultiplied` by
+    /// default
+    ///
+    /// Match the alpha mode of the window the canvas is presented to. In
+    /// `AlphaMode::Opaque` pixels are treated as opaque and colors drawn are shown as if
+    /// drawn over black where they leave the canvas translucent. Pixels already on the
+    /// canvas are not converted.
+    pub fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        self.compositor.alpha_mode = mode;
+    }
+
+    /// The pixels of the canvas with the padding between rows removed
+    pub(crate) fn packed_pixels(&self) -> Cow<'_, [u32]> {
+        if self.is_packed() {
+            Cow::Borrowed(&self.pixels[..self.width * self.height])
+        } else {
+            let mut packed = Vec::with_capacity(self.width * self.height);
+            for y in 0..self.height {
+                packed.extend_from_slice(&self.pixels[y * self.stride..][..self.width]);
+            }
+            Cow::Owned(packed)
+        }
+    }
+
+    /// Color of the pixel at `x`, `y`, if it lies within the canvas
+    pub fn pixel(&self, x: i32, y: i32) -> Option<u32> {
+        self.index(x, y).map(|index| self.pixels[index])
+    }
+
+    /// Set the pixel at `x`, `y` to `color` as is, without compositing
+    ///
+    /// Nothing happens if the pixel lies outside of the canvas.
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = color;
+        }
+    }
+
+    /// Replace every pixel of the canvas with `color`, without compositing
+    pub fn clear(&mut self, color: u32) {
+        let color = self.compositor.store(premultiply(color));
+        for y in 0..self.height {
+            for pixel in &mut self.pixels[y * self.stride..][..self.width] {
+                *pixel = color;
+            }
+        }
+    }
+
+    /// Fill the rectangle with its top left corner at `x`, `y`
+    pub fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        let (left, right) = self.clip_x(x, x.saturating_add(width));
+        let (top, bottom) = self.clip_y(y, y.saturating_add(height));
+        for y in top..bottom {
+            self.span(y, left, right, color);
+        }
+    }
+
+    /// Draw the one pixel wide outline of a rectangle with its top left corner at `x`, `y`
+    pub fn stroke_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: u32) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        let (right, bottom) = (x.saturating_add(width - 1), y.saturating_add(height - 1));
+        self.fill_rect(x, y, width, 1, color);
+        // Rects one pixel wide or high have no second side to draw
+        if height > 1 {
+            self.fill_rect(x, bottom, width, 1, color);
+        }
+        self.fill_rect(x, y.saturating_add(1), 1, height - 2, color);
+        if width > 1 {
+            self.fill_rect(right, y.saturating_add(1), 1, height - 2, color);
+        }
+    }
+
+    /// Draw a one pixel wide line from `start` to `end`, both included
+    pub fn line(&mut self, start: (i32, i32), end: (i32, i32), color: u32) {
+        let source = premultiply(color);
+        let size = (self.width, self.height);
+        line_pixels(start, end, size, |x, y| self.plot(x, y, source));
+    }
+
+    /// Draw the one pixel wide outline of a circle
+    pub fn circle(&mut self, center: (i32, i32), radius: i32, color: u32) {
+        self.ellipse(center, (radius, radius), color);
+    }
+
+    pub fn fill_circle(&mut self, center: (i32, i32), radius: i32, color: u32) {
+        self.fill_ellipse(center, (radius, radius), color);
+    }
+
+    /// Draw the one pixel wide outline of an axis aligned ellipse with horizontal and
+    /// vertical radii `radii`
+    pub fn ellipse(&mut self, center: (i32, i32), radii: (i32, i32), color: u32) {
+        let (nearest, farthest) = match self.ellipse_rows(center, radii) {
+            Some(rows) => rows,
+            None => return,
+        };
+        let (center_x, center_y) = (i64::from(center.0), i64::from(center.1));
+        for dy in nearest..=farthest {
+            let (start, end) = match ellipse_row(radii, dy) {
+                Some(run) => run,
+                None => continue,
+            };
+            // Pixels on the axes belong to two quadrants but are only drawn once
+            let (left, right) = self.clip_run(center_x + start, center_x + end);
+            let (mirrored_left, mirrored_right) =
+                self.clip_run(center_x - end, center_x - start.max(1));
+            for &y in &[center_y + dy, center_y - dy] {
+                if y >= 0 && y < self.height as i64 {
+                    self.span(y as usize, left, right, color);
+                    self.span(y as usize, mirrored_left, mirrored_right, color);
+                }
+                if dy == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Fill an axis aligned ellipse with horizontal and vertical radii `radii`
+    ///
+    /// The filled area covers exactly the pixels of the outline drawn by `ellipse` and
+    /// everything inside of it.
+    pub fn fill_ellipse(&mut self, center: (i32, i32), radii: (i32, i32), color: u32) {
+        let (nearest, farthest) = match self.ellipse_rows(center, radii) {
+            Some(rows) => rows,
+            None => return,
+        };
+        let (center_x, center_y) = (i64::from(center.0), i64::from(center.1));
+        for dy in nearest..=farthest {
+            // Widest outline pixel of the row
+            let half = ellipse_row(radii, dy).map_or(0, |(_, end)| end);
+            let (left, right) = self.clip_run(center_x - half, center_x + half);
+            for &y in &[center_y + dy, center_y - dy] {
+                if y >= 0 && y < self.height as i64 {
+                    self.span(y as usize, left, right, color);
+                }
+                if dy == 0 {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Draw the one pixel wide outline of a triangle
+    pub fn triangle(&mut self, points: [(i32, i32); 3], color: u32) {
+        self.polygon(&points, color);
+    }
+
+    pub fn fill_triangle(&mut self, points: [(i32, i32); 3], color: u32) {
+        self.fill_polygon(&points, color);
+    }
+
+    /// Draw the one pixel wide outline of the closed polygon through `points`
+    pub fn polygon(&mut self, points: &[(i32, i32)], color: u32) {
+        let mut outline = Vec::new();
+        let size = (self.width, self.height);
+        for (i, &start) in points.iter().enumerate() {
+            let end = points[(i + 1) % points.len()];
+            line_pixels(start, end, size, |x, y| outline.push((x, y)));
+        }
+        self.plot_all(outline, color);
+    }
+
+    /// Fill the closed polygon through `points`
+    ///
+    /// Pixels whose centers lie inside the polygon are filled, using the even-odd rule for
+    /// self intersecting polygons.
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)], color: u32) {
+        if points.len() < 3 {
+            return;
+        }
+        let min_y = points.iter().map(|point| point.1).min().unwrap_or(0);
+        let max_y = points.iter().map(|point| point.1).max().unwrap_or(0);
+        let (top, bottom) = self.clip_y(min_y, max_y);
+        let mut crossings = Vec::new();
+        for y in top..bottom {
+            let center = y as f64 + 0.5;
+            crossings.clear();
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                let (y0, y1) = (f64::from(y0), f64::from(y1));
+                // Half open so vertices shared by two edges are only counted once
+                if (y0 <= center) != (y1 <= center) {
+                    let t = (center - y0) / (y1 - y0);
+                    crossings.push(f64::from(x0) + t * f64::from(x1 - x0));
+                }
+            }
+            crossings.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for pair in crossings.chunks_exact(2) {
+                // Pixels whose centers lie between the crossings
+                let start = (pair[0] - 0.5).ceil() as i32;
+                let end = (pair[1] - 0.5).ceil() as i32;
+                let (left, right) = self.clip_x(start, end);
+                self.span(y, left, right, color);
+            }
+        }
+    }
+
+    /// Fill the areas enclosed by `path` according to `rule`
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule, color: u32) {
+        let polygons: Vec<_> = path
+            .flatten()
+            .into_iter()
+            .map(|polyline| polyline.points)
+            .collect();
+        self.blend_polygons(&polygons, rule, color);
+    }
+
+    /// Draw the outline of `path` as described by `stroke`
+    pub fn stroke_path(&mut self, path: &Path, stroke: &Stroke, color: u32) {
+        let polygons = stroke_polygons(&path.flatten(), stroke);
+        self.blend_polygons(&polygons, FillRule::NonZero, color);
+    }
+
+    /// Composite `source` onto the canvas with its top left corner at `x`, `y`
+    ///
+    /// The pixels of `source` are read according to its own alpha mode.
+    pub fn blit(&mut self, source: &Canvas, x: i32, y: i32) {
+        let (left, right) = self.clip_x(x, x.saturating_add(source.width as i32));
+        let (top, bottom) = self.clip_y(y, y.saturating_add(source.height as i32));
+        let source_mode = source.compositor.alpha_mode;
+        for target_y in top..bottom {
+            let source_row = (target_y as i32 - y) as usize * source.stride;
+            let source_left = source_row + (left as i32 - x) as usize;
+            let source_pixels = &source.pixels[source_left..][..right - left];
+            let row = &mut self.pixels[target_y * self.stride..][left..right];
+            for (pixel, &color) in row.iter_mut().zip(source_pixels) {
+                *pixel = self.compositor.apply(load(color, source_mode), *pixel, 255);
+            }
+        }
+    }
+
+    /// Composite straight alpha `color` wherever `polygons` cover the canvas
+    fn blend_polygons(&mut self, polygons: &[Vec<(f32, f32)>], rule: FillRule, color: u32) {
+        let source = premultiply(color);
+        let (width, height, stride) = (self.width, self.height, self.stride);
+        let (pixels, compositor) = (&mut *self.pixels, self.compositor);
+        rasterize(polygons, rule, width, height, |y, x, coverage| {
+            let row = &mut pixels[y * stride + x..][..coverage.len()];
+            for (pixel, &coverage) in row.iter_mut().zip(coverage) {
+                *pixel = compositor.apply(source, *pixel, (coverage * 255. + 0.5) as u32);
+            }
+        });
+    }
+
+    /// Composite straight alpha `color` through the coverage `mask` of rows of `width`
+    /// bytes, with its top left corner at `position`
+    #[cfg(feature = "truetype")]
+    pub(crate) fn blend_mask(
+        &mut self,
+        position: (i32, i32),
+        mask: &[u8],
+        width: usize,
+        color: u32,
+    ) {
+        let height = mask.len() / width;
+        let (x, y) = position;
+        let (left, right) = self.clip_x(x, x.saturating_add(width as i32));
+        let (top, bottom) = self.clip_y(y, y.saturating_add(height as i32));
+        let source = premultiply(color);
+        for target_y in top..bottom {
+            let mask_row = (target_y as i32 - y) as usize * width;
+            let mask_left = mask_row + (left as i32 - x) as usize;
+            let coverage = &mask[mask_left..][..right - left];
+            let row = &mut self.pixels[target_y * self.stride..][left..right];
+            for (pixel, &coverage) in row.iter_mut().zip(coverage) {
+                *pixel = self.compositor.apply(source, *pixel, u32::from(coverage));
+            }
+        }
+    }
+
+    /// Composite premultiplied `source` onto the pixel at `x`, `y` if it lies within the
+    /// canvas
+    pub(crate) fn plot(&mut self, x: i32, y: i32, source: u32) {
+        if let Some(index) = self.index(x, y) {
+            self.pixels[index] = self.compositor.apply(source, self.pixels[index], 255);
+        }
+    }
+
+    /// Plot straight alpha `color` at every distinct point of `points`, so translucent
+    /// outlines are not darker where they touch a pixel twice
+    fn plot_all(&mut self, mut points: Vec<(i32, i32)>, color: u32) {
+        points.sort_unstable();
+        points.dedup();
+        let source = premultiply(color);
+        for (x, y) in points {
+            self.plot(x, y, source);
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(y as usize * self.stride + x as usize)
+        }
+    }
+
+    /// Clamp the half open range `start..end` of columns to the canvas
+    pub(crate) fn clip_x(&self, start: i32, end: i32) -> (usize, usize) {
+        clip(start, end, self.width)
+    }
+
+    /// Clamp the half open range `start..end` of rows to the canvas
+    pub(crate) fn clip_y(&self, start: i32, end: i32) -> (usize, usize) {
+        clip(start, end, self.height)
+    }
+
+    /// Vertical distances from the center of an ellipse with radii `radii` around `center`
+    /// at which its rows lie on the canvas, `None` if none do
+    ///
+    /// Every distance in the range has a row above or below the center on the canvas.
+    fn ellipse_rows(&self, center: (i32, i32), radii: (i32, i32)) -> Option<(i64, i64)> {
+        let (center_y, height) = (i64::from(center.1), self.height as i64);
+        if radii.0 < 0 || radii.1 < 0 || height == 0 {
+            return None;
+        }
+        let nearest = if center_y < 0 {
+            -center_y
+        } else {
+            (center_y - height + 1).max(0)
+        };
+        let farthest = center_y.abs().max((height - 1 - center_y).abs());
+        let farthest = farthest.min(i64::from(radii.1));
+        if nearest > farthest {
+            None
+        } else {
+            Some((nearest, farthest))
+        }
+    }
+
+    /// Clamp the columns `start..=end` to the canvas, as a half open range
+    fn clip_run(&self, start: i64, end: i64) -> (usize, usize) {
+        let width = self.width as i64;
+        let (start, end) = (start.max(0).min(width), (end + 1).max(0).min(width));
+        (start as usize, end as usize)
+    }
+
+    /// Composite straight alpha `color` onto the columns `left..right` of row `y`, which
+    /// must lie within the canvas
+    fn span(&mut self, y: usize, left: usize, right: usize, color: u32) {
+        if left >= right {
+            return;
+        }
+        let source = premultiply(color);
+        let row = &mut self.pixels[y * self.stride..][left..right];
+        if self.compositor.replaces(source) {
+            let stored = self.compositor.store(source);
+            for pixel in row {
+                *pixel = stored;
+            }
+        } else {
+            for pixel in row {
+                *pixel = self.compositor.apply(source, *pixel, 255);
+            }
+        }
+    }
+}
+
+/// Call `plot` with the pixels of a one pixel wide line from `start` to `end`, both included,
+/// that lie on a canvas of `size`
+///
+/// The line is clipped to the canvas before it is walked, so only pixels on the canvas are
+/// visited. Those are exactly the pixels Bresenham's algorithm would pick for the whole line.
+fn line_pixels<F: FnMut(i32, i32)>(
+    start: (i32, i32),
+    end: (i32, i32),
+    size: (usize, usize),
+    mut plot: F,
+) {
+    let (width, height) = (size.0 as i128, size.1 as i128);
+    let (dx, dy) = (
+        i128::from(end.0) - i128::from(start.0),
+        i128::from(end.1) - i128::from(start.1),
+    );
+    // Walk the major axis one pixel at a time, the minor coordinate after `n` steps is
+    // `n * rise / length` rounded half up
+    let x_major = dx.abs() >= dy.abs();
+    let ((major, major_delta, major_size), (minor, minor_delta, minor_size)) = if x_major {
+        (
+            (i128::from(start.0), dx, width),
+            (i128::from(start.1), dy, height),
+        )
+    } else {
+        (
+            (i128::from(start.1), dy, height),
+            (i128::from(start.0), dx, width),
+        )
+    };
+    let (length, rise) = (major_delta.abs(), minor_delta.abs());
+    let (major_step, minor_step) = (major_delta.signum(), minor_delta.signum());
+
+    // Steps at which the major coordinate lies on the canvas
+    let (mut first, mut last) = match steps_within(major, major_step, major_size) {
+        Some((first, last)) => (first.max(0), last.min(length)),
+        None => return,
+    };
+    // Narrowed to the steps at which the minor coordinate does, which form one range too as
+    // it only moves one way
+    if rise == 0 {
+        if minor < 0 || minor >= minor_size {
+            return;
+        }
+    } else {
+        let (low, high) = match steps_within(minor, minor_step, minor_size) {
+            Some((low, high)) => (low.max(0), high),
+            None => return,
+        };
+        if low > high {
+            return;
+        }
+        // Smallest step reaching `low` minor steps and largest not reaching `high + 1`
+        let (numerator, denominator) = (2 * length * low - length, 2 * rise);
+        first = first.max(-(-numerator).div_euclid(denominator));
+        last = last.min((2 * length * high + length - 1).div_euclid(denominator));
+    }
+    if first > last {
+        return;
+    }
+
+    // Bresenham's algorithm from the first step on the canvas, `remainder` tracks the
+    // fractional part of the minor coordinate in units of `1 / (2 * length)`
+    let total = 2 * length.max(1);
+    let position = 2 * rise * first + length;
+    let (mut minor_steps, mut remainder) = (position / total, position % total);
+    for n in first..=last {
+        let (a, b) = (major + major_step * n, minor + minor_step * minor_steps);
+        let (x, y) = if x_major { (a, b) } else { (b, a) };
+        plot(x as i32, y as i32);
+        remainder += 2 * rise;
+        if remainder >= total {
+            remainder -= total;
+            minor_steps += 1;
+        }
+    }
+}
+
+/// Range of steps `n` for which `start + step * n` lies within `0..size`, `None` if it
+/// never does
+fn steps_within(start: i128, step: i128, size: i128) -> Option<(i128, i128)> {
+    match step {
+        1 => Some((-start, size - 1 - start)),
+        -1 => Some((start - (size - 1), start)),
+        _ if start >= 0 && start < size => Some((0, i128::MAX)),
+        _ => None,
+    }
+}
+
+/// Columns `start..=end` of the outline pixels of an ellipse around the origin in row `dy`
+/// of the quadrant of positive coordinates, `None` if the outline leaves that row out
+///
+/// These are the pixels the midpoint algorithm picks, found without walking the outline up
+/// to the row. Its decision terms are `4 * f` of the midpoints between pixels, where
+/// `f(x, y) = ry² x² + rx² y² - rx² ry²` is negative inside of the ellipse.
+fn ellipse_row(radii: (i32, i32), dy: i64) -> Option<(i64, i64)> {
+    if radii.0 < 0 || radii.1 < 0 || dy < 0 || dy > i64::from(radii.1) {
+        return None;
+    }
+    let (rx, ry, dy) = (radii.0 as u128, radii.1 as u128, dy as u128);
+    if rx == 0 || ry == 0 {
+        // Degenerate ellipses are straight lines the midpoint algorithm leaves gaps in
+        return Some((0, rx as i64));
+    }
+    let (rx2, ry2) = (rx * rx, ry * ry);
+    let whole = 4 * rx2 * ry2;
+    let mut run: Option<(u128, u128)> = None;
+
+    // The shallow part, stepping along x, stays in the row from the first column whose
+    // midpoint below lies outside of the ellipse to the last whose midpoint above lies inside
+    let below = rx2 * (2 * dy + 1) * (2 * dy + 1);
+    let first = if below >= whole {
+        0
+    } else {
+        ceil_sqrt((whole - below).div_ceil(4 * ry2))
+    };
+    let above = if dy == 0 {
+        rx2
+    } else {
+        rx2 * (2 * dy - 1) * (2 * dy - 1)
+    };
+    if above < whole {
+        // It ends where the slope becomes steeper than -1
+        let last = sqrt((whole - above).div_ceil(4 * ry2) - 1).min(rx2 * dy / ry2);
+        if first <= last {
+            run = Some((first, last));
+        }
+    }
+
+    // The steep part, stepping along y, picks one pixel per row
+    let x = ceil_sqrt((4 * rx2 * (ry2 - dy * dy)).div_ceil(ry2)) / 2;
+    if rx2 * dy <= ry2 * x {
+        run = Some(run.map_or((x, x), |(start, end)| (start.min(x), end.max(x))));
+    }
+    run.map(|(start, end)| (start as i64, end as i64))
+}
+
+/// Largest integer whose square is at most `n`
+fn sqrt(n: u128) -> u128 {
+    let mut root = (n as f64).sqrt() as u128;
+    while root * root > n {
+        root -= 1;
+    }
+    while (root + 1) * (root + 1) <= n {
+        root += 1;
+    }
+    root
+}
+
+/// Smallest integer whose square is at least `n`
+fn ceil_sqrt(n: u128) -> u128 {
+    let root = sqrt(n);
+    if root * root == n {
+        root
+    } else {
+        root + 1
+    }
+}
+
+fn clip(start: i32, end: i32, size: usize) -> (usize, usize) {
+    let start = start.max(0) as usize;
+    let end = end.max(0) as usize;
+    (start.min(size), end.min(size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PADDING: u32 = 0x1234_5678;
+    const OPAQUE: u32 = 0xFFFF_FFFF;
+    const TRANSLUCENT: u32 = 0x80FF_FFFF;
+
+    /// Rows of a `width` by `height` canvas with rows `stride` pixels apart after `draw`, with
+    /// opaque pixels as `#` and translucent ones composited once as `+`
+    ///
+    /// The padding between rows must be left alone.
+    fn render<F: FnOnce(&mut Canvas)>(
+        (width, height): (usize, usize),
+        stride: usize,
+        draw: F,
+    ) -> Vec<String> {
+        let mut pixels = vec![PADDING; stride * height];
+        for y in 0..height {
+            for pixel in &mut pixels[y * stride..][..width] {
+                *pixel = 0;
+            }
+        }
+        draw(&mut Canvas::with_stride(&mut pixels, width, height, stride).unwrap());
+        pixels
+            .chunks(stride)
+            .map(|row| {
+                assert!(row[width..].iter().all(|&pixel| pixel == PADDING));
+                row[..width]
+                    .iter()
+                    .map(|&pixel| match pixel {
+                        0 => '.',
+                        OPAQUE => '#',
+                        0x8080_8080 => '+',
+                        _ => '?',
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Check that drawing on a small canvas shows the same as the part of a large canvas it
+    /// would be cut out of, with the shapes moved to match
+    fn assert_clipped<F: Fn(&mut Canvas, i32)>(draw: F) {
+        let small = render((5, 4), 7, |canvas| draw(canvas, 0));
+        let large = render((40, 40), 40, |canvas| draw(canvas, 15));
+        let window: Vec<String> = large[15..19]
+            .iter()
+            .map(|row| row[15..20].to_string())
+            .collect();
+        assert_eq!(small, window);
+    }
+
+    /// Pixels of the quadrant of an ellipse outline as walked by the midpoint algorithm
+    fn midpoint_quadrant((rx, ry): (i64, i64)) -> Vec<(i64, i64)> {
+        let mut points = Vec::new();
+        if rx == 0 || ry == 0 {
+            for x in 0..=rx {
+                for y in 0..=ry {
+                    points.push((x, y));
+                }
+            }
+            return points;
+        }
+        let (rx2, ry2) = (rx * rx, ry * ry);
+        let (mut x, mut y) = (0, ry);
+        let mut decision = 4 * ry2 - 4 * rx2 * ry + rx2;
+        while ry2 * x <= rx2 * y {
+            points.push((x, y));
+            if decision >= 0 {
+                y -= 1;
+                decision -= 8 * rx2 * y;
+            }
+            x += 1;
+            decision += 4 * ry2 * (2 * x + 1);
+        }
+        let (mut x, mut y) = (rx, 0);
+        let mut decision = 4 * rx2 - 4 * ry2 * rx + ry2;
+        while rx2 * y <= ry2 * x {
+            points.push((x, y));
+            if decision >= 0 {
+                x -= 1;
+                decision -= 8 * ry2 * x;
+            }
+            y += 1;
+            decision += 4 * rx2 * (2 * y + 1);
+        }
+        points
+    }
+
+    #[test]
+    fn fills_rects() {
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.fill_rect(-1, 1, 3, 2, OPAQUE);
+            canvas.fill_rect(4, 3, 10, 10, OPAQUE);
+            canvas.fill_rect(2, 0, 0, 5, OPAQUE);
+            canvas.fill_rect(2, 0, 1, -3, OPAQUE);
+            canvas.fill_rect(i32::MIN, 0, i32::MAX, 4, OPAQUE);
+            canvas.fill_rect(5, 0, i32::MAX, 4, OPAQUE);
+        });
+        assert_eq!(rows, [".....", "##...", "##...", "....#"]);
+
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.fill_rect(i32::MIN, i32::MIN, i32::MAX, i32::MAX, OPAQUE);
+            canvas.fill_rect(1, 1, i32::MAX, i32::MAX, TRANSLUCENT);
+        });
+        assert_eq!(rows, [".....", ".++++", ".++++", ".++++"]);
+    }
+
+    #[test]
+    fn strokes_rects() {
+        let rows = render((6, 5), 8, |canvas| canvas.stroke_rect(1, 1, 4, 3, OPAQUE));
+        assert_eq!(rows, ["......", ".####.", ".#..#.", ".####.", "......"]);
+
+        let rows = render((4, 4), 5, |canvas| canvas.stroke_rect(-2, -2, 5, 5, OPAQUE));
+        assert_eq!(rows, ["..#.", "..#.", "###.", "...."]);
+
+        // Rects one pixel wide or high do not draw their pixels twice
+        let rows = render((4, 4), 5, |canvas| {
+            canvas.stroke_rect(0, 0, 1, 1, TRANSLUCENT);
+            canvas.stroke_rect(2, 0, 1, 4, TRANSLUCENT);
+            canvas.stroke_rect(0, 3, 2, 1, TRANSLUCENT);
+            canvas.stroke_rect(0, 1, 0, 1, OPAQUE);
+            canvas.stroke_rect(i32::MAX, i32::MAX, i32::MAX, i32::MAX, OPAQUE);
+        });
+        assert_eq!(rows, ["+.+.", "..+.", "..+.", "+++."]);
+    }
+
+    #[test]
+    fn draws_lines() {
+        let rows = render((5, 3), 6, |canvas| canvas.line((0, 0), (4, 2), OPAQUE));
+        assert_eq!(rows, ["#....", ".##..", "...##"]);
+        let rows = render((5, 3), 6, |canvas| canvas.line((4, 2), (4, 2), OPAQUE));
+        assert_eq!(rows, [".....", ".....", "....#"]);
+    }
+
+    #[test]
+    fn clips_lines() {
+        let lines = [
+            ((-10, 2), (10, 2)),
+            ((-3, -3), (7, 7)),
+            ((-6, 5), (8, -2)),
+            ((2, -20), (3, 20)),
+            ((-7, 3), (1, -1)),
+            ((9, 9), (-1, 1)),
+            ((-5, -1), (20, 3)),
+        ];
+        for &((x0, y0), (x1, y1)) in &lines {
+            assert_clipped(|canvas, offset| {
+                let (start, end) = ((x0 + offset, y0 + offset), (x1 + offset, y1 + offset));
+                canvas.line(start, end, OPAQUE);
+            });
+        }
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.line((i32::MIN, i32::MIN), (i32::MAX, i32::MAX), OPAQUE);
+            canvas.line((-10, -1), (10, -1), OPAQUE);
+            canvas.line((6, -5), (6, 5), OPAQUE);
+        });
+        assert_eq!(rows, ["#....", ".#...", "..#..", "...#."]);
+    }
+
+    #[test]
+    fn draws_circles() {
+        let rows = render((7, 7), 9, |canvas| canvas.circle((3, 3), 3, OPAQUE));
+        let circle = [
+            "..###..", ".#...#.", "#.....#", "#.....#", "#.....#", ".#...#.", "..###..",
+        ];
+        assert_eq!(rows, circle);
+        // Translucent outlines are composited once where quadrants meet
+        let rows = render((7, 7), 9, |canvas| canvas.circle((3, 3), 3, TRANSLUCENT));
+        let translucent: Vec<String> = circle.iter().map(|row| row.replace('#', "+")).collect();
+        assert_eq!(rows, translucent);
+
+        let rows = render((7, 7), 9, |canvas| canvas.fill_circle((3, 3), 3, OPAQUE));
+        let filled = [
+            "..###..", ".#####.", "#######", "#######", "#######", ".#####.", "..###..",
+        ];
+        assert_eq!(rows, filled);
+
+        let rows = render((3, 3), 4, |canvas| {
+            canvas.circle((1, 1), 0, TRANSLUCENT);
+            canvas.circle((1, 1), -1, OPAQUE);
+            canvas.fill_circle((0, 0), -1, OPAQUE);
+        });
+        assert_eq!(rows, ["...", ".+.", "..."]);
+    }
+
+    #[test]
+    fn draws_flat_ellipses() {
+        let rows = render((7, 3), 8, |canvas| {
+            canvas.ellipse((2, 1), (2, 0), TRANSLUCENT);
+            canvas.ellipse((6, 1), (0, 1), TRANSLUCENT);
+        });
+        assert_eq!(rows, ["......+", "+++++.+", "......+"]);
+    }
+
+    #[test]
+    fn clips_circles() {
+        for &(x, y, radius) in &[(2, 2, 4), (-3, 1, 5), (6, 5, 3), (2, -10, 12), (20, 20, 3)] {
+            assert_clipped(|canvas, offset| {
+                canvas.circle((x + offset, y + offset), radius, OPAQUE);
+                canvas.fill_circle((x + offset, y + offset), radius / 2, OPAQUE);
+            });
+        }
+        assert_clipped(|canvas, offset| {
+            canvas.ellipse((2 + offset, 1 + offset), (9, 2), OPAQUE);
+        });
+    }
+
+    #[test]
+    fn draws_huge_circles_quickly() {
+        // The top of the circle is flat across the canvas
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.circle((2, i32::MAX), i32::MAX, OPAQUE);
+            canvas.circle((i32::MIN, i32::MIN), i32::MAX, OPAQUE);
+            canvas.ellipse((0, 0), (i32::MAX, i32::MAX), OPAQUE);
+        });
+        assert_eq!(rows, ["#####", ".....", ".....", "....."]);
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.fill_circle((2, i32::MAX), i32::MAX, OPAQUE);
+        });
+        assert_eq!(rows, ["#####"; 4]);
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.fill_ellipse((i32::MAX, 1), (i32::MAX - 1, 0), TRANSLUCENT);
+            canvas.fill_ellipse((i32::MIN, i32::MIN), (i32::MAX, i32::MAX), OPAQUE);
+        });
+        assert_eq!(rows, [".....", ".++++", ".....", "....."]);
+    }
+
+    #[test]
+    fn matches_the_midpoint_algorithm() {
+        for rx in 0..40 {
+            for ry in 0..40 {
+                let mut rows = vec![None; ry as usize + 1];
+                for (x, y) in midpoint_quadrant((rx, ry)) {
+                    let row: &mut Option<(i64, i64)> = &mut rows[y as usize];
+                    *row = Some(row.map_or((x, x), |(start, end)| (start.min(x), end.max(x))));
+                }
+                for (dy, &row) in rows.iter().enumerate() {
+                    assert_eq!(ellipse_row((rx as i32, ry as i32), dy as i64), row);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn draws_polygons() {
+        let rows = render((5, 4), 6, |canvas| {
+            canvas.triangle([(0, 0), (4, 0), (0, 3)], TRANSLUCENT);
+        });
+        assert_eq!(rows, ["+++++", "+..+.", "+++..", "+...."]);
+        let rows = render((5, 4), 6, |canvas| {
+            canvas.fill_triangle([(0, 0), (4, 0), (0, 3)], OPAQUE);
+        });
+        assert_eq!(rows, ["###..", "##...", "#....", "....."]);
+    }
+
+    #[test]
+    fn clips_polygons() {
+        let shapes: [&[(i32, i32)]; 4] = [
+            &[(-3, -2), (6, 1), (1, 7)],
+            &[(2, -5), (9, 3), (2, 9), (-6, 3)],
+            &[(-1, 1), (30, 2), (-1, 3)],
+            &[(0, 0), (8, 8), (8, 0), (0, 8)],
+        ];
+        for shape in &shapes {
+            assert_clipped(|canvas, offset| {
+                let moved: Vec<_> = shape
+                    .iter()
+                    .map(|&(x, y)| (x + offset, y + offset))
+                    .collect();
+                canvas.polygon(&moved, OPAQUE);
+            });
+            assert_clipped(|canvas, offset| {
+                let moved: Vec<_> = shape
+                    .iter()
+                    .map(|&(x, y)| (x + offset, y + offset))
+                    .collect();
+                canvas.fill_polygon(&moved, OPAQUE);
+            });
+        }
+        let rows = render((5, 4), 7, |canvas| {
+            canvas.triangle([(-9, -9), (-1, -9), (-5, -1)], OPAQUE);
+            canvas.fill_polygon(&[(0, 0), (4, 0)], OPAQUE);
+            canvas.fill_triangle([(i32::MIN, 0), (i32::MAX, 0), (0, 2)], OPAQUE);
+        });
+        assert_eq!(rows, ["#####", "#####", ".....", "....."]);
+    }
+}
@@ -0,0 +1,263 @@
+//! Conversion of pixel formats into the native endian argb `u32`s backends present
+//!
+//! Conversions and premultiplication use SSE2, SSSE3 or AVX2 on x86 and NEON on little
+//! endian aarch64 when the processor supports them, chosen at runtime, and fall back to
+//! scalar code otherwise.
+
+#[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+mod neon;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+mod x86;
+
+use crate::{AlphaMode, PixelFormat};
+
+/// Convert the `format` pixels in `source` into argb `u32`s in `target`
+///
+/// Converts as many pixels as fit in both slices, a trailing partial pixel in `source` is
+/// ignored.
+pub fn to_argb32(format: PixelFormat, source: &[u8], target: &mut [u32]) {
+    let bytes_per_pixel = format.bytes_per_pixel();
+    let count = target.len().min(source.len() / bytes_per_pixel);
+    let (source, target) = (&source[..count * bytes_per_pixel], &mut target[..count]);
+
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let done = x86::to_argb32(format, source, target);
+    #[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+    let done = neon::to_argb32(format, source, target);
+    #[cfg(not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        all(target_arch = "aarch64", target_endian = "little")
+    )))]
+    let done = 0;
+
+    scalar_to_argb32(
+        format,
+        &source[done * bytes_per_pixel..],
+        &mut target[done..],
+    );
+}
+
+/// Convert pixels given as the bytes `a, r, g, b` into argb `u32`s in `target`
+///
+/// This is the layout taken by `draw_argb8888` and `draw_argb8888_bytes`.
+pub fn argb_bytes_to_argb32(source: &[u8], target: &mut [u32]) {
+    // Read as a native endian integer the bytes hold 0xBBGGRRAA on little endian machines
+    if cfg!(target_endian = "little") {
+        to_argb32(PixelFormat::Bgra8888, source, target);
+    } else {
+        to_argb32(PixelFormat::Argb8888, source, target);
+    }
+}
+
+/// Multiply the color channels of straight argb `pixels` by their alpha, in place
+///
+/// This is what `AlphaMode::Straight` frames go through before they are presented.
+pub fn premultiply_argb32(pixels: &mut [u32]) {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let done = x86::premultiply(pixels);
+    #[cfg(all(target_arch = "aarch64", target_endian = "little"))]
+    let done = neon::premultiply(pixels);
+    #[cfg(not(any(
+        target_arch = "x86",
+        target_arch = "x86_64",
+        all(target_arch = "aarch64", target_endian = "little")
+    )))]
+    let done = 0;
+
+    for pixel in &mut pixels[done..] {
+        *pixel = premultiply(*pixel);
+    }
+}
+
+/// View pixels given as `[a, r, g, b]` arrays as bytes
+pub(crate) fn argb_arrays_as_bytes(pixels: &[[u8; 4]]) -> &[u8] {
+    // Arrays of bytes have no padding and an alignment of 1
+    unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const u8, pixels.len() * 4) }
+}
+
+/// Rewrite straight or opaque argb pixels as the premultiplied pixels compositors expect
+pub(crate) fn apply_alpha(mode: AlphaMode, pixels: &mut [u32]) {
+    match mode {
+        AlphaMode::Opaque => {
+            for pixel in pixels {
+                *pixel |= 0xFF00_0000;
+            }
+        }
+        AlphaMode::Straight => premultiply_argb32(pixels),
+        AlphaMode::Premultiplied => {}
+    }
+}
+
+/// Multiply the color channels of a straight argb pixel by its alpha
+pub(crate) fn premultiply(pixel: u32) -> u32 {
+    let alpha = pixel >> 24;
+    match alpha {
+        0xFF => pixel,
+        0 => 0,
+        _ => {
+            // Divides by 255 with rounding, exact for every product of two bytes
+            let scale = |channel: u32| {
+                let product = channel * alpha + 0x80;
+                (product + (product >> 8)) >> 8
+            };
+            alpha << 24
+                | scale((pixel >> 16) & 0xFF) << 16
+                | scale((pixel >> 8) & 0xFF) << 8
+                | scale(pixel & 0xFF)
+        }
+    }
+}
+
+/// Convert pixels one at a time, used for whatever the vector paths leave over
+fn scalar_to_argb32(format: PixelFormat, source: &[u8], target: &mut [u32]) {
+    match format {
+        PixelFormat::Argb8888 => {
+            for (target, pixel) in target.iter_mut().zip(source.chunks_exact(4)) {
+                *target = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            }
+        }
+        PixelFormat::Xrgb8888 => {
+            for (target, pixel) in target.iter_mut().zip(source.chunks_exact(4)) {
+                *target =
+                    0xFF00_0000 | u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+            }
+        }
+        PixelFormat::Rgba8888 => {
+            for (target, pixel) in target.iter_mut().zip(source.chunks_exact(4)) {
+                *target =
+                    u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]).rotate_right(8);
+            }
+        }
+        PixelFormat::Bgra8888 => {
+            for (target, pixel) in target.iter_mut().zip(source.chunks_exact(4)) {
+                *target = u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]).swap_bytes();
+            }
+        }
+        PixelFormat::Rgb888 => {
+            for (target, pixel) in target.iter_mut().zip(source.chunks_exact(3)) {
+                *target = 0xFF00_0000
+                    | u32::from(pixel[0]) << 16
+                    | u32::from(pixel[1]) << 8
+                    | u32::from(pixel[2]);
+            }
+        }
+        PixelFormat::Rgb565 => {
+            for (target, pixel) in target.iter_mut().zip(source.chunks_exact(2)) {
+                let pixel = u32::from(u16::from_ne_bytes([pixel[0], pixel[1]]));
+                let (r, g, b) = (pixel >> 11, (pixel >> 5) & 0x3F, pixel & 0x1F);
+                // Replicate the top bits so full intensity maps to 0xFF
+                *target = 0xFF00_0000
+                    | ((r << 3) | (r >> 2)) << 16
+                    | ((g << 2) | (g >> 4)) << 8
+                    | ((b << 3) | (b >> 2));
+            }
+        }
+        PixelFormat::Gray8 => {
+            for (target, luminance) in target.iter_mut().zip(source) {
+                *target = 0xFF00_0000 | (u32::from(*luminance) * 0x01_01_01);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    const FORMATS: [PixelFormat; 7] = [
+        PixelFormat::Argb8888,
+        PixelFormat::Xrgb8888,
+        PixelFormat::Rgba8888,
+        PixelFormat::Bgra8888,
+        PixelFormat::Rgb888,
+        PixelFormat::Rgb565,
+        PixelFormat::Gray8,
+    ];
+
+    /// Around every vector width of every path
+    const LENGTHS: [usize; 16] = [0, 1, 3, 4, 7, 8, 15, 16, 17, 31, 32, 33, 63, 64, 65, 257];
+
+    fn random_bytes(len: usize) -> Vec<u8> {
+        let mut rng = StdRng::seed_from_u64(len as u64);
+        (0..len).map(|_| rng.gen()).collect()
+    }
+
+    #[test]
+    fn vector_paths_match_scalar() {
+        for &format in &FORMATS {
+            for &len in &LENGTHS {
+                // An extra byte shows the vector paths do not read past the last pixel
+                let bytes = random_bytes(len * format.bytes_per_pixel() + 1);
+                // Start at an odd address too, loads are unaligned
+                for source in &[&bytes[..bytes.len() - 1], &bytes[1..]] {
+                    for &mode in &[AlphaMode::Straight, AlphaMode::Premultiplied] {
+                        let mut expected = vec![0; len];
+                        scalar_to_argb32(format, source, &mut expected);
+                        apply_alpha(mode, &mut expected);
+                        let mut converted = vec![0; len];
+                        to_argb32(format, source, &mut converted);
+                        apply_alpha(mode, &mut converted);
+                        assert_eq!(converted, expected, "{:?} of {} pixels", format, len);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn converts_what_fits_in_both_slices() {
+        let bytes = random_bytes(3 * 20 + 2);
+        let mut expected = vec![0xDEAD_BEEF; 25];
+        scalar_to_argb32(PixelFormat::Rgb888, &bytes, &mut expected[..20]);
+        let mut converted = vec![0xDEAD_BEEF; 25];
+        to_argb32(PixelFormat::Rgb888, &bytes, &mut converted);
+        assert_eq!(converted, expected);
+    }
+
+    #[test]
+    fn reads_argb_bytes() {
+        let bytes = [0x80, 0x11, 0x22, 0x33, 0xFF, 0xAA, 0xBB, 0xCC];
+        let mut converted = [0; 2];
+        argb_bytes_to_argb32(&bytes, &mut converted);
+        assert_eq!(converted, [0x8011_2233, 0xFFAA_BBCC]);
+        assert_eq!(
+            argb_arrays_as_bytes(&[[1, 2, 3, 4], [5, 6, 7, 8]]),
+            &[1, 2, 3, 4, 5, 6, 7, 8]
+        );
+    }
+
+    #[test]
+    fn vector_premultiply_matches_scalar() {
+        for &len in &LENGTHS {
+            let bytes = random_bytes(4 * len);
+            let mut pixels = vec![0; len];
+            to_argb32(PixelFormat::Argb8888, &bytes, &mut pixels);
+            // Fully transparent and opaque pixels take shortcuts in the scalar path
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                match i % 5 {
+                    0 => *pixel &= 0x00FF_FFFF,
+                    1 => *pixel |= 0xFF00_0000,
+                    _ => {}
+                }
+            }
+            let expected: Vec<u32> = pixels.iter().map(|&pixel| premultiply(pixel)).collect();
+            premultiply_argb32(&mut pixels);
+            assert_eq!(pixels, expected, "{} pixels", len);
+        }
+    }
+
+    #[test]
+    fn premultiplies_exactly() {
+        for alpha in 0..=255u32 {
+            for channel in 0..=255u32 {
+                let pixel = premultiply((alpha << 24) | (channel * 0x01_01_01));
+                let scaled = (channel * alpha + 127) / 255;
+                assert_eq!(pixel, (alpha << 24) | (scaled * 0x01_01_01));
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+//! NEON conversions, every function converts a leading run of whole vectors and returns how
+//! many pixels it wrote
+//!
+//! NEON is part of every aarch64 processor so no runtime detection is needed. Callers
+//! guarantee `source` holds at least one pixel of its format for every `u32` in `target`.
+
+use std::arch::aarch64::*;
+
+use crate::PixelFormat;
+
+pub(super) fn to_argb32(format: PixelFormat, source: &[u8], target: &mut [u32]) -> usize {
+    unsafe {
+        match format {
+            PixelFormat::Xrgb8888 => or_alpha(source, target),
+            PixelFormat::Rgba8888 => rotate(source, target),
+            PixelFormat::Bgra8888 => swap(source, target),
+            PixelFormat::Rgb888 => rgb888(source, target),
+            PixelFormat::Rgb565 => rgb565(source, target),
+            PixelFormat::Gray8 => gray8(source, target),
+            // Argb8888 is a plain copy the scalar path already compiles to
+            PixelFormat::Argb8888 => 0,
+        }
+    }
+}
+
+/// Premultiply a leading run of whole vectors of `pixels`, returning how many were written
+pub(super) fn premultiply(pixels: &mut [u32]) -> usize {
+    unsafe { premultiply_alpha(pixels) }
+}
+
+#[inline(always)]
+unsafe fn load_u32(source: &[u8], offset: usize) -> uint32x4_t {
+    vreinterpretq_u32_u8(vld1q_u8(source.as_ptr().add(offset)))
+}
+
+#[inline(always)]
+unsafe fn store_u32(target: &mut [u32], index: usize, pixels: uint32x4_t) {
+    vst1q_u8(
+        target.as_mut_ptr().add(index) as *mut u8,
+        vreinterpretq_u8_u32(pixels),
+    )
+}
+
+unsafe fn or_alpha(source: &[u8], target: &mut [u32]) -> usize {
+    let alpha = vdupq_n_u32(0xFF00_0000);
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        store_u32(target, i, vorrq_u32(load_u32(source, 4 * i), alpha));
+    }
+    count
+}
+
+unsafe fn rotate(source: &[u8], target: &mut [u32]) -> usize {
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        let pixels = load_u32(source, 4 * i);
+        let rotated = vorrq_u32(vshrq_n_u32::<8>(pixels), vshlq_n_u32::<24>(pixels));
+        store_u32(target, i, rotated);
+    }
+    count
+}
+
+unsafe fn swap(source: &[u8], target: &mut [u32]) -> usize {
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        let pixels = vrev32q_u8(vld1q_u8(source.as_ptr().add(4 * i)));
+        vst1q_u8(target.as_mut_ptr().add(i) as *mut u8, pixels);
+    }
+    count
+}
+
+unsafe fn rgb888(source: &[u8], target: &mut [u32]) -> usize {
+    let alpha = vdupq_n_u8(0xFF);
+    let count = target.len() / 16 * 16;
+    for i in (0..count).step_by(16) {
+        let rgb = vld3q_u8(source.as_ptr().add(3 * i));
+        // Little endian argb is stored as the bytes b, g, r, a
+        let bgra = uint8x16x4_t(rgb.2, rgb.1, rgb.0, alpha);
+        vst4q_u8(target.as_mut_ptr().add(i) as *mut u8, bgra);
+    }
+    count
+}
+
+unsafe fn rgb565(source: &[u8], target: &mut [u32]) -> usize {
+    let (green_mask, blue_mask) = (vdupq_n_u16(0x3F), vdupq_n_u16(0x1F));
+    let alpha = vdupq_n_u16(0xFF00);
+    let count = target.len() / 8 * 8;
+    for i in (0..count).step_by(8) {
+        let pixels = vreinterpretq_u16_u8(vld1q_u8(source.as_ptr().add(2 * i)));
+        let r = vshrq_n_u16::<11>(pixels);
+        let g = vandq_u16(vshrq_n_u16::<5>(pixels), green_mask);
+        let b = vandq_u16(pixels, blue_mask);
+        // Replicate the top bits so full intensity maps to 0xFF
+        let r = vorrq_u16(vshlq_n_u16::<3>(r), vshrq_n_u16::<2>(r));
+        let g = vorrq_u16(vshlq_n_u16::<2>(g), vshrq_n_u16::<4>(g));
+        let b = vorrq_u16(vshlq_n_u16::<3>(b), vshrq_n_u16::<2>(b));
+        // Interleaving 0xGGBB and 0xFFRR halves gives 0xFFRRGGBB
+        let low = vorrq_u16(b, vshlq_n_u16::<8>(g));
+        let high = vorrq_u16(r, alpha);
+        vst2q_u16(
+            target.as_mut_ptr().add(i) as *mut u16,
+            uint16x8x2_t(low, high),
+        );
+    }
+    count
+}
+
+unsafe fn gray8(source: &[u8], target: &mut [u32]) -> usize {
+    let alpha = vdupq_n_u8(0xFF);
+    let count = target.len() / 16 * 16;
+    for i in (0..count).step_by(16) {
+        let luminance = vld1q_u8(source.as_ptr().add(i));
+        let bgra = uint8x16x4_t(luminance, luminance, luminance, alpha);
+        vst4q_u8(target.as_mut_ptr().add(i) as *mut u8, bgra);
+    }
+    count
+}
+
+/// Multiply the bytes of `channel` by those of `alpha`, dividing by 255 with the rounding of
+/// the scalar `premultiply`
+#[inline(always)]
+unsafe fn multiply_alpha(channel: uint8x16_t, alpha: uint8x16_t) -> uint8x16_t {
+    // Rounding shifts give (p + 128 + ((p + 128) >> 8)) >> 8 for every product p
+    let low = vmull_u8(vget_low_u8(channel), vget_low_u8(alpha));
+    let high = vmull_high_u8(channel, alpha);
+    vcombine_u8(
+        vraddhn_u16(low, vrshrq_n_u16::<8>(low)),
+        vraddhn_u16(high, vrshrq_n_u16::<8>(high)),
+    )
+}
+
+unsafe fn premultiply_alpha(pixels: &mut [u32]) -> usize {
+    let count = pixels.len() / 16 * 16;
+    for i in (0..count).step_by(16) {
+        let pointer = pixels.as_mut_ptr().add(i) as *mut u8;
+        // Little endian argb is stored as the bytes b, g, r, a
+        let bgra = vld4q_u8(pointer);
+        let alpha = bgra.3;
+        let premultiplied = uint8x16x4_t(
+            multiply_alpha(bgra.0, alpha),
+            multiply_alpha(bgra.1, alpha),
+            multiply_alpha(bgra.2, alpha),
+            alpha,
+        );
+        vst4q_u8(pointer, premultiplied);
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::convert::scalar_to_argb32;
+
+    type Conversion = unsafe fn(&[u8], &mut [u32]) -> usize;
+
+    /// Run `convert` on every length around its vector width and compare what it wrote with
+    /// the scalar conversion, byte for byte
+    fn check(format: PixelFormat, convert: Conversion) {
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+        for len in (0..70).chain(vec![127, 128, 129, 1000]) {
+            let bytes: Vec<u8> = (0..len * format.bytes_per_pixel() + 1)
+                .map(|_| rng.gen())
+                .collect();
+            for source in &[&bytes[..bytes.len() - 1], &bytes[1..]] {
+                let mut expected = vec![0; len];
+                scalar_to_argb32(format, source, &mut expected);
+                let mut converted = vec![0xDEAD_BEEF; len];
+                let count = unsafe { convert(source, &mut converted) };
+                assert!(
+                    count <= len,
+                    "{:?} wrote {} of {} pixels",
+                    format,
+                    count,
+                    len
+                );
+                assert!(converted[count..].iter().all(|&pixel| pixel == 0xDEAD_BEEF));
+                scalar_to_argb32(
+                    format,
+                    &source[count * format.bytes_per_pixel()..],
+                    &mut converted[count..],
+                );
+                let as_bytes = |pixels: &[u32]| -> Vec<u8> {
+                    pixels
+                        .iter()
+                        .flat_map(|pixel| pixel.to_ne_bytes())
+                        .collect()
+                };
+                assert_eq!(
+                    as_bytes(&converted),
+                    as_bytes(&expected),
+                    "{:?} of {} pixels",
+                    format,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn premultiply_matches_scalar() {
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+        for len in (0..70).chain(vec![127, 128, 129, 1000]) {
+            // Fully transparent and opaque pixels take shortcuts in the scalar path
+            let pixels: Vec<u32> = (0..len)
+                .map(|i| match i % 7 {
+                    0 => rng.gen::<u32>() & 0x00FF_FFFF,
+                    1 => rng.gen::<u32>() | 0xFF00_0000,
+                    _ => rng.gen(),
+                })
+                .collect();
+            let expected: Vec<u32> = pixels
+                .iter()
+                .map(|&pixel| crate::convert::premultiply(pixel))
+                .collect();
+            let mut premultiplied = pixels.clone();
+            let count = unsafe { premultiply_alpha(&mut premultiplied) };
+            assert!(count <= len, "wrote {} of {} pixels", count, len);
+            assert_eq!(premultiplied[count..], pixels[count..]);
+            assert_eq!(premultiplied[..count], expected[..count], "{} pixels", len);
+        }
+        // Every channel and alpha pair
+        let mut pixels: Vec<u32> = (0..=0xFFFF)
+            .map(|i: u32| ((i >> 8) << 24) | ((i & 0xFF) * 0x01_01_01))
+            .collect();
+        let expected: Vec<u32> = pixels
+            .iter()
+            .map(|&pixel| crate::convert::premultiply(pixel))
+            .collect();
+        let count = unsafe { premultiply_alpha(&mut pixels) };
+        assert_eq!(count, pixels.len());
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn neon_matches_scalar() {
+        check(PixelFormat::Xrgb8888, or_alpha);
+        check(PixelFormat::Rgba8888, rotate);
+        check(PixelFormat::Bgra8888, swap);
+        check(PixelFormat::Rgb888, rgb888);
+        check(PixelFormat::Rgb565, rgb565);
+        check(PixelFormat::Gray8, gray8);
+    }
+}
@@ -0,0 +1,394 @@
+//! x86 conversions, every function converts a leading run of whole vectors and returns how
+//! many pixels it wrote
+//!
+//! Callers guarantee `source` holds at least one pixel of its format for every `u32` in
+//! `target`.
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::PixelFormat;
+
+pub(super) fn to_argb32(format: PixelFormat, source: &[u8], target: &mut [u32]) -> usize {
+    let avx2 = is_x86_feature_detected!("avx2");
+    let ssse3 = is_x86_feature_detected!("ssse3");
+    let sse2 = is_x86_feature_detected!("sse2");
+    unsafe {
+        match format {
+            PixelFormat::Xrgb8888 if avx2 => or_alpha_avx2(source, target),
+            PixelFormat::Xrgb8888 if sse2 => or_alpha_sse2(source, target),
+            PixelFormat::Rgba8888 if avx2 => rotate_avx2(source, target),
+            PixelFormat::Rgba8888 if sse2 => rotate_sse2(source, target),
+            PixelFormat::Bgra8888 if avx2 => swap_avx2(source, target),
+            PixelFormat::Bgra8888 if ssse3 => swap_ssse3(source, target),
+            PixelFormat::Bgra8888 if sse2 => swap_sse2(source, target),
+            PixelFormat::Rgb888 if ssse3 => rgb888_ssse3(source, target),
+            PixelFormat::Rgb565 if sse2 => rgb565_sse2(source, target),
+            PixelFormat::Gray8 if sse2 => gray8_sse2(source, target),
+            // Argb8888 is a plain copy the scalar path already compiles to
+            _ => 0,
+        }
+    }
+}
+
+/// Premultiply a leading run of whole vectors of `pixels`, returning how many were written
+pub(super) fn premultiply(pixels: &mut [u32]) -> usize {
+    unsafe {
+        if is_x86_feature_detected!("avx2") {
+            premultiply_avx2(pixels)
+        } else if is_x86_feature_detected!("sse2") {
+            premultiply_sse2(pixels)
+        } else {
+            0
+        }
+    }
+}
+
+#[inline(always)]
+unsafe fn load128(source: &[u8], offset: usize) -> __m128i {
+    _mm_loadu_si128(source.as_ptr().add(offset) as *const __m128i)
+}
+
+#[inline(always)]
+unsafe fn store128(target: &mut [u32], index: usize, pixels: __m128i) {
+    _mm_storeu_si128(target.as_mut_ptr().add(index) as *mut __m128i, pixels)
+}
+
+#[inline(always)]
+unsafe fn load256(source: &[u8], offset: usize) -> __m256i {
+    _mm256_loadu_si256(source.as_ptr().add(offset) as *const __m256i)
+}
+
+#[inline(always)]
+unsafe fn store256(target: &mut [u32], index: usize, pixels: __m256i) {
+    _mm256_storeu_si256(target.as_mut_ptr().add(index) as *mut __m256i, pixels)
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn or_alpha_sse2(source: &[u8], target: &mut [u32]) -> usize {
+    let alpha = _mm_set1_epi32(0xFF00_0000u32 as i32);
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        store128(target, i, _mm_or_si128(load128(source, 4 * i), alpha));
+    }
+    count
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn or_alpha_avx2(source: &[u8], target: &mut [u32]) -> usize {
+    let alpha = _mm256_set1_epi32(0xFF00_0000u32 as i32);
+    let count = target.len() / 8 * 8;
+    for i in (0..count).step_by(8) {
+        store256(target, i, _mm256_or_si256(load256(source, 4 * i), alpha));
+    }
+    count
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn rotate_sse2(source: &[u8], target: &mut [u32]) -> usize {
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        let pixels = load128(source, 4 * i);
+        let rotated = _mm_or_si128(_mm_srli_epi32(pixels, 8), _mm_slli_epi32(pixels, 24));
+        store128(target, i, rotated);
+    }
+    count
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn rotate_avx2(source: &[u8], target: &mut [u32]) -> usize {
+    let count = target.len() / 8 * 8;
+    for i in (0..count).step_by(8) {
+        let pixels = load256(source, 4 * i);
+        let rotated = _mm256_or_si256(_mm256_srli_epi32(pixels, 8), _mm256_slli_epi32(pixels, 24));
+        store256(target, i, rotated);
+    }
+    count
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn swap_sse2(source: &[u8], target: &mut [u32]) -> usize {
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        let pixels = load128(source, 4 * i);
+        // Swap the bytes of every 16 bit half, then swap the halves
+        let swapped = _mm_or_si128(_mm_slli_epi16(pixels, 8), _mm_srli_epi16(pixels, 8));
+        let swapped =
+            _mm_shufflehi_epi16(_mm_shufflelo_epi16(swapped, 0b10_11_00_01), 0b10_11_00_01);
+        store128(target, i, swapped);
+    }
+    count
+}
+
+#[target_feature(enable = "ssse3")]
+unsafe fn swap_ssse3(source: &[u8], target: &mut [u32]) -> usize {
+    let mask = _mm_setr_epi8(3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8, 15, 14, 13, 12);
+    let count = target.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        store128(target, i, _mm_shuffle_epi8(load128(source, 4 * i), mask));
+    }
+    count
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn swap_avx2(source: &[u8], target: &mut [u32]) -> usize {
+    let mask = _mm256_setr_epi8(
+        3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8, 15, 14, 13, 12, 3, 2, 1, 0, 7, 6, 5, 4, 11, 10, 9, 8,
+        15, 14, 13, 12,
+    );
+    let count = target.len() / 8 * 8;
+    for i in (0..count).step_by(8) {
+        store256(target, i, _mm256_shuffle_epi8(load256(source, 4 * i), mask));
+    }
+    count
+}
+
+#[target_feature(enable = "ssse3")]
+unsafe fn rgb888_ssse3(source: &[u8], target: &mut [u32]) -> usize {
+    // Four pixels take 12 bytes but every load reads 16
+    let mask = _mm_setr_epi8(2, 1, 0, -128, 5, 4, 3, -128, 8, 7, 6, -128, 11, 10, 9, -128);
+    let alpha = _mm_set1_epi32(0xFF00_0000u32 as i32);
+    let mut count = 0;
+    while count + 4 <= target.len() && 3 * count + 16 <= source.len() {
+        let pixels = _mm_shuffle_epi8(load128(source, 3 * count), mask);
+        store128(target, count, _mm_or_si128(pixels, alpha));
+        count += 4;
+    }
+    count
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn rgb565_sse2(source: &[u8], target: &mut [u32]) -> usize {
+    let (green_mask, blue_mask) = (_mm_set1_epi16(0x3F), _mm_set1_epi16(0x1F));
+    let alpha = _mm_set1_epi16(0xFF00u16 as i16);
+    let count = target.len() / 8 * 8;
+    for i in (0..count).step_by(8) {
+        let pixels = load128(source, 2 * i);
+        let r = _mm_srli_epi16(pixels, 11);
+        let g = _mm_and_si128(_mm_srli_epi16(pixels, 5), green_mask);
+        let b = _mm_and_si128(pixels, blue_mask);
+        // Replicate the top bits so full intensity maps to 0xFF
+        let r = _mm_or_si128(_mm_slli_epi16(r, 3), _mm_srli_epi16(r, 2));
+        let g = _mm_or_si128(_mm_slli_epi16(g, 2), _mm_srli_epi16(g, 4));
+        let b = _mm_or_si128(_mm_slli_epi16(b, 3), _mm_srli_epi16(b, 2));
+        // Interleaving 0xGGBB and 0xFFRR halves gives 0xFFRRGGBB
+        let low = _mm_or_si128(b, _mm_slli_epi16(g, 8));
+        let high = _mm_or_si128(r, alpha);
+        store128(target, i, _mm_unpacklo_epi16(low, high));
+        store128(target, i + 4, _mm_unpackhi_epi16(low, high));
+    }
+    count
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn gray8_sse2(source: &[u8], target: &mut [u32]) -> usize {
+    let alpha = _mm_set1_epi8(-1);
+    let count = target.len() / 16 * 16;
+    for i in (0..count).step_by(16) {
+        let luminance = load128(source, i);
+        // Interleaving 0xLL_LL and 0xFF_LL halves gives 0xFFLLLLLL
+        let low = _mm_unpacklo_epi8(luminance, luminance);
+        let high = _mm_unpackhi_epi8(luminance, luminance);
+        let low_alpha = _mm_unpacklo_epi8(luminance, alpha);
+        let high_alpha = _mm_unpackhi_epi8(luminance, alpha);
+        store128(target, i, _mm_unpacklo_epi16(low, low_alpha));
+        store128(target, i + 4, _mm_unpackhi_epi16(low, low_alpha));
+        store128(target, i + 8, _mm_unpacklo_epi16(high, high_alpha));
+        store128(target, i + 12, _mm_unpackhi_epi16(high, high_alpha));
+    }
+    count
+}
+
+/// Multiply the 16 bit color channels of every pixel in `channels` by its alpha, dividing by
+/// 255 with the rounding of the scalar `premultiply`
+///
+/// `color_mask` selects the color channels and `opaque` holds 0xFF in every alpha channel, so
+/// alpha is multiplied by 255 and stays as it is.
+#[inline(always)]
+unsafe fn premultiply_sse2_channels(
+    channels: __m128i,
+    color_mask: __m128i,
+    opaque: __m128i,
+) -> __m128i {
+    let alpha = _mm_shufflehi_epi16(_mm_shufflelo_epi16(channels, 0xFF), 0xFF);
+    let factors = _mm_or_si128(_mm_and_si128(alpha, color_mask), opaque);
+    let product = _mm_add_epi16(_mm_mullo_epi16(channels, factors), _mm_set1_epi16(0x80));
+    _mm_srli_epi16(_mm_add_epi16(product, _mm_srli_epi16(product, 8)), 8)
+}
+
+/// The 256 bit version of `premultiply_sse2_channels`
+#[inline(always)]
+unsafe fn premultiply_avx2_channels(
+    channels: __m256i,
+    color_mask: __m256i,
+    opaque: __m256i,
+) -> __m256i {
+    let alpha = _mm256_shufflehi_epi16(_mm256_shufflelo_epi16(channels, 0xFF), 0xFF);
+    let factors = _mm256_or_si256(_mm256_and_si256(alpha, color_mask), opaque);
+    let product = _mm256_add_epi16(
+        _mm256_mullo_epi16(channels, factors),
+        _mm256_set1_epi16(0x80),
+    );
+    _mm256_srli_epi16(_mm256_add_epi16(product, _mm256_srli_epi16(product, 8)), 8)
+}
+
+#[target_feature(enable = "sse2")]
+unsafe fn premultiply_sse2(pixels: &mut [u32]) -> usize {
+    let zero = _mm_setzero_si128();
+    let color_mask = _mm_set1_epi64x(0x0000_FFFF_FFFF_FFFF);
+    let opaque = _mm_set1_epi64x(0x00FF_0000_0000_0000);
+    let count = pixels.len() / 4 * 4;
+    for i in (0..count).step_by(4) {
+        let packed = _mm_loadu_si128(pixels.as_ptr().add(i) as *const __m128i);
+        // Widen the channels to 16 bits, two pixels at a time
+        let low = premultiply_sse2_channels(_mm_unpacklo_epi8(packed, zero), color_mask, opaque);
+        let high = premultiply_sse2_channels(_mm_unpackhi_epi8(packed, zero), color_mask, opaque);
+        store128(pixels, i, _mm_packus_epi16(low, high));
+    }
+    count
+}
+
+#[target_feature(enable = "avx2")]
+unsafe fn premultiply_avx2(pixels: &mut [u32]) -> usize {
+    let zero = _mm256_setzero_si256();
+    let color_mask = _mm256_set1_epi64x(0x0000_FFFF_FFFF_FFFF);
+    let opaque = _mm256_set1_epi64x(0x00FF_0000_0000_0000);
+    let count = pixels.len() / 8 * 8;
+    for i in (0..count).step_by(8) {
+        let packed = _mm256_loadu_si256(pixels.as_ptr().add(i) as *const __m256i);
+        // Unpacking and packing both work within 128 bit lanes, so pixels stay in order
+        let low = premultiply_avx2_channels(_mm256_unpacklo_epi8(packed, zero), color_mask, opaque);
+        let high =
+            premultiply_avx2_channels(_mm256_unpackhi_epi8(packed, zero), color_mask, opaque);
+        store256(pixels, i, _mm256_packus_epi16(low, high));
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+    use crate::convert::scalar_to_argb32;
+
+    type Conversion = unsafe fn(&[u8], &mut [u32]) -> usize;
+
+    /// Run `convert` on every length around its vector width and compare what it wrote with
+    /// the scalar conversion, byte for byte
+    fn check(feature: bool, format: PixelFormat, convert: Conversion) {
+        if !feature {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+        for len in (0..70).chain(vec![127, 128, 129, 1000]) {
+            let bytes: Vec<u8> = (0..len * format.bytes_per_pixel() + 1)
+                .map(|_| rng.gen())
+                .collect();
+            for source in &[&bytes[..bytes.len() - 1], &bytes[1..]] {
+                let mut expected = vec![0; len];
+                scalar_to_argb32(format, source, &mut expected);
+                let mut converted = vec![0xDEAD_BEEF; len];
+                let count = unsafe { convert(source, &mut converted) };
+                assert!(
+                    count <= len,
+                    "{:?} wrote {} of {} pixels",
+                    format,
+                    count,
+                    len
+                );
+                assert!(converted[count..].iter().all(|&pixel| pixel == 0xDEAD_BEEF));
+                scalar_to_argb32(
+                    format,
+                    &source[count * format.bytes_per_pixel()..],
+                    &mut converted[count..],
+                );
+                let as_bytes = |pixels: &[u32]| -> Vec<u8> {
+                    pixels
+                        .iter()
+                        .flat_map(|pixel| pixel.to_ne_bytes())
+                        .collect()
+                };
+                assert_eq!(
+                    as_bytes(&converted),
+                    as_bytes(&expected),
+                    "{:?} of {} pixels",
+                    format,
+                    len
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn sse2_matches_scalar() {
+        let sse2 = is_x86_feature_detected!("sse2");
+        check(sse2, PixelFormat::Xrgb8888, or_alpha_sse2);
+        check(sse2, PixelFormat::Rgba8888, rotate_sse2);
+        check(sse2, PixelFormat::Bgra8888, swap_sse2);
+        check(sse2, PixelFormat::Rgb565, rgb565_sse2);
+        check(sse2, PixelFormat::Gray8, gray8_sse2);
+    }
+
+    #[test]
+    fn ssse3_matches_scalar() {
+        let ssse3 = is_x86_feature_detected!("ssse3");
+        check(ssse3, PixelFormat::Bgra8888, swap_ssse3);
+        check(ssse3, PixelFormat::Rgb888, rgb888_ssse3);
+    }
+
+    /// Run `premultiply` on every length around its vector width, including fully
+    /// transparent and opaque pixels, and compare with the scalar premultiplication
+    fn check_premultiply(feature: bool, premultiply: unsafe fn(&mut [u32]) -> usize) {
+        if !feature {
+            return;
+        }
+        let mut rng = StdRng::seed_from_u64(0x5EED);
+        for len in (0..70).chain(vec![127, 128, 129, 1000]) {
+            let pixels: Vec<u32> = (0..len)
+                .map(|i| match i % 7 {
+                    0 => rng.gen::<u32>() & 0x00FF_FFFF,
+                    1 => rng.gen::<u32>() | 0xFF00_0000,
+                    _ => rng.gen(),
+                })
+                .collect();
+            let expected: Vec<u32> = pixels
+                .iter()
+                .map(|&pixel| crate::convert::premultiply(pixel))
+                .collect();
+            let mut premultiplied = pixels.clone();
+            let count = unsafe { premultiply(&mut premultiplied) };
+            assert!(count <= len, "wrote {} of {} pixels", count, len);
+            assert_eq!(premultiplied[count..], pixels[count..]);
+            assert_eq!(premultiplied[..count], expected[..count], "{} pixels", len);
+        }
+        // Every channel and alpha pair
+        let mut pixels: Vec<u32> = (0..=0xFFFF)
+            .map(|i: u32| ((i >> 8) << 24) | ((i & 0xFF) * 0x01_01_01))
+            .collect();
+        let expected: Vec<u32> = pixels
+            .iter()
+            .map(|&pixel| crate::convert::premultiply(pixel))
+            .collect();
+        let count = unsafe { premultiply(&mut pixels) };
+        assert_eq!(count, pixels.len());
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn premultiply_matches_scalar() {
+        check_premultiply(is_x86_feature_detected!("sse2"), premultiply_sse2);
+        check_premultiply(is_x86_feature_detected!("avx2"), premultiply_avx2);
+    }
+
+    #[test]
+    fn avx2_matches_scalar() {
+        let avx2 = is_x86_feature_detected!("avx2");
+        check(avx2, PixelFormat::Xrgb8888, or_alpha_avx2);
+        check(avx2, PixelFormat::Rgba8888, rotate_avx2);
+        check(avx2, PixelFormat::Bgra8888, swap_avx2);
+    }
+}
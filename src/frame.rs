@@ -0,0 +1,83 @@
+use crate::{Backend, Canvas, DrawError, FrameStatus, Scaler};
+
+/// A frame being rendered directly into the memory the backend presents from
+///
+/// Obtained from `YuxaWindow::begin_frame`. Backends that cannot lend out their own memory
+/// are given an intermediate buffer instead, which is copied when the frame is presented.
+/// The initial contents of the pixels are unspecified, so every pixel should be written.
+pub struct FrameBuffer<'a> {
+    backend: &'a mut dyn Backend,
+    dimensions: (usize, usize),
+    // Used when the backend has no memory of its own to lend out, or when scaling
+    fallback: Option<Vec<u32>>,
+    /// Scales the frame to the given size when it is presented
+    scaling: Option<(Scaler, (usize, usize))>,
+}
+
+impl<'a> FrameBuffer<'a> {
+    pub(crate) fn new(
+        backend: &'a mut dyn Backend,
+        dimensions: (usize, usize),
+    ) -> Result<FrameBuffer<'a>, DrawError> {
+        let fallback = if backend.map_frame(dimensions)? {
+            None
+        } else {
+            Some(vec![0; dimensions.0 * dimensions.1])
+        };
+
+        Ok(FrameBuffer {
+            backend,
+            dimensions,
+            fallback,
+            scaling: None,
+        })
+    }
+
+    /// A frame of the size `scaler` takes, scaled to `target_size` when presented
+    pub(crate) fn scaled(
+        backend: &'a mut dyn Backend,
+        scaler: Scaler,
+        target_size: (usize, usize),
+    ) -> FrameBuffer<'a> {
+        let dimensions = scaler.size();
+        FrameBuffer {
+            backend,
+            dimensions,
+            fallback: Some(vec![0; dimensions.0 * dimensions.1]),
+            scaling: Some((scaler, target_size)),
+        }
+    }
+
+    /// Dimensions of the frame in pixels
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.dimensions
+    }
+
+    /// The native endian argb pixels of the frame, row by row
+    pub fn pixels(&mut self) -> &mut [u32] {
+        match self.fallback {
+            Some(ref mut pixels) => pixels,
+            None => self
+                .backend
+                .mapped_pixels()
+                .expect("Backend lost its mapped frame"),
+        }
+    }
+
+    /// A `Canvas` drawing straight into the pixels of the frame
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        let (width, height) = self.dimensions;
+        Canvas::new(self.pixels(), width, height).expect("Frame holds a whole frame of pixels")
+    }
+
+    /// Present the frame
+    pub fn present(self) -> Result<FrameStatus, DrawError> {
+        match (self.fallback, self.scaling) {
+            (Some(pixels), Some((scaler, target_size))) => {
+                scaler.present(self.backend, target_size, &pixels)
+            }
+            (Some(pixels), None) => self.backend.draw_argb32(self.dimensions, &pixels),
+            (None, _) => self.backend.present_mapped(),
+        }
+    }
+}
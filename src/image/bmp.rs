@@ -0,0 +1,409 @@
+use super::{Image, ImageError};
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// Decode an uncompressed Windows or OS/2 bitmap with 1, 4, 8, 16, 24 or 32 bits per pixel
+pub(crate) fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    let offset = read_u32(data, 10)? as usize;
+    let header_size = read_u32(data, 14)? as usize;
+    let (width, height, bits, compression) = if header_size == 12 {
+        // OS/2 BITMAPCOREHEADER
+        (
+            i64::from(read_u16(data, 18)?),
+            i64::from(read_u16(data, 20)?),
+            read_u16(data, 24)?,
+            BI_RGB,
+        )
+    } else if header_size >= 40 {
+        (
+            i64::from(read_u32(data, 18)? as i32),
+            i64::from(read_u32(data, 22)? as i32),
+            read_u16(data, 28)?,
+            read_u32(data, 30)?,
+        )
+    } else {
+        return Err(ImageError::Unsupported("BMP header version"));
+    };
+    // Rows are stored bottom up unless the height is negative
+    let top_down = height < 0;
+    if width <= 0 {
+        return Err(ImageError::Malformed("width not positive"));
+    }
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+    let masks = match (compression, bits) {
+        (BI_RGB, 16) => Some([0x7C00, 0x03E0, 0x001F, 0]),
+        (BI_RGB, 32) => Some([0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0]),
+        (BI_RGB, _) => None,
+        (BI_BITFIELDS, 16)
+        | (BI_BITFIELDS, 32)
+        | (BI_ALPHABITFIELDS, 16)
+        | (BI_ALPHABITFIELDS, 32) => {
+            // The masks follow the 40 byte header or are part of later versions of it
+            let alpha = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                read_u32(data, 66)?
+            } else {
+                0
+            };
+            Some([
+                read_u32(data, 54)?,
+                read_u32(data, 58)?,
+                read_u32(data, 62)?,
+                alpha,
+            ])
+        }
+        _ => return Err(ImageError::Unsupported("compressed BMP")),
+    };
+    let palette = match bits {
+        1 | 4 | 8 => {
+            let colors = if header_size == 12 {
+                0
+            } else {
+                read_u32(data, 46)? as usize
+            };
+            let colors = if colors == 0 || colors > 1 << bits {
+                1 << bits
+            } else {
+                colors
+            };
+            // Palette entries are blue, green, red and, except for OS/2, a padding byte
+            let entry = if header_size == 12 { 3 } else { 4 };
+            let start = 14 + header_size;
+            let table = data
+                .get(start..start + colors * entry)
+                .ok_or(ImageError::Malformed("palette cut short"))?;
+            table
+                .chunks_exact(entry)
+                .map(|color| {
+                    0xFF00_0000
+                        | u32::from(color[2]) << 16
+                        | u32::from(color[1]) << 8
+                        | u32::from(color[0])
+                })
+                .collect()
+        }
+        16 | 24 | 32 => Vec::new(),
+        _ => return Err(ImageError::Unsupported("BMP bit depth")),
+    };
+
+    // Rows are padded to a multiple of four bytes
+    let row_size = width
+        .checked_mul(usize::from(bits))
+        .map(|row_bits| row_bits.div_ceil(32) * 4)
+        .ok_or(ImageError::Malformed("image too large"))?;
+    let body = data
+        .get(offset..)
+        .filter(|body| {
+            row_size
+                .checked_mul(height)
+                .is_some_and(|size| body.len() >= size)
+        })
+        .ok_or(ImageError::Malformed("pixel data cut short"))?;
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row = &body[row * row_size..][..row_size];
+        for x in 0..width {
+            let pixel = match bits {
+                1 | 4 | 8 => {
+                    let bits = usize::from(bits);
+                    let bit = x * bits;
+                    let shift = 8 - bits - bit % 8;
+                    let index = usize::from(row[bit / 8] >> shift) & ((1 << bits) - 1);
+                    // Indices past the end of the palette are treated as black
+                    palette.get(index).cloned().unwrap_or(0xFF00_0000)
+                }
+                16 => u32::from(row[x * 2]) | u32::from(row[x * 2 + 1]) << 8,
+                24 => {
+                    let color = &row[x * 3..x * 3 + 3];
+                    0xFF00_0000
+                        | u32::from(color[2]) << 16
+                        | u32::from(color[1]) << 8
+                        | u32::from(color[0])
+                }
+                _ => read_u32(row, x * 4)?,
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    if let Some([red, green, blue, alpha]) = masks {
+        let (red, green, blue) = (Mask::new(red), Mask::new(green), Mask::new(blue));
+        let alpha = Mask::new(alpha);
+        // Bitmaps whose alpha is zero everywhere did not mean to be transparent
+        let opaque = alpha.mask == 0 || pixels.iter().all(|&pixel| alpha.extract(pixel) == 0);
+        for pixel in &mut pixels {
+            let a = if opaque { 0xFF } else { alpha.extract(*pixel) };
+            *pixel = a << 24
+                | red.extract(*pixel) << 16
+                | green.extract(*pixel) << 8
+                | blue.extract(*pixel);
+        }
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// A bitfield of a pixel holding one channel
+struct Mask {
+    mask: u32,
+    shift: u32,
+    bits: u32,
+}
+
+impl Mask {
+    fn new(mask: u32) -> Mask {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Mask {
+            mask,
+            shift,
+            bits: (mask >> shift).count_ones(),
+        }
+    }
+
+    /// The channel of `pixel` scaled to eight bits
+    fn extract(&self, pixel: u32) -> u32 {
+        if self.bits == 0 {
+            return 0;
+        }
+        let value = u64::from((pixel & self.mask) >> self.shift);
+        let max = (1u64 << self.bits) - 1;
+        ((value * 255 + max / 2) / max) as u32
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ImageError> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from(bytes[0]) | u16::from(bytes[1]) << 8),
+        None => Err(ImageError::Malformed("header cut short")),
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ImageError> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => {
+            let mut word = [0; 4];
+            word.copy_from_slice(bytes);
+            Ok(u32::from_le_bytes(word))
+        }
+        None => Err(ImageError::Malformed("header cut short")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A BITMAPINFOHEADER declaring itself `size` bytes long, without a palette
+    fn info(size: u32, width: i32, height: i32, bits: u16, compression: u32) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&size.to_le_bytes());
+        header.extend_from_slice(&width.to_le_bytes());
+        header.extend_from_slice(&height.to_le_bytes());
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&bits.to_le_bytes());
+        header.extend_from_slice(&compression.to_le_bytes());
+        header.resize(40, 0);
+        header
+    }
+
+    /// A bitmap file of `header` followed by the palette or masks in `extra` and the pixel data
+    fn bitmap(header: &[u8], extra: &[u8], body: &[u8]) -> Vec<u8> {
+        let offset = 14 + header.len() + extra.len();
+        let mut data = b"BM".to_vec();
+        data.extend_from_slice(&((offset + body.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&(offset as u32).to_le_bytes());
+        data.extend_from_slice(header);
+        data.extend_from_slice(extra);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn words(words: &[u32]) -> Vec<u8> {
+        words
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    /// Two padded rows of 24 bit pixels, blue and green stored first
+    const ROWS: [u8; 16] = [
+        0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0, 0, //
+        0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0,
+    ];
+
+    #[test]
+    fn decodes_bottom_up_rows() {
+        let image = decode(&bitmap(&info(40, 2, 2, 24, BI_RGB), &[], &ROWS)).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(
+            image.pixels(),
+            &[0xFFFF_0000, 0xFFFF_FFFF, 0xFF00_00FF, 0xFF00_FF00]
+        );
+    }
+
+    #[test]
+    fn decodes_top_down_rows() {
+        let image = decode(&bitmap(&info(40, 2, -2, 24, BI_RGB), &[], &ROWS)).unwrap();
+        assert_eq!(image.dimensions(), (2, 2));
+        assert_eq!(
+            image.pixels(),
+            &[0xFF00_00FF, 0xFF00_FF00, 0xFFFF_0000, 0xFFFF_FFFF]
+        );
+    }
+
+    #[test]
+    fn ignores_padding_byte_of_32_bit_pixels() {
+        let body = words(&[0x0010_2030, 0xAA40_5060]);
+        let image = decode(&bitmap(&info(40, 2, 1, 32, BI_RGB), &[], &body)).unwrap();
+        assert_eq!(image.pixels(), &[0xFF10_2030, 0xFF40_5060]);
+    }
+
+    #[test]
+    fn decodes_555_pixels() {
+        let body = [0x00, 0x7C, 0xE0, 0x03, 0x1F, 0x00, 0x10, 0x42];
+        let image = decode(&bitmap(&info(40, 4, 1, 16, BI_RGB), &[], &body)).unwrap();
+        assert_eq!(
+            image.pixels(),
+            &[0xFFFF_0000, 0xFF00_FF00, 0xFF00_00FF, 0xFF84_8484]
+        );
+    }
+
+    #[test]
+    fn decodes_bitfields() {
+        let masks = words(&[0xF800, 0x07E0, 0x001F]);
+        let body = [0x00, 0xF8, 0xE0, 0x07, 0x1F, 0x00, 0x10, 0x84];
+        let image = decode(&bitmap(&info(40, 4, 1, 16, BI_BITFIELDS), &masks, &body)).unwrap();
+        assert_eq!(
+            image.pixels(),
+            &[0xFFFF_0000, 0xFF00_FF00, 0xFF00_00FF, 0xFF84_8284]
+        );
+    }
+
+    #[test]
+    fn decodes_alpha_bitfields() {
+        // Channels in the reverse of their usual order
+        let masks = words(&[0x0000_FF00, 0x00FF_0000, 0xFF00_0000, 0x0000_00FF]);
+        let body = words(&[0x3020_1080, 0x0000_FF00]);
+        let expected = [0x8010_2030, 0x00FF_0000];
+        let data = bitmap(&info(40, 2, 1, 32, BI_ALPHABITFIELDS), &masks, &body);
+        assert_eq!(decode(&data).unwrap().pixels(), &expected);
+
+        // Version 3 and later headers hold the alpha mask themselves
+        let mut header = info(56, 2, 1, 32, BI_BITFIELDS);
+        header.extend_from_slice(&masks);
+        assert_eq!(
+            decode(&bitmap(&header, &[], &body)).unwrap().pixels(),
+            &expected
+        );
+
+        // A 40 byte header with plain bitfields has no alpha mask
+        let data = bitmap(&info(40, 2, 1, 32, BI_BITFIELDS), &masks, &body);
+        assert_eq!(decode(&data).unwrap().pixels(), &[0xFF10_2030, 0xFFFF_0000]);
+    }
+
+    #[test]
+    fn treats_zero_alpha_everywhere_as_opaque() {
+        let masks = words(&[0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000]);
+        let body = words(&[0x0010_2030]);
+        let data = bitmap(&info(40, 1, 1, 32, BI_ALPHABITFIELDS), &masks, &body);
+        assert_eq!(decode(&data).unwrap().pixels(), &[0xFF10_2030]);
+    }
+
+    #[test]
+    fn decodes_palettes() {
+        let palette = words(&[0x00AA_BBCC, 0x0010_2030]);
+        // Nine one bit pixels spill into a second byte
+        let body = [0b1010_0000, 0b1000_0000, 0, 0];
+        let data = bitmap(&info(40, 9, 1, 1, BI_RGB), &palette, &body);
+        let (a, b) = (0xFFAA_BBCC, 0xFF10_2030);
+        assert_eq!(
+            decode(&data).unwrap().pixels(),
+            &[b, a, b, a, a, a, a, a, b]
+        );
+
+        // A shorter palette than the bit depth allows, with an index past its end
+        let mut header = info(40, 3, 1, 4, BI_RGB);
+        header[32..36].copy_from_slice(&2u32.to_le_bytes());
+        let data = bitmap(&header, &palette, &[0x10, 0x30, 0, 0]);
+        assert_eq!(decode(&data).unwrap().pixels(), &[b, a, 0xFF00_0000]);
+    }
+
+    #[test]
+    fn decodes_os2_bitmaps() {
+        let mut header = words(&[12]);
+        for &field in &[2u16, 1, 1, 8] {
+            header.extend_from_slice(&field.to_le_bytes());
+        }
+        // Three byte palette entries
+        let palette: Vec<u8> = (0..=255).flat_map(|index| vec![index, 0, !index]).collect();
+        let data = bitmap(&header, &palette, &[0, 200, 0, 0]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.pixels(), &[0xFFFF_0000, 0xFF37_00C8]);
+    }
+
+    #[test]
+    fn rejects_malformed_bitmaps() {
+        let error = |data: &[u8]| decode(data).unwrap_err();
+        let cut_short = |length| {
+            matches!(
+                error(&bitmap(&info(40, 2, 2, 24, BI_RGB), &[], &ROWS)[..length]),
+                ImageError::Malformed("header cut short")
+            )
+        };
+        assert!(cut_short(12));
+        assert!(cut_short(30));
+
+        assert!(matches!(
+            error(&bitmap(&info(40, 0, 2, 24, BI_RGB), &[], &ROWS)),
+            ImageError::Malformed("width not positive")
+        ));
+        assert!(matches!(
+            error(&bitmap(&info(40, -2, 2, 24, BI_RGB), &[], &ROWS)),
+            ImageError::Malformed("width not positive")
+        ));
+        assert!(matches!(
+            error(&bitmap(&info(40, 2, 1, 8, BI_RGB), &[0; 8], &[0; 4])),
+            ImageError::Malformed("palette cut short")
+        ));
+        assert!(matches!(
+            error(&bitmap(&info(40, 2, 2, 24, BI_RGB), &[], &ROWS[..12])),
+            ImageError::Malformed("pixel data cut short")
+        ));
+        // Masks cut short
+        assert!(matches!(
+            error(&bitmap(&info(40, 1, 1, 32, BI_BITFIELDS), &[], &[])),
+            ImageError::Malformed("header cut short")
+        ));
+    }
+
+    #[test]
+    fn rejects_unsupported_bitmaps() {
+        let error = |data: &[u8]| decode(data).unwrap_err();
+        assert!(matches!(
+            error(&bitmap(&info(20, 2, 2, 24, BI_RGB), &[], &ROWS)),
+            ImageError::Unsupported("BMP header version")
+        ));
+        // Run length encoded and bitfields at a depth they do not apply to
+        assert!(matches!(
+            error(&bitmap(&info(40, 2, 2, 8, 1), &[], &ROWS)),
+            ImageError::Unsupported("compressed BMP")
+        ));
+        assert!(matches!(
+            error(&bitmap(&info(40, 2, 2, 24, BI_BITFIELDS), &[0; 12], &ROWS)),
+            ImageError::Unsupported("compressed BMP")
+        ));
+        assert!(matches!(
+            error(&bitmap(&info(40, 2, 2, 2, BI_RGB), &[0; 16], &ROWS)),
+            ImageError::Unsupported("BMP bit depth")
+        ));
+    }
+}
@@ -0,0 +1,474 @@
+//! Decoding, encoding and drawing images onto a `Canvas`
+//!
+//! PPM, PGM and BMP files are always decoded. PNG files are decoded with the `png` feature
+//! and QOI files with the `qoi` feature. Images are saved as PPM, or PNG with the `png`
+//! feature.
+
+mod bmp;
+#[cfg(feature = "png")]
+mod png;
+mod ppm;
+#[cfg(feature = "qoi")]
+mod qoi;
+
+use std::io::Write;
+use std::path::Path;
+
+use crate::blend::load;
+use crate::{AlphaMode, Canvas, DrawError, Rect};
+
+/// Error returned when an image could not be loaded or saved
+#[derive(Debug)]
+pub enum ImageError {
+    /// The image file could not be read or written
+    Io(std::io::Error),
+    /// The data is not in any format that can be decoded, the feature for its format may be
+    /// disabled
+    UnknownFormat,
+    /// The image uses a part of its format that is not supported
+    Unsupported(&'static str),
+    /// The image data is invalid or cut short
+    Malformed(&'static str),
+    /// A decoder of another crate failed
+    Decoder(Box<dyn std::error::Error + Send + Sync>),
+    /// An encoder of another crate failed
+    Encoder(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl std::fmt::Display for ImageError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            ImageError::Io(ref err) => write!(formatter, "Failed to access the image: {}", err),
+            ImageError::UnknownFormat => formatter.write_str("Unknown image format"),
+            ImageError::Unsupported(what) => write!(formatter, "Unsupported image: {}", what),
+            ImageError::Malformed(what) => write!(formatter, "Malformed image: {}", what),
+            ImageError::Decoder(ref err) => {
+                write!(formatter, "Failed to decode the image: {}", err)
+            }
+            ImageError::Encoder(ref err) => {
+                write!(formatter, "Failed to encode the image: {}", err)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match *self {
+            ImageError::Io(ref err) => Some(err),
+            ImageError::Decoder(ref err) | ImageError::Encoder(ref err) => Some(&**err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ImageError {
+    fn from(err: std::io::Error) -> Self {
+        ImageError::Io(err)
+    }
+}
+
+/// File formats images can be saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Binary PPM, which has no alpha channel so translucent pixels are saved as if drawn
+    /// over black
+    Ppm,
+    /// PNG with an alpha channel if any pixel is translucent
+    #[cfg(feature = "png")]
+    Png,
+}
+
+impl ImageFormat {
+    /// File name extension of the format, without a dot
+    pub fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Ppm => "ppm",
+            #[cfg(feature = "png")]
+            ImageFormat::Png => "png",
+        }
+    }
+}
+
+/// Pixels decoded from an image, as native endian argb `u32` with straight alpha
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<u32>,
+}
+
+impl Image {
+    /// An image of `width` by `height` straight alpha argb `pixels`, row by row
+    pub fn new(width: usize, height: usize, pixels: Vec<u32>) -> Result<Image, DrawError> {
+        // Sizes too large to address expect more pixels than any vector holds
+        crate::backend::check_len(crate::backend::frame_len((width, height), 1), pixels.len())?;
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// Read and decode the image file at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Image, ImageError> {
+        Image::decode(&std::fs::read(path)?)
+    }
+
+    /// Decode an image file, detecting its format from its contents
+    pub fn decode(data: &[u8]) -> Result<Image, ImageError> {
+        match data {
+            [b'P', b'1'..=b'6', ..] => ppm::decode(data),
+            [b'B', b'M', ..] => bmp::decode(data),
+            #[cfg(feature = "png")]
+            [0x89, b'P', b'N', b'G', ..] => png::decode(data),
+            #[cfg(feature = "qoi")]
+            [b'q', b'o', b'i', b'f', ..] => qoi::decode(data),
+            _ => Err(ImageError::UnknownFormat),
+        }
+    }
+
+    /// Encode the image in `format` and write it to a file at `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P, format: ImageFormat) -> Result<(), ImageError> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.encode(format, &mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Encode the image in `format` into `writer`
+    pub fn encode<W: Write>(&self, format: ImageFormat, writer: W) -> Result<(), ImageError> {
+        match format {
+            ImageFormat::Ppm => ppm::encode(self, writer),
+            #[cfg(feature = "png")]
+            ImageFormat::Png => png::encode(self, writer),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u32> {
+        self.pixels
+    }
+
+    /// A `Canvas` drawing onto the image, keeping its alpha straight
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        let mut canvas = Canvas::new(&mut self.pixels, self.width, self.height)
+            .expect("Image holds a whole frame of pixels");
+        canvas.set_alpha_mode(AlphaMode::Straight);
+        canvas
+    }
+
+    /// Premultiplied pixel at `x`, `y`, which must lie within the image
+    fn premultiplied(&self, x: usize, y: usize) -> u32 {
+        load(self.pixels[y * self.width + x], AlphaMode::Straight)
+    }
+}
+
+/// How an image is sampled when it is drawn at a different size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// The closest pixel is used, keeping pixel art crisp
+    Nearest,
+    /// The four closest pixels are interpolated, for smooth scaling
+    Bilinear,
+}
+
+/// Where and how an image is drawn by `Canvas::draw_image_with`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blit {
+    /// Top left corner of the image on the canvas
+    pub position: (i32, i32),
+    /// Part of the image to draw, the whole image when `None`
+    pub source: Option<Rect>,
+    /// Size to draw the image at, the size of the source when `None`
+    pub size: Option<(usize, usize)>,
+    pub filter: Filter,
+    /// Mirror the image horizontally
+    pub flip_x: bool,
+    /// Mirror the image vertically
+    pub flip_y: bool,
+}
+
+impl Blit {
+    /// Draw the whole image unscaled with its top left corner at `x`, `y`
+    pub fn at(x: i32, y: i32) -> Blit {
+        Blit {
+            position: (x, y),
+            source: None,
+            size: None,
+            filter: Filter::Nearest,
+            flip_x: false,
+            flip_y: false,
+        }
+    }
+}
+
+impl<'a> Canvas<'a> {
+    /// Draw `image` unscaled with its top left corner at `position`
+    pub fn draw_image(&mut self, image: &Image, position: (i32, i32)) {
+        self.draw_image_with(image, &Blit::at(position.0, position.1));
+    }
+
+    /// Draw `image` as described by `blit`
+    ///
+    /// The image is composited like any other shape, so translucent pixels are blended with
+    /// the canvas.
+    pub fn draw_image_with(&mut self, image: &Image, blit: &Blit) {
+        let source = match blit.source {
+            Some(source) => match source.clip(image.dimensions()) {
+                Some(source) => source,
+                None => return,
+            },
+            None => Rect::new(0, 0, image.width, image.height),
+        };
+        if source.width == 0 || source.height == 0 {
+            return;
+        }
+        let (width, height) = blit.size.unwrap_or((source.width, source.height));
+        let (x, y) = blit.position;
+        let (left, right) = self.clip_x(x, x.saturating_add(width as i32));
+        let (top, bottom) = self.clip_y(y, y.saturating_add(height as i32));
+        // Source pixels per canvas pixel
+        let step_x = source.width as f32 / width as f32;
+        let step_y = source.height as f32 / height as f32;
+
+        for target_y in top..bottom {
+            let row = (target_y as i32 - y) as usize;
+            let row = if blit.flip_y { height - 1 - row } else { row };
+            for target_x in left..right {
+                let column = (target_x as i32 - x) as usize;
+                let column = if blit.flip_x {
+                    width - 1 - column
+                } else {
+                    column
+                };
+                // Sample at the center of the canvas pixel
+                let u = (column as f32 + 0.5) * step_x;
+                let v = (row as f32 + 0.5) * step_y;
+                let color = match blit.filter {
+                    Filter::Nearest => {
+                        let u = (u as usize).min(source.width - 1);
+                        let v = (v as usize).min(source.height - 1);
+                        image.premultiplied(source.x + u, source.y + v)
+                    }
+                    Filter::Bilinear => bilinear(image, source, u - 0.5, v - 0.5),
+                };
+                self.plot(target_x as i32, target_y as i32, color);
+            }
+        }
+    }
+}
+
+/// Interpolate the premultiplied pixels of `source` around `u`, `v`, clamping to its edges
+fn bilinear(image: &Image, source: Rect, u: f32, v: f32) -> u32 {
+    let u = u.max(0.).min((source.width - 1) as f32);
+    let v = v.max(0.).min((source.height - 1) as f32);
+    let (x0, y0) = (u as usize, v as usize);
+    let x1 = (x0 + 1).min(source.width - 1);
+    let y1 = (y0 + 1).min(source.height - 1);
+    let (fx, fy) = (u - x0 as f32, v - y0 as f32);
+    let pixel = |x: usize, y: usize| image.premultiplied(source.x + x, source.y + y);
+    let corners = [
+        (pixel(x0, y0), (1. - fx) * (1. - fy)),
+        (pixel(x1, y0), fx * (1. - fy)),
+        (pixel(x0, y1), (1. - fx) * fy),
+        (pixel(x1, y1), fx * fy),
+    ];
+    let mut result = 0;
+    for &shift in &[24, 16, 8, 0] {
+        let channel: f32 = corners
+            .iter()
+            .map(|&(pixel, weight)| ((pixel >> shift) & 0xFF) as f32 * weight)
+            .sum();
+        result |= ((channel + 0.5) as u32).min(0xFF) << shift;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: u32 = 0xFF11_2233;
+    const B: u32 = 0xFF44_5566;
+    const C: u32 = 0xFF77_8899;
+    const D: u32 = 0xFFAA_BBCC;
+    const E: u32 = 0xFF00_0000;
+
+    /// The 2 by 2 image of `A` and `B` above `C` and `D`
+    fn image() -> Image {
+        Image::new(2, 2, vec![A, B, C, D]).unwrap()
+    }
+
+    /// Pixels of a `width` by `height` canvas of `E` after drawing `image` with `blit`
+    fn draw(image: &Image, blit: &Blit, (width, height): (usize, usize)) -> Vec<u32> {
+        let mut pixels = vec![E; width * height];
+        Canvas::new(&mut pixels, width, height)
+            .unwrap()
+            .draw_image_with(image, blit);
+        pixels
+    }
+
+    #[test]
+    fn detects_formats() {
+        let image = Image::decode(b"P3 1 1 255 1 2 3").unwrap();
+        assert_eq!(image.pixels(), &[0xFF01_0203]);
+        assert!(matches!(
+            Image::decode(b"BM"),
+            Err(ImageError::Malformed("header cut short"))
+        ));
+        assert!(matches!(
+            Image::decode(b"GIF89a"),
+            Err(ImageError::UnknownFormat)
+        ));
+        assert!(matches!(Image::decode(b""), Err(ImageError::UnknownFormat)));
+        #[cfg(not(feature = "qoi"))]
+        assert!(matches!(
+            Image::decode(b"qoif\0\0\0\x01\0\0\0\x01\x04\0\xC0"),
+            Err(ImageError::UnknownFormat)
+        ));
+        #[cfg(not(feature = "png"))]
+        assert!(matches!(
+            Image::decode(b"\x89PNG\r\n\x1a\n"),
+            Err(ImageError::UnknownFormat)
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_pixel_counts() {
+        assert!(matches!(
+            Image::new(2, 2, vec![A; 3]),
+            Err(DrawError::BufferSizeMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+        assert!(Image::new(usize::MAX, 2, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn draws_images() {
+        let pixels = draw(&image(), &Blit::at(1, 1), (4, 3));
+        assert_eq!(pixels, [E, E, E, E, E, A, B, E, E, C, D, E]);
+    }
+
+    #[test]
+    fn clips_images_to_the_canvas() {
+        assert_eq!(draw(&image(), &Blit::at(-1, -1), (2, 2)), [D, E, E, E]);
+        assert_eq!(draw(&image(), &Blit::at(1, 1), (2, 2)), [E, E, E, A]);
+        assert_eq!(draw(&image(), &Blit::at(2, 0), (2, 2)), [E; 4]);
+    }
+
+    #[test]
+    fn flips_images() {
+        let flip_x = Blit {
+            flip_x: true,
+            ..Blit::at(0, 0)
+        };
+        let flip_y = Blit {
+            flip_y: true,
+            ..Blit::at(0, 0)
+        };
+        let both = Blit {
+            flip_x: true,
+            ..flip_y
+        };
+        assert_eq!(draw(&image(), &flip_x, (2, 2)), [B, A, D, C]);
+        assert_eq!(draw(&image(), &flip_y, (2, 2)), [C, D, A, B]);
+        assert_eq!(draw(&image(), &both, (2, 2)), [D, C, B, A]);
+
+        // Mirroring the whole drawn image, not each scaled pixel
+        let scaled = Blit {
+            source: Some(Rect::new(0, 0, 2, 1)),
+            size: Some((4, 1)),
+            ..flip_x
+        };
+        assert_eq!(draw(&image(), &scaled, (4, 1)), [B, B, A, A]);
+        // Clipping keeps the mirrored part of the image that is on the canvas
+        let clipped = Blit {
+            position: (-1, 0),
+            ..flip_x
+        };
+        assert_eq!(draw(&image(), &clipped, (2, 2)), [A, E, C, E]);
+    }
+
+    #[test]
+    fn draws_source_rects() {
+        let blit = |source| Blit {
+            source: Some(source),
+            ..Blit::at(0, 0)
+        };
+        assert_eq!(
+            draw(&image(), &blit(Rect::new(1, 0, 1, 2)), (2, 2)),
+            [B, E, D, E]
+        );
+        // Sources reaching past the image are clipped to it
+        assert_eq!(
+            draw(&image(), &blit(Rect::new(1, 1, 5, 5)), (2, 2)),
+            [D, E, E, E]
+        );
+        assert_eq!(draw(&image(), &blit(Rect::new(2, 0, 1, 1)), (2, 2)), [E; 4]);
+        assert_eq!(draw(&image(), &blit(Rect::new(0, 0, 0, 2)), (2, 2)), [E; 4]);
+    }
+
+    #[test]
+    fn scales_to_the_nearest_pixel() {
+        let blit = |size| Blit {
+            size: Some(size),
+            ..Blit::at(0, 0)
+        };
+        assert_eq!(
+            draw(&image(), &blit((4, 2)), (4, 2)),
+            [A, A, B, B, C, C, D, D]
+        );
+        // Sampled at the center of the canvas pixel
+        assert_eq!(draw(&image(), &blit((1, 1)), (1, 1)), [D]);
+        assert_eq!(draw(&image(), &blit((0, 2)), (2, 2)), [E; 4]);
+    }
+
+    #[test]
+    fn interpolates_between_pixels() {
+        let ramp = Image::new(2, 1, vec![0xFF00_0000, 0xFFFF_FFFF]).unwrap();
+        let blit = |size| Blit {
+            size: Some(size),
+            filter: Filter::Bilinear,
+            ..Blit::at(0, 0)
+        };
+        // Samples beyond the outer pixel centers are clamped to the edges
+        assert_eq!(
+            draw(&ramp, &blit((4, 1)), (4, 1)),
+            [0xFF00_0000, 0xFF40_4040, 0xFFBF_BFBF, 0xFFFF_FFFF]
+        );
+        assert_eq!(
+            draw(&ramp, &blit((4, 2)), (4, 2))[4..],
+            [0xFF00_0000, 0xFF40_4040, 0xFFBF_BFBF, 0xFFFF_FFFF]
+        );
+
+        // Premultiplied, so transparent pixels do not darken their neighbours
+        let fade = Image::new(2, 1, vec![0x00FF_FFFF, 0xFFFF_FFFF]).unwrap();
+        let mut pixels = vec![0; 4];
+        let mut canvas = Canvas::new(&mut pixels, 4, 1).unwrap();
+        canvas.set_composite_op(crate::CompositeOp::Source);
+        canvas.draw_image_with(&fade, &blit((4, 1)));
+        assert_eq!(pixels, [0x0000_0000, 0x4040_4040, 0xBFBF_BFBF, 0xFFFF_FFFF]);
+    }
+}
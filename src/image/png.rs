@@ -0,0 +1,195 @@
+use std::io::Write;
+
+use ::png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+
+use super::{Image, ImageError};
+
+/// Decode the first frame of a PNG file
+pub(crate) fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    let mut decoder = Decoder::new(data);
+    // Expand palettes and low bit depths and reduce 16 bit samples, leaving 8 bit channels
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|err| ImageError::Decoder(Box::new(err)))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|err| ImageError::Decoder(Box::new(err)))?;
+    if info.bit_depth != BitDepth::Eight {
+        return Err(ImageError::Unsupported("PNG bit depth"));
+    }
+    let channels = match info.color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::Rgb => 3,
+        ColorType::Rgba => 4,
+        ColorType::Indexed => return Err(ImageError::Unsupported("PNG palette")),
+    };
+    let (width, height) = (info.width as usize, info.height as usize);
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in buffer.chunks(info.line_size).take(height) {
+        pixels.extend(row[..width * channels].chunks_exact(channels).map(|pixel| {
+            let (r, g, b, a) = match *pixel {
+                [gray] => (gray, gray, gray, 0xFF),
+                [gray, a] => (gray, gray, gray, a),
+                [r, g, b] => (r, g, b, 0xFF),
+                [r, g, b, a] => (r, g, b, a),
+                _ => unreachable!(),
+            };
+            u32::from(a) << 24 | u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b)
+        }));
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Encode `image` as an 8 bit PNG file, without an alpha channel if every pixel is opaque
+pub(crate) fn encode<W: Write>(image: &Image, writer: W) -> Result<(), ImageError> {
+    let opaque = image.pixels.iter().all(|&pixel| pixel >> 24 == 0xFF);
+    let mut encoder = Encoder::new(writer, image.width as u32, image.height as u32);
+    encoder.set_color(if opaque {
+        ColorType::Rgb
+    } else {
+        ColorType::Rgba
+    });
+    encoder.set_depth(BitDepth::Eight);
+    let mut data = Vec::with_capacity(image.pixels.len() * if opaque { 3 } else { 4 });
+    for &pixel in &image.pixels {
+        let [a, r, g, b] = pixel.to_be_bytes();
+        data.extend_from_slice(&[r, g, b]);
+        if !opaque {
+            data.push(a);
+        }
+    }
+    let encoding = |err| ImageError::Encoder(Box::new(err));
+    let mut writer = encoder.write_header().map_err(encoding)?;
+    writer.write_image_data(&data).map_err(encoding)?;
+    writer.finish().map_err(encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A PNG file of `data` in `color` at `depth`, with an optional palette and its alpha
+    fn file(
+        (width, height): (u32, u32),
+        color: ColorType,
+        depth: BitDepth,
+        palette: Option<(&[u8], &[u8])>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut encoder = Encoder::new(&mut file, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        if let Some((palette, trns)) = palette {
+            encoder.set_palette(palette);
+            encoder.set_trns(trns);
+        }
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        file
+    }
+
+    /// Color type field of the header chunk of an encoded file
+    fn color_type(file: &[u8]) -> u8 {
+        file[25]
+    }
+
+    #[test]
+    fn round_trips_opaque_images_without_alpha() {
+        let image = Image::new(2, 1, vec![0xFF11_2233, 0xFFFF_FFFF]).unwrap();
+        let mut file = Vec::new();
+        encode(&image, &mut file).unwrap();
+        assert_eq!(color_type(&file), ColorType::Rgb as u8);
+        assert_eq!(decode(&file).unwrap(), image);
+    }
+
+    #[test]
+    fn round_trips_translucent_images() {
+        let image = Image::new(1, 2, vec![0xFF11_2233, 0x8040_80C0]).unwrap();
+        let mut file = Vec::new();
+        encode(&image, &mut file).unwrap();
+        assert_eq!(color_type(&file), ColorType::Rgba as u8);
+        assert_eq!(decode(&file).unwrap(), image);
+    }
+
+    #[test]
+    fn decodes_grayscale() {
+        let gray = file(
+            (2, 1),
+            ColorType::Grayscale,
+            BitDepth::Eight,
+            None,
+            &[0, 0x80],
+        );
+        assert_eq!(decode(&gray).unwrap().pixels(), &[0xFF00_0000, 0xFF80_8080]);
+
+        let gray_alpha = [0x80, 0x40];
+        let gray_alpha = file(
+            (1, 1),
+            ColorType::GrayscaleAlpha,
+            BitDepth::Eight,
+            None,
+            &gray_alpha,
+        );
+        assert_eq!(decode(&gray_alpha).unwrap().pixels(), &[0x4080_8080]);
+
+        // Two bit samples are scaled to eight bits
+        let low = file(
+            (3, 1),
+            ColorType::Grayscale,
+            BitDepth::Two,
+            None,
+            &[0b1101_0000],
+        );
+        assert_eq!(
+            decode(&low).unwrap().pixels(),
+            &[0xFFFF_FFFF, 0xFF55_5555, 0xFF00_0000]
+        );
+    }
+
+    #[test]
+    fn strips_16_bit_samples() {
+        let data = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC];
+        let rgb = file((1, 1), ColorType::Rgb, BitDepth::Sixteen, None, &data);
+        assert_eq!(decode(&rgb).unwrap().pixels(), &[0xFF12_569A]);
+    }
+
+    #[test]
+    fn expands_palettes() {
+        let palette = [0x10, 0x20, 0x30, 0x40, 0x50, 0x60];
+        // Only the first entry has an alpha value, the others are opaque
+        let palette = Some((&palette[..], &[0x80][..]));
+        let indexed = file(
+            (3, 1),
+            ColorType::Indexed,
+            BitDepth::One,
+            palette,
+            &[0b0100_0000],
+        );
+        assert_eq!(
+            decode(&indexed).unwrap().pixels(),
+            &[0x8010_2030, 0xFF40_5060, 0x8010_2030]
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let mut corrupt = b"\x89PNG\r\n\x1a\n".to_vec();
+        corrupt.extend_from_slice(b"not a chunk");
+        assert!(matches!(decode(&corrupt), Err(ImageError::Decoder(_))));
+
+        let image = Image::new(2, 1, vec![0xFF11_2233, 0xFFFF_FFFF]).unwrap();
+        let mut file = Vec::new();
+        encode(&image, &mut file).unwrap();
+        file.truncate(file.len() - 20);
+        assert!(matches!(decode(&file), Err(ImageError::Decoder(_))));
+    }
+}
@@ -0,0 +1,227 @@
+use std::io::Write;
+
+use super::{Image, ImageError};
+use crate::convert::premultiply;
+
+/// Decode a plain or binary PGM (`P2`, `P5`) or PPM (`P3`, `P6`) file
+pub(crate) fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    let mut header = Header { data, position: 2 };
+    let (binary, channels) = match &data[..2] {
+        b"P2" => (false, 1),
+        b"P3" => (false, 3),
+        b"P5" => (true, 1),
+        b"P6" => (true, 3),
+        _ => return Err(ImageError::Unsupported("PBM bitmaps")),
+    };
+    let width = header.number()? as usize;
+    let height = header.number()? as usize;
+    let max = header.number()?;
+    if max == 0 || max > 0xFFFF {
+        return Err(ImageError::Malformed("maximum value out of range"));
+    }
+    let count = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or(ImageError::Malformed("image too large"))?;
+
+    let mut samples = Vec::with_capacity(count.min(data.len()));
+    if binary {
+        // A single whitespace character separates the header from the samples
+        let start = header.position + 1;
+        let bytes = if max < 0x100 { 1 } else { 2 };
+        let body = data
+            .get(start..)
+            .filter(|body| body.len() / bytes >= count)
+            .ok_or(ImageError::Malformed("pixel data cut short"))?;
+        if bytes == 1 {
+            samples.extend(body[..count].iter().map(|&sample| u32::from(sample)));
+        } else {
+            samples.extend(
+                body.chunks_exact(2)
+                    .take(count)
+                    .map(|pair| u32::from(pair[0]) << 8 | u32::from(pair[1])),
+            );
+        }
+    } else {
+        for _ in 0..count {
+            samples.push(header.number()?);
+        }
+    }
+    if samples.iter().any(|&sample| sample > max) {
+        return Err(ImageError::Malformed("sample above the maximum value"));
+    }
+
+    let scale = |sample: u32| (sample * 255 + max / 2) / max;
+    let pixels = samples
+        .chunks_exact(channels)
+        .map(|pixel| {
+            let (r, g, b) = if channels == 1 {
+                (pixel[0], pixel[0], pixel[0])
+            } else {
+                (pixel[0], pixel[1], pixel[2])
+            };
+            0xFF00_0000 | scale(r) << 16 | scale(g) << 8 | scale(b)
+        })
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Encode `image` as a binary PPM file, drawn over black
+pub(crate) fn encode<W: Write>(image: &Image, mut writer: W) -> Result<(), ImageError> {
+    write!(writer, "P6\n{} {}\n255\n", image.width, image.height)?;
+    let mut row = Vec::with_capacity(image.width * 3);
+    for pixels in image.pixels.chunks(image.width.max(1)) {
+        row.clear();
+        for &pixel in pixels {
+            let [_, r, g, b] = premultiply(pixel).to_be_bytes();
+            row.extend_from_slice(&[r, g, b]);
+        }
+        writer.write_all(&row)?;
+    }
+    Ok(())
+}
+
+/// Reads the whitespace separated decimal numbers of the header and plain samples
+struct Header<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Header<'a> {
+    fn number(&mut self) -> Result<u32, ImageError> {
+        // Skip whitespace and comments running to the end of the line
+        loop {
+            match self.data.get(self.position) {
+                Some(byte) if byte.is_ascii_whitespace() => self.position += 1,
+                Some(b'#') => {
+                    while self
+                        .data
+                        .get(self.position)
+                        .is_some_and(|&byte| byte != b'\n')
+                    {
+                        self.position += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let start = self.position;
+        let mut value: u32 = 0;
+        while let Some(&digit) = self
+            .data
+            .get(self.position)
+            .filter(|byte| byte.is_ascii_digit())
+        {
+            value = value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add(u32::from(digit - b'0')))
+                .ok_or(ImageError::Malformed("number too large"))?;
+            self.position += 1;
+        }
+        if self.position == start {
+            Err(ImageError::Malformed("expected a number"))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_binary_pixmaps() {
+        let mut data = b"P6\n# made by hand\n2 1 # two pixels\n255\n".to_vec();
+        data.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.pixels(), &[0xFF01_0203, 0xFF04_0506]);
+    }
+
+    #[test]
+    fn decodes_plain_pixmaps() {
+        let image = decode(b"P3 2 1 255\n255 0 0  0 128 255\n").unwrap();
+        assert_eq!(image.pixels(), &[0xFFFF_0000, 0xFF00_80FF]);
+    }
+
+    #[test]
+    fn scales_samples_to_the_maximum_value() {
+        let image = decode(b"P2 3 1 15 0 7 15").unwrap();
+        assert_eq!(image.pixels(), &[0xFF00_0000, 0xFF77_7777, 0xFFFF_FFFF]);
+    }
+
+    #[test]
+    fn decodes_two_byte_samples() {
+        // Samples above 255 are stored big endian in two bytes
+        let mut data = b"P5 3 1 1000\n".to_vec();
+        data.extend_from_slice(&[0x01, 0xF4, 0x03, 0xE8, 0x00, 0x00]);
+        let image = decode(&data).unwrap();
+        assert_eq!(image.pixels(), &[0xFF80_8080, 0xFFFF_FFFF, 0xFF00_0000]);
+
+        let mut data = b"P5 1 1 65535\n".to_vec();
+        data.extend_from_slice(&[0x80, 0x00]);
+        assert_eq!(decode(&data).unwrap().pixels(), &[0xFF80_8080]);
+    }
+
+    #[test]
+    fn round_trips_through_encode() {
+        let pixels = vec![0xFF11_2233, 0xFF00_0000, 0xFFFF_FFFF, 0x80FF_0000];
+        let image = Image::new(2, 2, pixels).unwrap();
+        let mut data = Vec::new();
+        encode(&image, &mut data).unwrap();
+        assert!(data.starts_with(b"P6\n2 2\n255\n"));
+        // Translucent pixels are drawn over black
+        assert_eq!(
+            decode(&data).unwrap().pixels(),
+            &[0xFF11_2233, 0xFF00_0000, 0xFFFF_FFFF, 0xFF80_0000]
+        );
+    }
+
+    #[test]
+    fn rejects_bitmaps() {
+        assert!(matches!(
+            decode(b"P1 1 1 0").unwrap_err(),
+            ImageError::Unsupported("PBM bitmaps")
+        ));
+        assert!(matches!(
+            decode(b"P4 1 1 \x00").unwrap_err(),
+            ImageError::Unsupported("PBM bitmaps")
+        ));
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let malformed = |data: &[u8], what| match decode(data) {
+            Err(ImageError::Malformed(error)) => error == what,
+            _ => false,
+        };
+        assert!(malformed(b"P2 1 1 0 0", "maximum value out of range"));
+        assert!(malformed(b"P2 1 1 65536 0", "maximum value out of range"));
+        assert!(malformed(
+            b"P6 4294967295 4294967295 255\n",
+            "image too large"
+        ));
+        assert!(malformed(
+            b"P6 2 1 255\n\x01\x02\x03\x04\x05",
+            "pixel data cut short"
+        ));
+        assert!(malformed(
+            b"P5 2 1 1000\n\x00\x01\x00",
+            "pixel data cut short"
+        ));
+        assert!(malformed(b"P2 1 1 15 16", "sample above the maximum value"));
+        assert!(malformed(
+            b"P5 1 1 100\n\x65",
+            "sample above the maximum value"
+        ));
+        assert!(malformed(b"P2 1 x", "expected a number"));
+        assert!(malformed(b"P3 1 1 255 1 2", "expected a number"));
+        assert!(malformed(b"P6", "expected a number"));
+        assert!(malformed(b"P2 4294967296 1 255", "number too large"));
+    }
+}
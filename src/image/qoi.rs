@@ -0,0 +1,176 @@
+use super::{Image, ImageError};
+
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_OP_RUN: u8 = 0xC0;
+const QOI_OP_RGB: u8 = 0xFE;
+const QOI_OP_RGBA: u8 = 0xFF;
+
+/// Decode a file in the Quite OK Image format
+pub(crate) fn decode(data: &[u8]) -> Result<Image, ImageError> {
+    let header = data
+        .get(..14)
+        .ok_or(ImageError::Malformed("header cut short"))?;
+    let dimension = |offset: usize| {
+        let mut word = [0; 4];
+        word.copy_from_slice(&header[offset..offset + 4]);
+        u32::from_be_bytes(word) as usize
+    };
+    let (width, height) = (dimension(4), dimension(8));
+    let count = width
+        .checked_mul(height)
+        .ok_or(ImageError::Malformed("image too large"))?;
+    // Every chunk but a run encodes a single pixel in at least one byte
+    if count / 62 > data.len() {
+        return Err(ImageError::Malformed("pixel data cut short"));
+    }
+
+    let mut pixels = Vec::with_capacity(count);
+    let mut index = [[0u8; 4]; 64];
+    let mut pixel = [0, 0, 0, 0xFF];
+    let mut bytes = data[14..].iter().cloned();
+    let mut next = || {
+        bytes
+            .next()
+            .ok_or(ImageError::Malformed("pixel data cut short"))
+    };
+    while pixels.len() < count {
+        let byte = next()?;
+        let mut run = 1;
+        match byte {
+            QOI_OP_RGB => {
+                pixel[0] = next()?;
+                pixel[1] = next()?;
+                pixel[2] = next()?;
+            }
+            QOI_OP_RGBA => {
+                pixel[0] = next()?;
+                pixel[1] = next()?;
+                pixel[2] = next()?;
+                pixel[3] = next()?;
+            }
+            _ => match byte & 0xC0 {
+                QOI_OP_INDEX => pixel = index[usize::from(byte)],
+                QOI_OP_DIFF => {
+                    pixel[0] = pixel[0].wrapping_add((byte >> 4) & 3).wrapping_sub(2);
+                    pixel[1] = pixel[1].wrapping_add((byte >> 2) & 3).wrapping_sub(2);
+                    pixel[2] = pixel[2].wrapping_add(byte & 3).wrapping_sub(2);
+                }
+                QOI_OP_LUMA => {
+                    let second = next()?;
+                    let green = (byte & 0x3F).wrapping_sub(32);
+                    pixel[0] = pixel[0]
+                        .wrapping_add(green)
+                        .wrapping_add(second >> 4)
+                        .wrapping_sub(8);
+                    pixel[1] = pixel[1].wrapping_add(green);
+                    pixel[2] = pixel[2]
+                        .wrapping_add(green)
+                        .wrapping_add(second & 0x0F)
+                        .wrapping_sub(8);
+                }
+                QOI_OP_RUN => run = usize::from(byte & 0x3F) + 1,
+                _ => unreachable!(),
+            },
+        }
+        let [r, g, b, a] = pixel;
+        let hash =
+            (usize::from(r) * 3 + usize::from(g) * 5 + usize::from(b) * 7 + usize::from(a) * 11)
+                % 64;
+        index[hash] = pixel;
+        let argb = u32::from(a) << 24 | u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b);
+        let run = run.min(count - pixels.len());
+        pixels.extend(std::iter::repeat_n(argb, run));
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A QOI file of `width` by `height` rgba pixels encoded by `chunks`
+    fn qoi(width: u32, height: u32, chunks: &[u8]) -> Vec<u8> {
+        let mut data = b"qoif".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[4, 0]);
+        data.extend_from_slice(chunks);
+        data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        data
+    }
+
+    #[test]
+    fn decodes_every_chunk() {
+        #[rustfmt::skip]
+        let chunks = [
+            QOI_OP_RGB, 0x10, 0x20, 0x30,
+            // Red up by one, green down by one
+            QOI_OP_DIFF | (3 << 4) | (1 << 2) | 2,
+            // Green up by five, red by seven and blue by three
+            QOI_OP_LUMA | 37, (10 << 4) | 6,
+            QOI_OP_RUN | 1,
+            // Hash of the first pixel
+            QOI_OP_INDEX | 21,
+            QOI_OP_RGBA, 0x01, 0x02, 0x03, 0x80,
+            // A run longer than the pixels left
+            QOI_OP_RUN | 4,
+        ];
+        let image = decode(&qoi(3, 3, &chunks)).unwrap();
+        assert_eq!(image.dimensions(), (3, 3));
+        assert_eq!(
+            image.pixels(),
+            &[
+                0xFF10_2030,
+                0xFF11_1F30,
+                0xFF18_2433,
+                0xFF18_2433,
+                0xFF18_2433,
+                0xFF10_2030,
+                0x8001_0203,
+                0x8001_0203,
+                0x8001_0203,
+            ]
+        );
+    }
+
+    #[test]
+    fn wraps_differences() {
+        // From the initial opaque black, every channel down by two
+        let image = decode(&qoi(1, 1, &[QOI_OP_DIFF])).unwrap();
+        assert_eq!(image.pixels(), &[0xFFFE_FEFE]);
+    }
+
+    #[test]
+    fn starts_with_an_empty_index() {
+        let image = decode(&qoi(1, 1, &[QOI_OP_INDEX | 5])).unwrap();
+        assert_eq!(image.pixels(), &[0x0000_0000]);
+    }
+
+    #[test]
+    fn rejects_malformed_files() {
+        let malformed = |data: &[u8], what| match decode(data) {
+            Err(ImageError::Malformed(error)) => error == what,
+            _ => false,
+        };
+        assert!(malformed(&qoi(1, 1, &[])[..13], "header cut short"));
+        assert!(malformed(
+            &qoi(2, 1, &[QOI_OP_RGB, 1, 2, 3])[..18],
+            "pixel data cut short"
+        ));
+        assert!(malformed(
+            &qoi(2, 1, &[QOI_OP_RGB, 1, 2])[..17],
+            "pixel data cut short"
+        ));
+        // Far more pixels than even runs could fill
+        assert!(malformed(
+            &qoi(0xFFFF, 0xFFFF, &[QOI_OP_RUN]),
+            "pixel data cut short"
+        ));
+    }
+}
@@ -12,7 +12,19 @@ mod platform;
 #[path = "platform/windows/mod.rs"]
 mod platform;
 
-pub use self::platform::*;
+mod backend;
+mod blend;
+mod canvas;
+pub mod convert;
+mod frame;
+pub mod image;
+mod offscreen;
+pub mod path;
+mod record;
+mod rect;
+mod scale;
+pub mod text;
+mod window;
 
 pub use winit::{
     dpi, AvailableMonitorsIter, AxisId, ButtonId, ControlFlow,
@@ -22,21 +34,35 @@ pub use winit::{
     WindowAttributes, WindowBuilder, WindowEvent, WindowId,
 };
 
-pub use platform::YuxaWindow;
+pub use backend::{
+    AlphaMode, Backend, FrameStatus, Layout, PixelFormat, PresentMode, PresentedFrame,
+};
+pub use blend::{BlendMode, CompositeOp};
+pub use canvas::Canvas;
+pub use frame::FrameBuffer;
+pub use image::{Blit, Filter, Image, ImageError, ImageFormat};
+pub use offscreen::OffscreenSurface;
+pub use path::{FillRule, LineCap, LineJoin, Path, Stroke};
+pub use record::{RecordedFrame, Recorder};
+pub use rect::Rect;
+pub use scale::{ScalePolicy, Scaler};
+pub use window::YuxaWindow;
 
-impl std::ops::Deref for YuxaWindow {
-    type Target = Window;
-    fn deref(&self) -> &Self::Target {
-        &self.window()
-    }
-}
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub use platform::{WaylandEvent, WaylandWindow};
 
 #[derive(Debug)]
 pub enum CreationError {
     OsError(String),
     /// TODO: remove this error
     NotSupported(&'static str),
-    NoBackendAvailable(Box<std::error::Error + Send>),
+    NoBackendAvailable(Box<dyn std::error::Error + Send>),
     RobustnessNotSupported,
     OpenGlVersionNotSupported,
     NoAvailablePixelFormat,
@@ -47,10 +73,11 @@ pub enum CreationError {
 }
 
 impl CreationError {
+    #[allow(deprecated)]
     fn to_string(&self) -> &str {
         match *self {
-            CreationError::OsError(ref text) => &text,
-            CreationError::NotSupported(text) => &text,
+            CreationError::OsError(ref text) => text,
+            CreationError::NotSupported(text) => text,
             CreationError::NoBackendAvailable(_) => "No backend is available",
             CreationError::RobustnessNotSupported => {
                 "You requested robustness, but it is \
@@ -64,7 +91,7 @@ impl CreationError {
                 "Couldn't find any pixel format that matches \
                  the criteria."
             }
-            CreationError::PlatformSpecific(ref text) => &text,
+            CreationError::PlatformSpecific(ref text) => text,
             CreationError::Window(ref err) => std::error::Error::description(err),
             CreationError::CreationErrorPair(ref _err1, ref _err2) => "Received two errors.",
         }
@@ -96,7 +123,7 @@ impl std::error::Error for CreationError {
         self.to_string()
     }
 
-    fn cause(&self) -> Option<&std::error::Error> {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
         match *self {
             CreationError::NoBackendAvailable(ref err) => Some(&**err),
             CreationError::Window(ref err) => Some(err),
@@ -110,3 +137,54 @@ impl From<WindowCreationError> for CreationError {
         CreationError::Window(err)
     }
 }
+
+/// Error returned when a frame could not be presented
+#[derive(Debug)]
+pub enum DrawError {
+    /// The connection to the compositor or display server was lost
+    CompositorDisconnected(std::io::Error),
+    /// The shared memory pool could not be allocated or written to
+    PoolAllocation(std::io::Error),
+    /// The window has been closed and can no longer be drawn to
+    WindowGone,
+    /// The buffer length does not match the dimensions it is presented at
+    ///
+    /// Lengths are counted in buffer elements, so in bytes for byte buffers.
+    BufferSizeMismatch { expected: usize, actual: usize },
+    /// The row stride of a layout is smaller than a row of pixels
+    InvalidStride { stride: usize, minimum: usize },
+}
+
+impl std::fmt::Display for DrawError {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        match *self {
+            DrawError::CompositorDisconnected(ref err) => {
+                write!(formatter, "Lost connection to the compositor: {}", err)
+            }
+            DrawError::PoolAllocation(ref err) => {
+                write!(formatter, "Failed to allocate the memory pool: {}", err)
+            }
+            DrawError::WindowGone => formatter.write_str("The window no longer exists"),
+            DrawError::BufferSizeMismatch { expected, actual } => write!(
+                formatter,
+                "Buffer has a length of {} but {} was expected",
+                actual, expected
+            ),
+            DrawError::InvalidStride { stride, minimum } => write!(
+                formatter,
+                "Stride of {} bytes is smaller than a row of {} bytes",
+                stride, minimum
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DrawError {
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        match *self {
+            DrawError::CompositorDisconnected(ref err) => Some(err),
+            DrawError::PoolAllocation(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
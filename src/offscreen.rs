@@ -0,0 +1,444 @@
+use crate::backend::{check_len, frame_len};
+use crate::convert;
+use crate::{
+    AlphaMode, Backend, Canvas, DrawError, FrameBuffer, FrameStatus, Layout, PixelFormat,
+    PresentedFrame, Rect,
+};
+
+/// An in-memory presentation target that needs no display server
+///
+/// Frames drawn to it are kept as argb `u32` pixels so they can be inspected afterwards,
+/// which makes it suitable for tests and CI. It can be used on its own or plugged into a
+/// `YuxaWindow` as a backend.
+pub struct OffscreenSurface {
+    dimensions: (usize, usize),
+    frame: Vec<u32>,
+    alpha_mode: AlphaMode,
+    retain_frame: bool,
+    /// Copy of the last frame presented while retaining frames
+    retained: Option<PresentedFrame>,
+}
+
+impl OffscreenSurface {
+    /// Create a surface of `width` by `height` pixels, initially filled with transparent black
+    pub fn new(width: usize, height: usize) -> OffscreenSurface {
+        OffscreenSurface {
+            dimensions: (width, height),
+            frame: vec![0; width * height],
+            alpha_mode: AlphaMode::Premultiplied,
+            retain_frame: false,
+            retained: None,
+        }
+    }
+
+    /// Dimensions of the surface in pixels
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.dimensions
+    }
+
+    /// Change the dimensions used by the next draw, like a window resize
+    pub fn set_dimensions(&mut self, width: usize, height: usize) {
+        self.dimensions = (width, height);
+    }
+
+    /// The last presented frame as native endian argb pixels, row by row
+    ///
+    /// Pixels are premultiplied as a compositor would receive them.
+    pub fn frame(&self) -> &[u32] {
+        &self.frame
+    }
+
+    /// Start a frame that is rendered straight into the surface
+    pub fn begin_frame(&mut self) -> FrameBuffer<'_> {
+        let dimensions = self.dimensions;
+        FrameBuffer::new(self, dimensions).expect("Offscreen surfaces can always map a frame")
+    }
+
+    /// Present a tightly packed frame at the surface dimensions in any `PixelFormat`
+    pub fn draw(&mut self, buffer: &[u8], format: PixelFormat) -> Result<FrameStatus, DrawError> {
+        let (width, height) = self.dimensions;
+        Backend::draw_with_layout(self, buffer, Layout::packed(width, height, format))
+    }
+
+    pub fn draw_argb8888(&mut self, buffer: &[[u8; 4]]) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.dimensions;
+        Backend::draw_argb8888(self, dimensions, buffer)
+    }
+
+    pub fn draw_argb8888_bytes(&mut self, buffer: &[u8]) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.dimensions;
+        Backend::draw_argb8888_bytes(self, dimensions, buffer)
+    }
+
+    pub fn draw_argb32(&mut self, buffer: &[u32]) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.dimensions;
+        Backend::draw_argb32(self, dimensions, buffer)
+    }
+
+    /// Present the pixels of `canvas` at its own dimensions
+    pub fn draw_canvas(&mut self, canvas: &Canvas) -> Result<FrameStatus, DrawError> {
+        let dimensions = (canvas.width(), canvas.height());
+        Backend::draw_argb32(self, dimensions, &canvas.packed_pixels())
+    }
+
+    pub fn draw_damaged(
+        &mut self,
+        buffer: &[u32],
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.dimensions;
+        Backend::draw_damaged(self, dimensions, buffer, damage)
+    }
+
+    pub fn draw_with_layout(
+        &mut self,
+        buffer: &[u8],
+        layout: Layout,
+    ) -> Result<FrameStatus, DrawError> {
+        Backend::draw_with_layout(self, buffer, layout)
+    }
+
+    /// Copy the frame just presented if frames are retained
+    fn retain(&mut self) {
+        if self.retain_frame {
+            self.retained = Some(PresentedFrame::from_argb32(self.dimensions, &self.frame));
+        }
+    }
+}
+
+impl Backend for OffscreenSurface {
+    fn draw_argb32(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        self.dimensions = dimensions;
+        self.frame.clear();
+        self.frame.extend_from_slice(buffer);
+        convert::apply_alpha(self.alpha_mode, &mut self.frame);
+        self.retain();
+        Ok(FrameStatus::Presented)
+    }
+
+    fn map_frame(&mut self, dimensions: (usize, usize)) -> Result<bool, DrawError> {
+        self.dimensions = dimensions;
+        self.frame.resize(dimensions.0 * dimensions.1, 0);
+        Ok(true)
+    }
+
+    fn mapped_pixels(&mut self) -> Option<&mut [u32]> {
+        Some(&mut self.frame)
+    }
+
+    fn present_mapped(&mut self) -> Result<FrameStatus, DrawError> {
+        convert::apply_alpha(self.alpha_mode, &mut self.frame);
+        self.retain();
+        Ok(FrameStatus::Presented)
+    }
+
+    fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        self.alpha_mode = mode;
+    }
+
+    fn set_retain_frame(&mut self, retain: bool) {
+        self.retain_frame = retain;
+        if !retain {
+            self.retained = None;
+        }
+    }
+
+    fn last_frame(&self) -> Option<&PresentedFrame> {
+        self.retained.as_ref()
+    }
+
+    fn read_back(&mut self) -> Option<PresentedFrame> {
+        Some(PresentedFrame::from_argb32(self.dimensions, &self.frame))
+    }
+
+    fn size(&self) -> Option<(usize, usize)> {
+        Some(self.dimensions)
+    }
+
+    fn formats(&self) -> &[PixelFormat] {
+        &[PixelFormat::Argb8888]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ImageFormat;
+
+    /// A 3 by 2 frame of straight argb pixels with every alpha class
+    const PIXELS: [u32; 6] = [
+        0xFF11_2233,
+        0x8040_80C0,
+        0x00FF_FFFF,
+        0xFF00_0000,
+        0x01FF_0080,
+        0xFEFF_FFFF,
+    ];
+
+    fn encode(format: PixelFormat, pixel: u32) -> Vec<u8> {
+        let [_, r, g, b] = pixel.to_be_bytes();
+        match format {
+            PixelFormat::Argb8888 | PixelFormat::Xrgb8888 => pixel.to_ne_bytes().to_vec(),
+            PixelFormat::Rgba8888 => pixel.rotate_left(8).to_ne_bytes().to_vec(),
+            PixelFormat::Bgra8888 => pixel.swap_bytes().to_ne_bytes().to_vec(),
+            PixelFormat::Rgb888 => vec![r, g, b],
+            PixelFormat::Rgb565 => {
+                let pixel = u16::from(r >> 3) << 11 | u16::from(g >> 2) << 5 | u16::from(b >> 3);
+                pixel.to_ne_bytes().to_vec()
+            }
+            PixelFormat::Gray8 => vec![g],
+        }
+    }
+
+    /// The argb pixel `encode` round trips to
+    fn decode(format: PixelFormat, pixel: u32) -> u32 {
+        let [_, r, g, b] = pixel.to_be_bytes();
+        let (r, g, b) = (u32::from(r), u32::from(g), u32::from(b));
+        match format {
+            PixelFormat::Argb8888 | PixelFormat::Rgba8888 | PixelFormat::Bgra8888 => pixel,
+            PixelFormat::Xrgb8888 | PixelFormat::Rgb888 => 0xFF00_0000 | pixel,
+            PixelFormat::Rgb565 => {
+                let (r, g, b) = (r >> 3, g >> 2, b >> 3);
+                0xFF00_0000
+                    | ((r << 3) | (r >> 2)) << 16
+                    | ((g << 2) | (g >> 4)) << 8
+                    | ((b << 3) | (b >> 2))
+            }
+            PixelFormat::Gray8 => 0xFF00_0000 | (g * 0x01_01_01),
+        }
+    }
+
+    const FORMATS: [PixelFormat; 7] = [
+        PixelFormat::Argb8888,
+        PixelFormat::Xrgb8888,
+        PixelFormat::Rgba8888,
+        PixelFormat::Bgra8888,
+        PixelFormat::Rgb888,
+        PixelFormat::Rgb565,
+        PixelFormat::Gray8,
+    ];
+
+    #[test]
+    fn draws_every_format() {
+        for &format in &FORMATS {
+            let buffer: Vec<u8> = PIXELS.iter().flat_map(|&p| encode(format, p)).collect();
+            let expected: Vec<u32> = PIXELS.iter().map(|&p| decode(format, p)).collect();
+            let mut surface = OffscreenSurface::new(3, 2);
+            assert_eq!(
+                surface.draw(&buffer, format).unwrap(),
+                FrameStatus::Presented
+            );
+            assert_eq!(surface.frame(), &expected[..], "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn draws_argb_bytes() {
+        let arrays: Vec<[u8; 4]> = PIXELS.iter().map(|p| p.to_be_bytes()).collect();
+        let mut surface = OffscreenSurface::new(3, 2);
+        surface.draw_argb8888(&arrays).unwrap();
+        assert_eq!(surface.frame(), &PIXELS[..]);
+
+        let bytes: Vec<u8> = arrays.concat();
+        let mut surface = OffscreenSurface::new(3, 2);
+        surface.draw_argb8888_bytes(&bytes).unwrap();
+        assert_eq!(surface.frame(), &PIXELS[..]);
+    }
+
+    #[test]
+    fn rejects_wrong_sizes() {
+        let mut surface = OffscreenSurface::new(3, 2);
+        assert!(matches!(
+            surface.draw_argb32(&PIXELS[..5]),
+            Err(DrawError::BufferSizeMismatch {
+                expected: 6,
+                actual: 5
+            })
+        ));
+        assert!(matches!(
+            surface.draw(&[0; 17], PixelFormat::Rgb888),
+            Err(DrawError::BufferSizeMismatch {
+                expected: 18,
+                actual: 17
+            })
+        ));
+    }
+
+    #[test]
+    fn draws_strided_layouts() {
+        for &format in &FORMATS {
+            let stride = 3 * format.bytes_per_pixel() + 5;
+            // Padding bytes hold garbage that must not show up in the frame
+            let mut buffer = vec![0xA5; stride + 3 * format.bytes_per_pixel()];
+            for (y, row) in PIXELS.chunks(3).enumerate() {
+                let bytes: Vec<u8> = row.iter().flat_map(|&p| encode(format, p)).collect();
+                buffer[y * stride..y * stride + bytes.len()].copy_from_slice(&bytes);
+            }
+            let layout = Layout {
+                width: 3,
+                height: 2,
+                stride,
+                format,
+            };
+            let expected: Vec<u32> = PIXELS.iter().map(|&p| decode(format, p)).collect();
+            let mut surface = OffscreenSurface::new(1, 1);
+            surface.draw_with_layout(&buffer, layout).unwrap();
+            assert_eq!(surface.dimensions(), (3, 2));
+            assert_eq!(surface.frame(), &expected[..], "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn rejects_short_strides() {
+        let layout = Layout {
+            width: 3,
+            height: 2,
+            stride: 11,
+            format: PixelFormat::Argb8888,
+        };
+        let mut surface = OffscreenSurface::new(3, 2);
+        assert!(matches!(
+            surface.draw_with_layout(&[0; 24], layout),
+            Err(DrawError::InvalidStride {
+                stride: 11,
+                minimum: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn draws_strided_canvases() {
+        let mut pixels = vec![0xDEAD_BEEF; 5 * 2];
+        {
+            let mut canvas = Canvas::with_stride(&mut pixels, 3, 2, 5).unwrap();
+            canvas.clear(0xFF00_FF00);
+            canvas.set_pixel(1, 1, 0xFF00_00FF);
+        }
+        let canvas = Canvas::with_stride(&mut pixels, 3, 2, 5).unwrap();
+        let mut surface = OffscreenSurface::new(1, 1);
+        surface.draw_canvas(&canvas).unwrap();
+        assert_eq!(surface.dimensions(), (3, 2));
+        assert_eq!(
+            surface.frame(),
+            &[
+                0xFF00_FF00,
+                0xFF00_FF00,
+                0xFF00_FF00,
+                0xFF00_FF00,
+                0xFF00_00FF,
+                0xFF00_FF00
+            ]
+        );
+    }
+
+    #[test]
+    fn draws_damaged_frames() {
+        let mut surface = OffscreenSurface::new(3, 2);
+        surface.draw_argb32(&[0xFF00_0000; 6]).unwrap();
+        let mut next = [0xFF00_0000; 6];
+        next[4] = 0xFFFF_FFFF;
+        surface
+            .draw_damaged(&next, &[Rect::new(1, 1, 1, 1)])
+            .unwrap();
+        assert_eq!(surface.frame(), &next);
+        // Damage is a hint, pixels outside of it are presented too
+        next[0] = 0xFF12_3456;
+        surface.draw_damaged(&next, &[]).unwrap();
+        assert_eq!(surface.frame(), &next);
+    }
+
+    #[test]
+    fn applies_alpha_modes() {
+        let mut surface = OffscreenSurface::new(3, 2);
+        surface.draw_argb32(&PIXELS).unwrap();
+        assert_eq!(surface.frame(), &PIXELS[..]);
+
+        surface.set_alpha_mode(AlphaMode::Opaque);
+        surface.draw_argb32(&PIXELS).unwrap();
+        let opaque: Vec<u32> = PIXELS.iter().map(|p| p | 0xFF00_0000).collect();
+        assert_eq!(surface.frame(), &opaque[..]);
+
+        surface.set_alpha_mode(AlphaMode::Straight);
+        surface.draw_argb32(&PIXELS).unwrap();
+        assert_eq!(
+            surface.frame(),
+            &[
+                0xFF11_2233,
+                0x8020_4060,
+                0x0000_0000,
+                0xFF00_0000,
+                0x0101_0001,
+                0xFEFE_FEFE
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_alpha_modes_to_mapped_frames() {
+        let mut surface = OffscreenSurface::new(3, 2);
+        surface.set_alpha_mode(AlphaMode::Straight);
+        let mut frame = surface.begin_frame();
+        assert_eq!(frame.dimensions(), (3, 2));
+        frame.pixels().copy_from_slice(&PIXELS);
+        assert_eq!(frame.present().unwrap(), FrameStatus::Presented);
+        assert_eq!(surface.frame()[1], 0x8020_4060);
+        assert_eq!(surface.frame()[2], 0);
+    }
+
+    #[test]
+    fn retains_and_reads_back_frames() {
+        let mut surface = OffscreenSurface::new(3, 2);
+        assert!(surface.last_frame().is_none());
+        surface.set_retain_frame(true);
+        surface.draw_argb32(&PIXELS).unwrap();
+        let retained = surface.last_frame().unwrap().to_argb32();
+        assert_eq!(retained, PIXELS.to_vec());
+        assert_eq!(surface.read_back().unwrap().to_argb32(), PIXELS.to_vec());
+        surface.set_retain_frame(false);
+        assert!(surface.last_frame().is_none());
+    }
+
+    #[test]
+    fn saves_opaque_frames_with_garbage_alpha() {
+        let pixels: [u32; 6] = [
+            0x0011_2233,
+            0x1240_80C0,
+            0x80FF_FFFF,
+            0x0000_0000,
+            0x01FF_0080,
+            0xFEFF_FFFF,
+        ];
+        let bytes: Vec<u8> = pixels.iter().flat_map(|p| p.to_ne_bytes()).collect();
+        let layout = Layout::packed(3, 2, PixelFormat::Argb8888);
+        let mut surface = OffscreenSurface::new(3, 2);
+        surface.set_alpha_mode(AlphaMode::Opaque);
+        surface.set_retain_frame(true);
+        surface.draw_with_layout(&bytes, layout).unwrap();
+        // What the Wayland backend retains after sending the frame as xrgb
+        let xrgb = Layout {
+            format: PixelFormat::Xrgb8888,
+            ..layout
+        };
+        let frames = [
+            surface.last_frame().unwrap().clone(),
+            PresentedFrame::new(xrgb, &bytes),
+        ];
+        for frame in &frames {
+            let mut ppm = Vec::new();
+            frame.to_image().encode(ImageFormat::Ppm, &mut ppm).unwrap();
+            assert_eq!(&ppm[..11], b"P6\n3 2\n255\n");
+            assert_eq!(
+                &ppm[11..],
+                &[
+                    0x11, 0x22, 0x33, 0x40, 0x80, 0xC0, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF,
+                    0x00, 0x80, 0xFF, 0xFF, 0xFF
+                ][..]
+            );
+        }
+    }
+}
@@ -0,0 +1,389 @@
+//! Vector paths rasterized with anti-aliasing
+//!
+//! A `Path` is built from lines, Bézier curves and arcs, then filled or stroked onto a
+//! `Canvas` with `Canvas::fill_path` and `Canvas::stroke_path`. Curves are flattened into
+//! lines and the resulting polygons are rasterized with exact horizontal coverage and
+//! sixteen samples per pixel vertically.
+
+mod raster;
+mod stroke;
+
+pub use self::stroke::{LineCap, LineJoin, Stroke};
+
+pub(crate) use self::raster::rasterize;
+pub(crate) use self::stroke::stroke_polygons;
+
+/// A point in canvas coordinates, pixel centers lie at half pixel offsets
+pub type Point = (f32, f32);
+
+/// Largest distance in pixels a flattened curve strays from the true curve
+const TOLERANCE: f32 = 0.05;
+
+/// Most full turns drawn by `Path::arc`, larger sweeps are reduced to this many turns
+const MAX_ARC_TURNS: f32 = 64.;
+
+/// Rule deciding which areas enclosed by a path are inside of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillRule {
+    /// Inside where the path winds around a point any number of times other than zero
+    NonZero,
+    /// Inside where the path crosses a ray from a point an odd number of times
+    EvenOdd,
+}
+
+impl FillRule {
+    fn is_inside(self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verb {
+    MoveTo(Point),
+    LineTo(Point),
+    QuadTo(Point, Point),
+    CubicTo(Point, Point, Point),
+    Close,
+}
+
+/// A connected run of lines produced by flattening a path
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Polyline {
+    pub points: Vec<Point>,
+    pub closed: bool,
+}
+
+/// A shape made of subpaths of lines and curves
+///
+/// Every subpath starts with `move_to`. Drawing without a current point starts a new
+/// subpath at the first point drawn to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Path {
+    verbs: Vec<Verb>,
+    /// Start of the current subpath
+    start: Option<Point>,
+    /// End of the last segment
+    current: Option<Point>,
+}
+
+impl Path {
+    pub fn new() -> Path {
+        Path::default()
+    }
+
+    /// Start a new subpath at `point`
+    pub fn move_to(&mut self, point: Point) -> &mut Path {
+        self.verbs.push(Verb::MoveTo(point));
+        self.start = Some(point);
+        self.current = Some(point);
+        self
+    }
+
+    /// Add a straight line to `point`
+    pub fn line_to(&mut self, point: Point) -> &mut Path {
+        self.ensure_current(point);
+        self.verbs.push(Verb::LineTo(point));
+        self.current = Some(point);
+        self
+    }
+
+    /// Add a quadratic Bézier curve to `point`, bending towards `control`
+    pub fn quad_to(&mut self, control: Point, point: Point) -> &mut Path {
+        self.ensure_current(control);
+        self.verbs.push(Verb::QuadTo(control, point));
+        self.current = Some(point);
+        self
+    }
+
+    /// Add a cubic Bézier curve to `point`, leaving in the direction of `control1` and
+    /// arriving from the direction of `control2`
+    pub fn cubic_to(&mut self, control1: Point, control2: Point, point: Point) -> &mut Path {
+        self.ensure_current(control1);
+        self.verbs.push(Verb::CubicTo(control1, control2, point));
+        self.current = Some(point);
+        self
+    }
+
+    /// Add a circular arc around `center` from `start_angle` to `end_angle`
+    ///
+    /// Angles are in radians, measured from the positive x axis towards the positive y axis,
+    /// which is clockwise on screen. The arc runs towards `end_angle` in whichever direction
+    /// it lies and is connected to the current point with a straight line. Sweeps of more than
+    /// 64 full turns are reduced to 64 turns.
+    pub fn arc(
+        &mut self,
+        center: Point,
+        radius: f32,
+        start_angle: f32,
+        end_angle: f32,
+    ) -> &mut Path {
+        let at = |angle: f32| {
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        };
+        let start = at(start_angle);
+        match self.current {
+            Some(_) => self.line_to(start),
+            None => self.move_to(start),
+        };
+
+        // Turns beyond the limit retrace the same circle, dropping them keeps huge sweeps cheap
+        let mut sweep = end_angle - start_angle;
+        let limit = MAX_ARC_TURNS * 2. * std::f32::consts::PI;
+        if sweep.abs() > limit {
+            sweep = sweep % (2. * std::f32::consts::PI) + limit.copysign(sweep);
+        }
+        // Every quarter turn or less is approximated by one cubic curve
+        let count = (sweep.abs() / std::f32::consts::FRAC_PI_2).ceil().max(1.);
+        let step = sweep / count;
+        let handle = 4. / 3. * (step / 4.).tan() * radius;
+        let mut angle = start_angle;
+        for _ in 0..count as usize {
+            let next = angle + step;
+            let (from, to) = (at(angle), at(next));
+            let control1 = (from.0 - handle * angle.sin(), from.1 + handle * angle.cos());
+            let control2 = (to.0 + handle * next.sin(), to.1 - handle * next.cos());
+            self.cubic_to(control1, control2, to);
+            angle = next;
+        }
+        self
+    }
+
+    /// Close the current subpath with a straight line back to its start
+    pub fn close(&mut self) -> &mut Path {
+        if self.current.is_some() {
+            self.verbs.push(Verb::Close);
+            self.current = self.start;
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.verbs.is_empty()
+    }
+
+    /// Start a subpath at `point` when there is no current point
+    fn ensure_current(&mut self, point: Point) {
+        if self.current.is_none() {
+            self.move_to(point);
+        }
+    }
+
+    /// The subpaths with every curve replaced by lines
+    pub(crate) fn flatten(&self) -> Vec<Polyline> {
+        let mut polylines = Vec::new();
+        let mut points: Vec<Point> = Vec::new();
+        let mut finish = |points: &mut Vec<Point>, closed: bool| {
+            if !points.is_empty() {
+                polylines.push(Polyline {
+                    points: std::mem::take(points),
+                    closed,
+                });
+            }
+        };
+        for &verb in &self.verbs {
+            match verb {
+                Verb::MoveTo(point) => {
+                    finish(&mut points, false);
+                    points.push(point);
+                }
+                Verb::LineTo(point) => points.push(point),
+                Verb::QuadTo(control, point) => {
+                    let from = *points.last().expect("Curves follow a current point");
+                    flatten_quad(from, control, point, &mut points);
+                }
+                Verb::CubicTo(control1, control2, point) => {
+                    let from = *points.last().expect("Curves follow a current point");
+                    flatten_cubic(from, control1, control2, point, &mut points);
+                }
+                Verb::Close => {
+                    let start = points.first().cloned();
+                    finish(&mut points, true);
+                    // Drawing on after closing continues from the start of the subpath
+                    points.extend(start);
+                }
+            }
+        }
+        finish(&mut points, false);
+        // A lone point left over by closing draws nothing
+        polylines.retain(|polyline| polyline.closed || polyline.points.len() > 1);
+        polylines
+    }
+}
+
+fn flatten_quad(from: Point, control: Point, to: Point, points: &mut Vec<Point>) {
+    // The deviation of a quadratic curve from its chord is a quarter of its second difference
+    let deviation = distance((
+        from.0 - 2. * control.0 + to.0,
+        from.1 - 2. * control.1 + to.1,
+    ));
+    let count = segments(deviation / 4.);
+    for i in 1..=count {
+        let t = i as f32 / count as f32;
+        let u = 1. - t;
+        points.push((
+            u * u * from.0 + 2. * u * t * control.0 + t * t * to.0,
+            u * u * from.1 + 2. * u * t * control.1 + t * t * to.1,
+        ));
+    }
+}
+
+fn flatten_cubic(
+    from: Point,
+    control1: Point,
+    control2: Point,
+    to: Point,
+    points: &mut Vec<Point>,
+) {
+    let second_difference =
+        |a: Point, b: Point, c: Point| distance((a.0 - 2. * b.0 + c.0, a.1 - 2. * b.1 + c.1));
+    let deviation =
+        second_difference(from, control1, control2).max(second_difference(control1, control2, to));
+    let count = segments(deviation * 3. / 4.);
+    for i in 1..=count {
+        let t = i as f32 / count as f32;
+        let u = 1. - t;
+        let (a, b, c, d) = (u * u * u, 3. * u * u * t, 3. * u * t * t, t * t * t);
+        points.push((
+            a * from.0 + b * control1.0 + c * control2.0 + d * to.0,
+            a * from.1 + b * control1.1 + c * control2.1 + d * to.1,
+        ));
+    }
+}
+
+/// Number of lines needed to keep a curve of `deviation` within the tolerance, deviation
+/// falls with the square of the number of lines
+fn segments(deviation: f32) -> usize {
+    ((deviation / TOLERANCE).sqrt().ceil() as usize).clamp(1, 1000)
+}
+
+fn distance(vector: Point) -> f32 {
+    (vector.0 * vector.0 + vector.1 * vector.1).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Canvas;
+
+    fn cubics(path: &Path) -> usize {
+        path.verbs
+            .iter()
+            .filter(|verb| matches!(verb, Verb::CubicTo(..)))
+            .count()
+    }
+
+    fn assert_near(actual: Point, expected: Point, tolerance: f32) {
+        assert!(
+            distance((actual.0 - expected.0, actual.1 - expected.1)) <= tolerance,
+            "{:?} is not {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn approximates_arcs_with_quarter_turns() {
+        let mut path = Path::new();
+        path.arc((10., 10.), 5., 0., std::f32::consts::FRAC_PI_2);
+        assert_eq!(path.verbs[0], Verb::MoveTo((15., 10.)));
+        assert_eq!(cubics(&path), 1);
+        let polylines = path.flatten();
+        assert_eq!(polylines.len(), 1);
+        for &point in &polylines[0].points {
+            let radius = distance((point.0 - 10., point.1 - 10.));
+            assert!((radius - 5.).abs() <= TOLERANCE, "{:?}", point);
+        }
+        assert_near(*polylines[0].points.last().unwrap(), (10., 15.), 1e-5);
+
+        // Negative sweeps run the other way, and a following arc connects with a line
+        let mut path = Path::new();
+        path.arc((0., 0.), 1., 0., -3.).arc((0., 0.), 2., 0., 0.);
+        assert_eq!(cubics(&path), 3);
+        assert_eq!(path.verbs[3], Verb::LineTo((2., 0.)));
+        assert_eq!(path.verbs.len(), 5);
+        let points = &path.flatten()[0].points;
+        assert!(points.iter().all(|point| point.1 <= 1e-5));
+    }
+
+    #[test]
+    fn limits_arc_turns() {
+        let turn = 2. * std::f32::consts::PI;
+        let mut path = Path::new();
+        path.arc((0., 0.), 10., 0., 64. * turn);
+        assert_eq!(cubics(&path), 64 * 4);
+        for &sweep in [1e9, -1e9, 1e30].iter() {
+            let mut path = Path::new();
+            path.arc((0., 0.), 10., 0., sweep);
+            assert!(cubics(&path) <= 65 * 4, "{}", sweep);
+            assert!(cubics(&path) > 64 * 4 - 4, "{}", sweep);
+        }
+        // The reduced sweep ends where the full one would
+        let mut path = Path::new();
+        path.arc((0., 0.), 10., 1., 1. + 100. * turn + 1.);
+        assert!(cubics(&path) <= 65 * 4);
+        let end = *path.flatten()[0].points.last().unwrap();
+        assert_near(end, (10. * 2f32.cos(), 10. * 2f32.sin()), 1e-2);
+    }
+
+    #[test]
+    fn flattens_subpaths() {
+        let mut path = Path::new();
+        path.line_to((1., 1.))
+            .line_to((3., 1.))
+            .quad_to((3., 3.), (1., 3.))
+            .close()
+            .line_to((0., 0.))
+            .move_to((5., 5.))
+            .close()
+            .move_to((7., 7.));
+        let polylines = path.flatten();
+        assert_eq!(polylines.len(), 3);
+        assert!(polylines[0].closed);
+        // Drawing without a current point starts there before drawing to it
+        assert_eq!(polylines[0].points[..3], [(1., 1.), (1., 1.), (3., 1.)]);
+        assert_eq!(*polylines[0].points.last().unwrap(), (1., 3.));
+        // Drawing after closing starts from the start of the closed subpath
+        assert_eq!(
+            polylines[1],
+            Polyline {
+                points: vec![(1., 1.), (0., 0.)],
+                closed: false,
+            }
+        );
+        // A closed lone point is kept for its caps, an open one is dropped
+        assert_eq!(
+            polylines[2],
+            Polyline {
+                points: vec![(5., 5.)],
+                closed: true,
+            }
+        );
+        assert!(Path::new().close().is_empty());
+    }
+
+    #[test]
+    fn fills_partially_covered_pixels() {
+        let mut pixels = vec![0; 4 * 2];
+        let mut canvas = Canvas::new(&mut pixels, 4, 2).unwrap();
+        let mut path = Path::new();
+        path.move_to((0.5, 0.))
+            .line_to((3., 0.))
+            .line_to((3., 1.5))
+            .line_to((0.5, 1.5))
+            .close();
+        canvas.fill_path(&path, FillRule::NonZero, 0xFFFFFFFF);
+        assert_eq!(
+            pixels,
+            [
+                0x80808080, 0xFFFFFFFF, 0xFFFFFFFF, 0, //
+                0x40404040, 0x80808080, 0x80808080, 0,
+            ]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use super::{FillRule, Point};
+
+/// Sub-scanlines sampled within every row of pixels
+const SAMPLES: usize = 16;
+
+struct Edge {
+    top: f32,
+    bottom: f32,
+    /// x at `top`
+    x: f32,
+    /// Change of x for every unit of y
+    slope: f32,
+    /// +1 for edges running down, -1 for edges running up
+    direction: i32,
+}
+
+/// Compute the anti-aliased coverage of the closed `polygons` within a `width` by `height`
+/// area, calling `span` with the row, first column and coverage between 0 and 1 of every
+/// run of touched pixels
+pub(crate) fn rasterize<F>(
+    polygons: &[Vec<Point>],
+    rule: FillRule,
+    width: usize,
+    height: usize,
+    mut span: F,
+) where
+    F: FnMut(usize, usize, &[f32]),
+{
+    let mut edges = Vec::new();
+    for polygon in polygons {
+        for (i, &from) in polygon.iter().enumerate() {
+            let to = polygon[(i + 1) % polygon.len()];
+            if from.1 == to.1 || !is_finite(from) || !is_finite(to) {
+                continue;
+            }
+            let (top, bottom, direction) = if from.1 < to.1 {
+                (from, to, 1)
+            } else {
+                (to, from, -1)
+            };
+            edges.push(Edge {
+                top: top.1,
+                bottom: bottom.1,
+                x: top.0,
+                slope: (bottom.0 - top.0) / (bottom.1 - top.1),
+                direction,
+            });
+        }
+    }
+    if edges.is_empty() || width == 0 {
+        return;
+    }
+    edges.sort_by(|a, b| a.top.partial_cmp(&b.top).unwrap());
+
+    let first_row = edges[0].top.max(0.).floor() as usize;
+    let last_row = edges
+        .iter()
+        .map(|edge| edge.bottom)
+        .fold(0., f32::max)
+        .ceil()
+        .min(height as f32) as usize;
+
+    // Coverage of partially covered pixels, and changes in coverage carried to the right
+    let mut cover = vec![0.; width];
+    let mut carry = vec![0.; width + 1];
+    let mut coverage = vec![0.; width];
+    let mut crossings: Vec<(f32, i32)> = Vec::new();
+    let mut active: Vec<usize> = Vec::new();
+    let mut next_edge = 0;
+    let weight = 1. / SAMPLES as f32;
+
+    for row in first_row..last_row {
+        let row_bottom = (row + 1) as f32;
+        while next_edge < edges.len() && edges[next_edge].top < row_bottom {
+            active.push(next_edge);
+            next_edge += 1;
+        }
+        active.retain(|&index| edges[index].bottom > row as f32);
+
+        let (mut left, mut right) = (width, 0);
+        for sample in 0..SAMPLES {
+            let y = row as f32 + (sample as f32 + 0.5) * weight;
+            crossings.clear();
+            for &index in &active {
+                let edge = &edges[index];
+                // Half open so vertices shared by two edges are only counted once
+                if edge.top <= y && y < edge.bottom {
+                    crossings.push((edge.x + (y - edge.top) * edge.slope, edge.direction));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+            let mut winding = 0;
+            let mut start = 0.;
+            for &(x, direction) in &crossings {
+                let was_inside = rule.is_inside(winding);
+                winding += direction;
+                match (was_inside, rule.is_inside(winding)) {
+                    (false, true) => start = x,
+                    (true, false) => {
+                        let (from, to) = (start.max(0.), x.min(width as f32));
+                        if from < to {
+                            accumulate(&mut cover, &mut carry, from, to, weight);
+                            left = left.min(from as usize);
+                            right = right.max((to.ceil() as usize).min(width));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if left < right {
+            let mut carried = 0.;
+            for x in left..right {
+                carried += carry[x];
+                coverage[x] = (cover[x] + carried).min(1.);
+                cover[x] = 0.;
+                carry[x] = 0.;
+            }
+            carry[right] = 0.;
+            span(row, left, &coverage[left..right]);
+        }
+    }
+}
+
+/// Add `weight` times the coverage of the interval `from..to` of a sub-scanline
+fn accumulate(cover: &mut [f32], carry: &mut [f32], from: f32, to: f32, weight: f32) {
+    let (first, last) = (from as usize, to as usize);
+    if first == last {
+        cover[first] += (to - from) * weight;
+        return;
+    }
+    cover[first] += (first as f32 + 1. - from) * weight;
+    // Pixels between the ends are fully covered
+    carry[first + 1] += weight;
+    carry[last] -= weight;
+    if last < cover.len() {
+        cover[last] += (to - last as f32) * weight;
+    }
+}
+
+fn is_finite(point: Point) -> bool {
+    point.0.is_finite() && point.1.is_finite()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Coverage of every pixel of a `width` by `height` area, row by row
+    fn coverage(
+        polygons: &[Vec<Point>],
+        rule: FillRule,
+        (width, height): (usize, usize),
+    ) -> Vec<f32> {
+        let mut pixels = vec![0.; width * height];
+        rasterize(polygons, rule, width, height, |row, column, coverage| {
+            assert!(column + coverage.len() <= width);
+            pixels[row * width + column..][..coverage.len()].copy_from_slice(coverage);
+        });
+        pixels
+    }
+
+    fn rect(left: f32, top: f32, right: f32, bottom: f32) -> Vec<Point> {
+        vec![(left, top), (right, top), (right, bottom), (left, bottom)]
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32], tolerance: f32) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(
+                (actual - expected).abs() <= tolerance,
+                "{:?} is not {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn covers_partial_pixels() {
+        // Horizontal coverage is exact
+        let pixels = coverage(&[rect(1.5, 0., 3., 1.)], FillRule::NonZero, (4, 1));
+        assert_close(&pixels, &[0., 0.5, 1., 0.], 1e-6);
+        let pixels = coverage(&[rect(0.25, 0., 0.5, 1.)], FillRule::NonZero, (2, 1));
+        assert_close(&pixels, &[0.25, 0.], 1e-6);
+        // Vertically half of the samples of the second row are inside
+        let pixels = coverage(&[rect(0., 0., 1., 1.5)], FillRule::NonZero, (1, 2));
+        assert_close(&pixels, &[1., 0.5], 1e-6);
+        // A diagonal edge through opposite corners covers half of the pixels it crosses
+        let triangle = vec![(0., 0.), (2., 0.), (0., 2.)];
+        let pixels = coverage(&[triangle], FillRule::NonZero, (2, 2));
+        assert_close(&pixels, &[1., 0.5, 0.5, 0.], 1. / 32.);
+    }
+
+    #[test]
+    fn clips_to_the_area() {
+        let pixels = coverage(&[rect(-5., -5., 1.5, 10.)], FillRule::NonZero, (3, 2));
+        assert_close(&pixels, &[1., 0.5, 0., 1., 0.5, 0.], 1e-6);
+        let pixels = coverage(&[rect(2.5, 1., 9., 9.)], FillRule::NonZero, (3, 2));
+        assert_close(&pixels, &[0., 0., 0., 0., 0., 0.5], 1e-6);
+        let outside = [
+            rect(-3., 0., -1., 2.),
+            rect(0., 5., 3., 6.),
+            rect(4., 0., 5., 2.),
+        ];
+        assert!(coverage(&outside, FillRule::NonZero, (3, 2))
+            .iter()
+            .all(|&pixel| pixel == 0.));
+        assert!(coverage(&[rect(0., 0., 3., 2.)], FillRule::NonZero, (0, 2)).is_empty());
+    }
+
+    #[test]
+    fn skips_degenerate_edges() {
+        let broken = vec![(0., 0.), (f32::NAN, 1.), (2., f32::INFINITY), (2., 2.)];
+        coverage(&[broken], FillRule::NonZero, (2, 2));
+        // Horizontal edges and single points cover nothing
+        let flat = vec![vec![(0., 1.), (2., 1.)], vec![(1., 1.)], Vec::new()];
+        assert!(coverage(&flat, FillRule::NonZero, (2, 2))
+            .iter()
+            .all(|&pixel| pixel == 0.));
+    }
+
+    #[test]
+    fn applies_the_fill_rule() {
+        // A five pointed star drawn in one stroke winds twice around its center
+        let star: Vec<Point> = (0..5)
+            .map(|i| {
+                let angle = (i * 2 % 5) as f32 * 2. * std::f32::consts::PI / 5.;
+                (10. + 9. * angle.sin(), 10. - 9. * angle.cos())
+            })
+            .collect();
+        let star = [star];
+        let non_zero = coverage(&star, FillRule::NonZero, (20, 20));
+        let even_odd = coverage(&star, FillRule::EvenOdd, (20, 20));
+        let (center, arm) = (10 * 20 + 10, 5 * 20 + 10);
+        assert_eq!((non_zero[center], even_odd[center]), (1., 0.));
+        assert_eq!((non_zero[arm], even_odd[arm]), (1., 1.));
+        assert_eq!((non_zero[0], even_odd[0]), (0., 0.));
+
+        // Overlapping polygons wound in opposite directions cancel out under either rule
+        let mut reversed = rect(1., 0., 3., 1.);
+        reversed.reverse();
+        let polygons = [rect(0., 0., 2., 1.), reversed];
+        let pixels = coverage(&polygons, FillRule::NonZero, (3, 1));
+        assert_close(&pixels, &[1., 0., 1.], 1e-6);
+        let polygons = [rect(0., 0., 2., 1.), rect(1., 0., 3., 1.)];
+        let pixels = coverage(&polygons, FillRule::NonZero, (3, 1));
+        assert_close(&pixels, &[1., 1., 1.], 1e-6);
+        let pixels = coverage(&polygons, FillRule::EvenOdd, (3, 1));
+        assert_close(&pixels, &[1., 0., 1.], 1e-6);
+    }
+}
@@ -0,0 +1,569 @@
+use std::f32::consts::PI;
+
+use super::{Point, Polyline, TOLERANCE};
+
+/// Most repetitions of a dash pattern along a subpath, finer patterns are drawn solid
+const MAX_DASH_CYCLES: f32 = 100_000.;
+
+/// Shape drawn at the open ends of a stroke
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineCap {
+    /// The stroke ends exactly at the end of the path
+    Butt,
+    /// The stroke ends in a half circle
+    Round,
+    /// The stroke extends past the end of the path by half its width
+    Square,
+}
+
+/// Shape drawn where two segments of a stroke meet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineJoin {
+    /// The outer edges are extended until they meet, falling back to `Bevel` at corners
+    /// sharper than the miter limit allows
+    Miter,
+    Round,
+    /// The outer corners are connected with a straight line
+    Bevel,
+}
+
+/// How the outline of a path is stroked
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stroke {
+    /// Width of the stroke in pixels
+    pub width: f32,
+    pub cap: LineCap,
+    pub join: LineJoin,
+    /// Longest miter allowed, as a multiple of the stroke width
+    pub miter_limit: f32,
+    /// Alternating lengths of dashes and gaps, an empty pattern draws a solid stroke
+    ///
+    /// Patterns of odd length are repeated to make them even. Patterns repeating more than
+    /// 100000 times along a subpath are too fine to draw and stroke it solid.
+    pub dashes: Vec<f32>,
+    /// Distance into the dash pattern at which every subpath starts
+    pub dash_offset: f32,
+}
+
+impl Stroke {
+    /// A solid stroke of `width` pixels with butt caps and miter joins
+    pub fn new(width: f32) -> Stroke {
+        Stroke {
+            width,
+            cap: LineCap::Butt,
+            join: LineJoin::Miter,
+            miter_limit: 4.,
+            dashes: Vec::new(),
+            dash_offset: 0.,
+        }
+    }
+}
+
+/// Polygons covering the stroke of `polylines`, all wound the same way so they can be
+/// filled together with `FillRule::NonZero`
+pub(crate) fn stroke_polygons(polylines: &[Polyline], stroke: &Stroke) -> Vec<Vec<Point>> {
+    let half = stroke.width / 2.;
+    let mut polygons = Vec::new();
+    if half.is_nan() || half <= 0. {
+        return polygons;
+    }
+    for polyline in polylines {
+        let mut points = polyline.points.clone();
+        points.dedup();
+        if polyline.closed && points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        match dash(&points, polyline.closed, stroke) {
+            Some(dashes) => {
+                for dash in dashes {
+                    stroke_polyline(&dash, false, stroke, half, &mut polygons);
+                }
+            }
+            None => stroke_polyline(&points, polyline.closed, stroke, half, &mut polygons),
+        }
+    }
+    for polygon in &mut polygons {
+        if signed_area(polygon) < 0. {
+            polygon.reverse();
+        }
+    }
+    polygons
+}
+
+fn stroke_polyline(
+    points: &[Point],
+    closed: bool,
+    stroke: &Stroke,
+    half: f32,
+    polygons: &mut Vec<Vec<Point>>,
+) {
+    match points.len() {
+        0 => return,
+        1 => {
+            // A single point only shows up through its caps
+            let point = points[0];
+            match stroke.cap {
+                LineCap::Butt => {}
+                LineCap::Round => polygons.push(circle(point, half)),
+                LineCap::Square => polygons.push(vec![
+                    (point.0 - half, point.1 - half),
+                    (point.0 + half, point.1 - half),
+                    (point.0 + half, point.1 + half),
+                    (point.0 - half, point.1 + half),
+                ]),
+            }
+            return;
+        }
+        _ => {}
+    }
+    let closed = closed && points.len() > 2;
+    let count = if closed {
+        points.len()
+    } else {
+        points.len() - 1
+    };
+    for i in 0..count {
+        let (from, to) = (points[i], points[(i + 1) % points.len()]);
+        let normal = scale(normal(from, to), half);
+        polygons.push(vec![
+            add(from, normal),
+            add(to, normal),
+            sub(to, normal),
+            sub(from, normal),
+        ]);
+    }
+
+    // Joins between consecutive segments, including the one closing the polyline
+    let joins = if closed {
+        0..points.len()
+    } else {
+        1..points.len() - 1
+    };
+    for i in joins {
+        let previous = points[(i + points.len() - 1) % points.len()];
+        let next = points[(i + 1) % points.len()];
+        join(previous, points[i], next, stroke, half, polygons);
+    }
+
+    if !closed {
+        cap(points[1], points[0], stroke.cap, half, polygons);
+        let last = points.len() - 1;
+        cap(points[last - 1], points[last], stroke.cap, half, polygons);
+    }
+}
+
+/// Fill the outer corner where the segments `previous` to `point` and `point` to `next` meet
+fn join(
+    previous: Point,
+    point: Point,
+    next: Point,
+    stroke: &Stroke,
+    half: f32,
+    polygons: &mut Vec<Vec<Point>>,
+) {
+    let (incoming, outgoing) = (normal(previous, point), normal(point, next));
+    let turn = cross(sub(point, previous), sub(next, point));
+    if turn == 0. && dot(incoming, outgoing) > 0. {
+        // Straight on, the segments already meet
+        return;
+    }
+    // The outer corner lies opposite the direction of the turn
+    let side = if turn > 0. { -half } else { half };
+    let (from, to) = (
+        add(point, scale(incoming, side)),
+        add(point, scale(outgoing, side)),
+    );
+    match stroke.join {
+        LineJoin::Round => polygons.push(circle(point, half)),
+        LineJoin::Miter => {
+            // The miter extends 1 / cos(theta / 2) half widths past the point, where theta
+            // is the angle between the normals
+            let cos_half = ((1. + dot(incoming, outgoing)) / 2.).sqrt();
+            if cos_half > 0. && 1. / cos_half <= stroke.miter_limit {
+                let direction = normalize(add(incoming, outgoing));
+                let tip = add(point, scale(direction, side / cos_half));
+                polygons.push(vec![point, from, tip, to]);
+            } else {
+                polygons.push(vec![point, from, to]);
+            }
+        }
+        LineJoin::Bevel => polygons.push(vec![point, from, to]),
+    }
+}
+
+/// Add the cap at `end` of the segment running from `from`
+fn cap(from: Point, end: Point, cap: LineCap, half: f32, polygons: &mut Vec<Vec<Point>>) {
+    match cap {
+        LineCap::Butt => {}
+        LineCap::Round => polygons.push(circle(end, half)),
+        LineCap::Square => {
+            let normal = scale(normal(from, end), half);
+            let extension = scale(normalize(sub(end, from)), half);
+            let beyond = add(end, extension);
+            polygons.push(vec![
+                add(end, normal),
+                add(beyond, normal),
+                sub(beyond, normal),
+                sub(end, normal),
+            ]);
+        }
+    }
+}
+
+/// Split `points` into the dashes of the pattern of `stroke`, `None` for solid strokes
+fn dash(points: &[Point], closed: bool, stroke: &Stroke) -> Option<Vec<Vec<Point>>> {
+    let mut pattern = stroke.dashes.clone();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(&stroke.dashes);
+    }
+    let total: f32 = pattern.iter().sum();
+    if pattern.is_empty()
+        || pattern.iter().any(|&length| length < 0.)
+        || !total.is_finite()
+        || total <= 0.
+    {
+        return None;
+    }
+
+    let segments = if closed {
+        points.len()
+    } else {
+        points.len() - 1
+    };
+    // Dashes far shorter than the path would take forever and lose all precision
+    let path_length: f32 = (0..segments)
+        .map(|i| distance(sub(points[(i + 1) % points.len()], points[i])))
+        .sum();
+    if path_length / total > MAX_DASH_CYCLES {
+        return None;
+    }
+
+    // Position of the start of the path within the pattern
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut offset = stroke.dash_offset.rem_euclid(total);
+    // Starting exactly at the end of a dash starts in the following gap, but zero length
+    // dashes at the start are kept. Rounding can leave the offset just past the last entry.
+    for _ in 0..pattern.len() {
+        if offset < remaining || (offset == remaining && remaining == 0.) {
+            break;
+        }
+        offset -= remaining;
+        index = (index + 1) % pattern.len();
+        remaining = pattern[index];
+    }
+    remaining = (remaining - offset).max(0.);
+
+    let mut dashes = Vec::new();
+    let mut current = vec![points[0]];
+    for i in 0..segments {
+        let (mut from, to) = (points[i], points[(i + 1) % points.len()]);
+        let mut length = distance(sub(to, from));
+        // Dashes ending exactly at the end of the path are still finished
+        while length >= remaining {
+            let split = if remaining < length {
+                lerp(from, to, remaining / length)
+            } else {
+                to
+            };
+            if index % 2 == 0 {
+                current.push(split);
+                // Dashes of zero length are left as single points, drawn by their caps
+                current.dedup();
+                dashes.push(std::mem::take(&mut current));
+            } else {
+                current = vec![split];
+            }
+            length -= remaining;
+            from = split;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length;
+        if index % 2 == 0 {
+            current.push(to);
+        }
+    }
+    if index % 2 == 0 && !current.is_empty() {
+        current.dedup();
+        dashes.push(current);
+    }
+    Some(dashes)
+}
+
+/// A polygon approximating a circle within the flattening tolerance
+fn circle(center: Point, radius: f32) -> Vec<Point> {
+    let count = if radius > TOLERANCE {
+        (PI / (1. - TOLERANCE / radius).acos())
+            .ceil()
+            .clamp(8., 1000.) as usize
+    } else {
+        8
+    };
+    (0..count)
+        .map(|i| {
+            let angle = 2. * PI * i as f32 / count as f32;
+            (
+                center.0 + radius * angle.cos(),
+                center.1 + radius * angle.sin(),
+            )
+        })
+        .collect()
+}
+
+fn signed_area(polygon: &[Point]) -> f32 {
+    let mut area = 0.;
+    for (i, &a) in polygon.iter().enumerate() {
+        area += cross(a, polygon[(i + 1) % polygon.len()]);
+    }
+    area / 2.
+}
+
+/// Unit vector perpendicular to the segment from `from` to `to`
+fn normal(from: Point, to: Point) -> Point {
+    let direction = normalize(sub(to, from));
+    (-direction.1, direction.0)
+}
+
+fn normalize(vector: Point) -> Point {
+    let length = distance(vector);
+    if length == 0. {
+        (0., 0.)
+    } else {
+        scale(vector, 1. / length)
+    }
+}
+
+fn distance(vector: Point) -> f32 {
+    (vector.0 * vector.0 + vector.1 * vector.1).sqrt()
+}
+
+fn lerp(a: Point, b: Point, t: f32) -> Point {
+    (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t)
+}
+
+fn add(a: Point, b: Point) -> Point {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Point, b: Point) -> Point {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn scale(a: Point, factor: f32) -> Point {
+    (a.0 * factor, a.1 * factor)
+}
+
+fn dot(a: Point, b: Point) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+fn cross(a: Point, b: Point) -> f32 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Smallest rectangle containing all `polygons`, as left, top, right and bottom
+    fn bounds(polygons: &[Vec<Point>]) -> (f32, f32, f32, f32) {
+        let points = polygons.iter().flatten();
+        points.fold(
+            (f32::INFINITY, f32::INFINITY, -f32::INFINITY, -f32::INFINITY),
+            |(left, top, right, bottom), &(x, y)| {
+                (left.min(x), top.min(y), right.max(x), bottom.max(y))
+            },
+        )
+    }
+
+    fn line(from: Point, to: Point) -> Polyline {
+        Polyline {
+            points: vec![from, to],
+            closed: false,
+        }
+    }
+
+    fn dashed(dashes: &[f32], offset: f32) -> Stroke {
+        Stroke {
+            dashes: dashes.to_vec(),
+            dash_offset: offset,
+            ..Stroke::new(1.)
+        }
+    }
+
+    /// The x range of every dash along a horizontal line
+    fn dash_ranges(length: f32, stroke: &Stroke) -> Option<Vec<(f32, f32)>> {
+        let dashes = dash(&[(0., 0.), (length, 0.)], false, stroke)?;
+        Some(
+            dashes
+                .iter()
+                .map(|dash| (dash[0].0, dash[dash.len() - 1].0))
+                .collect(),
+        )
+    }
+
+    fn assert_ranges(actual: Option<Vec<(f32, f32)>>, expected: &[(f32, f32)]) {
+        let actual = actual.expect("A dashed stroke");
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (&(from, to), &(expected_from, expected_to)) in actual.iter().zip(expected) {
+            assert!(
+                (from - expected_from).abs() < 1e-4 && (to - expected_to).abs() < 1e-4,
+                "{:?} is not {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn extends_caps_past_the_ends() {
+        let polylines = [line((2., 5.), (8., 5.))];
+        let stroke = |cap| Stroke {
+            cap,
+            ..Stroke::new(2.)
+        };
+        let butt = stroke_polygons(&polylines, &stroke(LineCap::Butt));
+        assert_eq!(bounds(&butt), (2., 4., 8., 6.));
+        let square = stroke_polygons(&polylines, &stroke(LineCap::Square));
+        assert_eq!(bounds(&square), (1., 4., 9., 6.));
+        let (left, top, right, bottom) =
+            bounds(&stroke_polygons(&polylines, &stroke(LineCap::Round)));
+        for (actual, expected) in [(left, 1.), (top, 4.), (right, 9.), (bottom, 6.)].iter() {
+            assert!(
+                (actual - expected).abs() < 1e-5,
+                "{:?}",
+                (left, top, right, bottom)
+            );
+        }
+        // Round caps stay within the flattening tolerance of the true circle
+        let round = stroke_polygons(&[line((5., 5.), (5., 5.))], &stroke(LineCap::Round));
+        assert!(round
+            .iter()
+            .flatten()
+            .all(|&point| (distance(sub(point, (5., 5.))) - 1.).abs() < 1e-5));
+
+        // Closed polylines have no caps
+        let polylines = [Polyline {
+            points: vec![(2., 2.), (8., 2.), (8., 8.), (2., 8.)],
+            closed: true,
+        }];
+        let closed = stroke_polygons(&polylines, &stroke(LineCap::Square));
+        assert_eq!(bounds(&closed), (1., 1., 9., 9.));
+    }
+
+    #[test]
+    fn strokes_single_points_with_their_caps() {
+        let point = [line((3., 3.), (3., 3.))];
+        let stroke = |cap| Stroke {
+            cap,
+            ..Stroke::new(2.)
+        };
+        assert!(stroke_polygons(&point, &stroke(LineCap::Butt)).is_empty());
+        let square = stroke_polygons(&point, &stroke(LineCap::Square));
+        assert_eq!(bounds(&square), (2., 2., 4., 4.));
+        assert!(stroke_polygons(&point, &Stroke::new(0.)).is_empty());
+        assert!(stroke_polygons(&point, &Stroke::new(f32::NAN)).is_empty());
+    }
+
+    #[test]
+    fn winds_all_polygons_the_same_way() {
+        let polylines = [line((8., 5.), (2., 5.)), line((2., 1.), (8., 3.))];
+        let stroke = Stroke {
+            cap: LineCap::Square,
+            ..Stroke::new(2.)
+        };
+        for polygon in stroke_polygons(&polylines, &stroke) {
+            assert!(signed_area(&polygon) >= 0.);
+        }
+    }
+
+    #[test]
+    fn starts_dashes_at_the_offset() {
+        let expected = [(0., 2.), (3., 5.), (6., 8.), (9., 10.)];
+        assert_ranges(dash_ranges(10., &dashed(&[2., 1.], 0.)), &expected);
+        let expected = [(0., 1.), (2., 4.), (5., 7.), (8., 10.)];
+        assert_ranges(dash_ranges(10., &dashed(&[2., 1.], 1.)), &expected);
+        // Offsets wrap around the pattern in either direction
+        assert_ranges(dash_ranges(10., &dashed(&[2., 1.], 4.)), &expected);
+        // A gap ending exactly at the end of the path leaves a zero length dash for the caps
+        let expected = [(1., 3.), (4., 6.), (7., 9.), (10., 10.)];
+        assert_ranges(dash_ranges(10., &dashed(&[2., 1.], -1.)), &expected);
+        // Starting exactly at the end of a dash starts in the gap
+        let expected = [(1., 3.), (4., 6.), (7., 9.)];
+        assert_ranges(dash_ranges(9.5, &dashed(&[2., 1.], 2.)), &expected);
+    }
+
+    #[test]
+    fn repeats_odd_patterns() {
+        // Dashes and gaps swap on every repetition of an odd pattern
+        let expected = [(0., 1.), (3., 4.), (5., 7.)];
+        assert_ranges(dash_ranges(7.5, &dashed(&[1., 2., 1.], 0.)), &expected);
+    }
+
+    #[test]
+    fn keeps_zero_length_dashes() {
+        let dashes = dash(&[(0., 0.), (4., 0.)], false, &dashed(&[0., 2.], 0.)).unwrap();
+        assert_eq!(dashes, vec![vec![(0., 0.)], vec![(2., 0.)], vec![(4., 0.)]]);
+    }
+
+    #[test]
+    fn dashes_across_corners() {
+        let points = [(0., 0.), (2., 0.), (2., 2.)];
+        let dashes = dash(&points, false, &dashed(&[3., 2.], 0.)).unwrap();
+        assert_eq!(dashes, vec![vec![(0., 0.), (2., 0.), (2., 1.)]]);
+        // Closed paths dash along their closing segment
+        let points = [(0., 0.), (2., 0.), (2., 2.), (0., 2.)];
+        let dashes = dash(&points, true, &dashed(&[7., 2.], 0.)).unwrap();
+        assert_eq!(
+            dashes,
+            vec![vec![(0., 0.), (2., 0.), (2., 2.), (0., 2.), (0., 1.)]]
+        );
+    }
+
+    #[test]
+    fn strokes_invalid_patterns_solid() {
+        for pattern in [
+            &[][..],
+            &[0., 0.],
+            &[1., -1.],
+            &[1., f32::NAN],
+            &[1., f32::INFINITY],
+        ]
+        .iter()
+        {
+            assert_eq!(
+                dash_ranges(10., &dashed(pattern, 0.)),
+                None,
+                "{:?}",
+                pattern
+            );
+        }
+        // A non-finite offset leaves the pattern where it is
+        assert!(dash_ranges(10., &dashed(&[2., 1.], f32::NAN)).is_some());
+    }
+
+    #[test]
+    fn strokes_too_fine_patterns_solid() {
+        let cycles = |count: f32| dash_ranges(count, &dashed(&[0.5, 0.5], 0.));
+        // The last cycle ends exactly at the end of the line, followed by a point
+        assert_eq!(
+            cycles(MAX_DASH_CYCLES).map(|dashes| dashes.len()),
+            Some(100_001)
+        );
+        assert_eq!(cycles(MAX_DASH_CYCLES * 2.), None);
+        let huge = dash_ranges(1e30, &dashed(&[1., 1.], 0.));
+        assert_eq!(huge, None);
+
+        // The solid stroke covers the whole line
+        let polylines = [line((0., 0.), (1e6, 0.))];
+        let stroke = Stroke {
+            width: 2.,
+            ..dashed(&[1e-3, 1e-3], 0.)
+        };
+        assert_eq!(
+            bounds(&stroke_polygons(&polylines, &stroke)),
+            (0., -1., 1e6, 1.)
+        );
+    }
+}
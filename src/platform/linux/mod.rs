@@ -1,85 +1,34 @@
 use winit::{EventsLoop, Window, WindowBuilder};
 
-use crate::CreationError;
+use crate::{Backend, CreationError};
 use wayland::WaylandBackend;
 use x11::X11Backend;
 
+pub use wayland_window::{WaylandEvent, WaylandWindow};
+
 mod wayland;
+mod wayland_window;
 mod x11;
 
-pub struct YuxaWindow {
-    window: Window,
-    wayland: Option<WaylandBackend>,
-    x11: Option<X11Backend>,
-}
-
-impl YuxaWindow {
-    pub fn new(
-        window_builder: WindowBuilder,
-        events_loop: &EventsLoop,
-    ) -> Result<Self, CreationError> {
-        let window = window_builder.build(events_loop)?;
-
-        let wayland = WaylandBackend::new(&window);
-        let x11 = if wayland.is_none() {
-            X11Backend::new(&window)
-        } else {
-            None
-        };
-
-        Ok(YuxaWindow {
-            wayland,
-            x11,
-            window,
-        })
-    }
-
-    /// Get reference to the inner winit window
-    pub fn window(&self) -> &Window {
-        &self.window
-    }
-
-    /// Get mutable reference to the inner winit window
-    pub fn window_mut(&mut self) -> &mut Window {
-        &mut self.window
-    }
-
-    pub fn draw_argb8888(&mut self, buffer: &[[u8; 4]]) {
-        if let Some(wayland) = &mut self.wayland {
-            let dimensions: (u32, u32) =
-                self.window.get_inner_size().unwrap().to_physical(1.).into();
-            wayland.draw_argb8888((dimensions.0 as usize, dimensions.1 as usize), buffer);
-        }
-        if let Some(x11) = &mut self.x11 {
-            let dimensions: (u32, u32) =
-                self.window.get_inner_size().unwrap().to_physical(1.).into();
-            x11.draw_argb8888((dimensions.0 as usize, dimensions.1 as usize), buffer);
-        }
+/// Adjust `builder` before winit creates the window, translucent X11 windows need an argb
+/// visual
+pub(crate) fn prepare_builder(builder: WindowBuilder, events_loop: &EventsLoop) -> WindowBuilder {
+    if builder.window.transparent {
+        x11::with_argb_visual(builder, events_loop)
+    } else {
+        builder
     }
+}
 
-    pub fn draw_argb8888_bytes(&mut self, buffer: &[u8]) {
-        if let Some(wayland) = &mut self.wayland {
-            let dimensions: (u32, u32) =
-                self.window.get_inner_size().unwrap().to_physical(1.).into();
-            wayland.draw_argb8888_bytes((dimensions.0 as usize, dimensions.1 as usize), buffer);
-        }
-        if let Some(x11) = &mut self.x11 {
-            let dimensions: (u32, u32) =
-                self.window.get_inner_size().unwrap().to_physical(1.).into();
-            x11.draw_argb8888_bytes((dimensions.0 as usize, dimensions.1 as usize), buffer);
-        }
+/// Pick Wayland when winit is running on it, falling back to X11 otherwise
+pub(crate) fn default_backend(window: &Window) -> Result<Box<dyn Backend>, CreationError> {
+    if let Some(wayland) = WaylandBackend::new(window)? {
+        return Ok(Box::new(wayland));
     }
-
-    pub fn draw_argb32(&mut self, buffer: &[u32]) {
-        if let Some(wayland) = &mut self.wayland {
-            let dimensions: (u32, u32) =
-                self.window.get_inner_size().unwrap().to_physical(1.).into();
-            wayland.draw_argb32((dimensions.0 as usize, dimensions.1 as usize), buffer);
-        }
-        if let Some(x11) = &mut self.x11 {
-            let dimensions: (u32, u32) =
-                self.window.get_inner_size().unwrap().to_physical(1.).into();
-            x11.draw_argb32((dimensions.0 as usize, dimensions.1 as usize), buffer);
-        }
+    if let Some(x11) = X11Backend::new(window)? {
+        return Ok(Box::new(x11));
     }
+    Err(CreationError::NotSupported(
+        "window is neither a wayland nor an x11 window",
+    ))
 }
@@ -1,153 +1,696 @@
-use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::io::{Seek, SeekFrom, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
-use sctk::utils::DoubleMemPool;
+use sctk::utils::MemPool;
 use sctk::Environment;
 
 use sctk::reexports::client::protocol::wl_surface::RequestsTrait as SurfaceRequests;
-use sctk::reexports::client::protocol::{wl_shm, wl_surface};
+use sctk::reexports::client::protocol::{wl_buffer, wl_callback, wl_shm, wl_surface};
 use sctk::reexports::client::{Display, EventQueue, Proxy};
+use sctk::reexports::protocols::viewporter::client::{wp_viewport, wp_viewporter};
 use sctk::wayland_client::sys::client::wl_display;
+use wp_viewport::RequestsTrait as ViewportRequests;
+use wp_viewporter::RequestsTrait as ViewporterRequests;
 
 use winit::os::unix::WindowExt;
 
-use byteorder::{NativeEndian, WriteBytesExt};
+use crate::backend::{check_len, frame_len};
+use crate::convert;
+use crate::{
+    AlphaMode, Backend, CreationError, DrawError, FrameStatus, Layout, PixelFormat, PresentMode,
+    PresentedFrame, Rect,
+};
+
+/// Number of stale regions a pool tracks before it is rewritten in full instead
+const MAX_STALE_REGIONS: usize = 64;
+
+/// A shm pool together with a record of the frame it holds
+pub struct Slot {
+    pub pool: MemPool,
+    /// Dimensions of the tightly packed frame in the pool, `None` if it must be fully rewritten
+    pub contents: Option<(usize, usize)>,
+    /// Regions presented from other pools since this pool was last written
+    pub stale: Vec<Rect>,
+}
+
+impl Slot {
+    fn new(env: &Environment) -> std::io::Result<Slot> {
+        Ok(Slot {
+            pool: MemPool::new(&env.shm, || {})?,
+            contents: None,
+            stale: Vec::new(),
+        })
+    }
+
+    /// The start of the pool as a tightly packed argb frame of `dimensions`
+    ///
+    /// The pool must have been resized to hold the frame.
+    fn pixels(&mut self, dimensions: (usize, usize)) -> &mut [u32] {
+        let mmap = self.pool.mmap();
+        // The mapping is page aligned and at least as large as the frame
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                mmap.as_mut_ptr() as *mut u32,
+                dimensions.0 * dimensions.1,
+            )
+        }
+    }
+}
 
 pub struct WaylandBackend {
     pub display: Display,
     pub event_queue: EventQueue,
+    // Owns the globals bound on the connection, which may be winit's
     pub env: Environment,
-    pub pools: DoubleMemPool,
+    pub slots: Vec<Slot>,
+    /// Number of pools to cycle through, surplus pools are dropped once released
+    pub buffer_count: usize,
     pub surface: Proxy<wl_surface::WlSurface>,
+    pub dimensions: Option<(usize, usize)>,
+    pub present_mode: PresentMode,
+    /// Whether draws wait for a buffer release instead of dropping the frame
+    pub wait_for_buffer: bool,
+    /// Pool index and dimensions of a frame lent out through `map_frame`
+    pub mapped: Option<(usize, (usize, usize))>,
+    /// Set by the frame callback of the last presented frame
+    pub frame_ready: Arc<AtomicBool>,
+    pub alpha_mode: AlphaMode,
+    /// Integer scale of the outputs the surface is on, buffers are attached at this scale
+    pub scale: i32,
+    /// Bound when the compositor can scale surfaces
+    pub viewporter: Option<Proxy<wp_viewporter::WpViewporter>>,
+    /// Created the first time frames are scaled to a destination size
+    pub viewport: Option<Proxy<wp_viewport::WpViewport>>,
+    /// Logical size every frame is scaled to, `None` to show frames at the buffer scale
+    pub destination: Option<(usize, usize)>,
+    /// Formats advertised by the compositor that frames can be presented in unconverted
+    pub formats: Vec<PixelFormat>,
+    pub retain_frame: bool,
+    /// Copy of the last frame presented while retaining frames
+    pub retained: Option<PresentedFrame>,
+}
+
+/// The `wl_shm` format matching `format`, if there is one
+fn shm_format(format: PixelFormat) -> Option<wl_shm::Format> {
+    match format {
+        PixelFormat::Argb8888 => Some(wl_shm::Format::Argb8888),
+        PixelFormat::Xrgb8888 => Some(wl_shm::Format::Xrgb8888),
+        PixelFormat::Rgba8888 => Some(wl_shm::Format::Rgba8888),
+        PixelFormat::Bgra8888 => Some(wl_shm::Format::Bgra8888),
+        // Wayland names 24 bit formats from the most significant byte of a little endian value
+        PixelFormat::Rgb888 => Some(wl_shm::Format::Bgr888),
+        PixelFormat::Rgb565 => Some(wl_shm::Format::Rgb565),
+        PixelFormat::Gray8 => None,
+    }
 }
 
 impl WaylandBackend {
+    /// Present to the surface of `window`, or `None` if it is not a Wayland window
     #[inline]
-    pub fn new(window: &winit::Window) -> Option<WaylandBackend> {
+    pub fn new(window: &winit::Window) -> Result<Option<WaylandBackend>, CreationError> {
         let mut wayland = None;
         if let Some(winit_display) = window.get_wayland_display() {
             if let Some(surface) = window.get_wayland_surface() {
                 let (display, mut event_queue) =
                     unsafe { Display::from_external_display(winit_display as *mut wl_display) };
-                let env = Environment::from_display(&*display, &mut event_queue).unwrap();
-                let pools =
-                    DoubleMemPool::new(&env.shm, || {}).expect("Failed to create a memory pool !");
+                let env = Environment::from_display(&display, &mut event_queue)
+                    .map_err(|err| CreationError::OsError(err.to_string()))?;
                 let surface = unsafe { Proxy::from_c_ptr(surface as *mut _) };
-
-                wayland = Some(WaylandBackend {
+                wayland = Some(WaylandBackend::with_environment(
                     display,
                     event_queue,
                     env,
-                    pools,
                     surface,
-                });
+                )?);
             }
         }
-        wayland
+        Ok(wayland)
     }
 
-    #[inline]
-    pub fn draw_argb8888(&mut self, dimensions: (usize, usize), buffer: &[[u8; 4]]) {
-        if let Some(mut pool) = self.pools.pool() {
-            pool.resize(4 * dimensions.0 * dimensions.1)
-                .expect("Failed to resize the memory pool.");
-            pool.seek(SeekFrom::Start(0)).unwrap();
-            if cfg!(target_endian = "little") {
-                let mut writer = BufWriter::new(&mut pool);
-                for pixel in buffer {
-                    writer
-                        .write_all(&[pixel[3], pixel[2], pixel[1], pixel[0]])
-                        .unwrap()
-                }
-            } else {
-                let mut writer = BufWriter::new(&mut pool);
-                for pixel in buffer {
-                    writer.write_all(pixel).unwrap()
+    /// Present to `surface` using the globals of `env`, bound on the connection of `display`
+    pub fn with_environment(
+        display: Display,
+        event_queue: EventQueue,
+        env: Environment,
+        surface: Proxy<wl_surface::WlSurface>,
+    ) -> Result<WaylandBackend, CreationError> {
+        let slots = (0..2)
+            .map(|_| Slot::new(&env))
+            .collect::<std::io::Result<_>>()
+            .map_err(|err| CreationError::OsError(err.to_string()))?;
+        let viewporter = env
+            .manager
+            .instantiate_auto(|viewporter| viewporter.implement(|_, _| {}, ()))
+            .ok();
+        let advertised = env.shm_formats();
+        let formats = [
+            PixelFormat::Argb8888,
+            PixelFormat::Xrgb8888,
+            PixelFormat::Rgba8888,
+            PixelFormat::Bgra8888,
+            PixelFormat::Rgb888,
+            PixelFormat::Rgb565,
+            PixelFormat::Gray8,
+        ]
+        .iter()
+        .cloned()
+        .filter(|&format| shm_format(format).is_some_and(|format| advertised.contains(&format)))
+        .collect();
+
+        Ok(WaylandBackend {
+            display,
+            event_queue,
+            env,
+            slots,
+            buffer_count: 2,
+            surface,
+            dimensions: None,
+            present_mode: PresentMode::Blocking,
+            wait_for_buffer: false,
+            mapped: None,
+            frame_ready: Arc::new(AtomicBool::new(true)),
+            alpha_mode: AlphaMode::Premultiplied,
+            scale: 1,
+            viewporter,
+            viewport: None,
+            destination: None,
+            formats,
+            retain_frame: false,
+            retained: None,
+        })
+    }
+
+    /// Index of a pool that holds no buffers still in use by the compositor
+    ///
+    /// Waiting events are read first so buffer releases are noticed. When every pool is in use
+    /// this either waits for a release or returns `None` so the frame is dropped.
+    fn free_slot(&mut self) -> Result<Option<usize>, DrawError> {
+        self.read_events()?;
+        while self.slots.len() > self.buffer_count {
+            match self.slots.iter().rposition(|slot| !slot.pool.is_used()) {
+                Some(index) => {
+                    self.slots.remove(index);
                 }
+                None => break,
+            }
+        }
+        loop {
+            let free = self.slots.iter().position(|slot| !slot.pool.is_used());
+            if free.is_some() || !self.wait_for_buffer {
+                return Ok(free);
             }
+            self.event_queue
+                .dispatch()
+                .map_err(DrawError::CompositorDisconnected)?;
+        }
+    }
 
-            pool.flush().unwrap();
-            let new_buffer = pool.buffer(
-                0,
-                dimensions.0 as i32,
-                dimensions.1 as i32,
-                4 * dimensions.0 as i32,
-                wl_shm::Format::Argb8888,
-            );
-            self.surface.attach(Some(&new_buffer), 0, 0);
-            self.surface.commit();
-            self.surface
-                .damage(0, 0, dimensions.0 as i32, dimensions.1 as i32);
+    /// Read the events waiting on the connection without blocking and dispatch them
+    ///
+    /// Nothing else reads the socket when winit is not pumped, or when there is no winit, so
+    /// frame callbacks and buffer releases would otherwise never arrive.
+    pub fn read_events(&mut self) -> Result<(), DrawError> {
+        let would_block = |result: std::io::Result<_>| match result {
+            Err(ref err) if err.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+            result => result.map(|_| ()),
+        };
+        would_block(self.display.flush()).map_err(DrawError::CompositorDisconnected)?;
+        self.event_queue
+            .dispatch_pending()
+            .map_err(DrawError::CompositorDisconnected)?;
+        // Declining to read means events arrived since, and they are dispatched below
+        if let Some(guard) = self.event_queue.prepare_read() {
+            would_block(guard.read_events().map(|_| ()))
+                .map_err(DrawError::CompositorDisconnected)?;
+        }
+        self.event_queue
+            .dispatch_pending()
+            .map_err(DrawError::CompositorDisconnected)?;
+        Ok(())
+    }
 
-            self.display.flush().unwrap();
-            self.event_queue.dispatch_pending().unwrap();
-            self.event_queue.sync_roundtrip().unwrap();
+    /// Format argb frames are tagged with, the compositor ignores alpha of opaque frames
+    fn argb_format(&self) -> wl_shm::Format {
+        match self.alpha_mode {
+            AlphaMode::Opaque => wl_shm::Format::Xrgb8888,
+            _ => wl_shm::Format::Argb8888,
         }
     }
 
-    #[inline]
-    pub fn draw_argb8888_bytes(&mut self, dimensions: (usize, usize), buffer: &[u8]) {
-        if let Some(mut pool) = self.pools.pool() {
-            pool.resize(4 * dimensions.0 * dimensions.1)
-                .expect("Failed to resize the memory pool.");
-            pool.seek(SeekFrom::Start(0)).unwrap();
-            if cfg!(target_endian = "little") {
-                let mut writer = BufWriter::new(&mut pool);
-                for i in (0..buffer.len()).step_by(4) {
-                    writer
-                        .write_all(&[buffer[i + 3], buffer[i + 2], buffer[i + 1], buffer[i]])
-                        .unwrap()
-                }
+    /// Layout of tightly packed argb frames in the pools
+    fn argb_layout(&self, dimensions: (usize, usize)) -> Layout {
+        let format = match self.alpha_mode {
+            AlphaMode::Opaque => PixelFormat::Xrgb8888,
+            _ => PixelFormat::Argb8888,
+        };
+        Layout::packed(dimensions.0, dimensions.1, format)
+    }
+
+    /// Premultiply `regions` of the argb frame written to the pool at `index` if alpha is
+    /// straight
+    fn premultiply(&mut self, index: usize, dimensions: (usize, usize), regions: &[Rect]) {
+        if self.alpha_mode != AlphaMode::Straight {
+            return;
+        }
+        let pixels = self.slots[index].pixels(dimensions);
+        for rect in regions.iter().filter_map(|rect| rect.clip(dimensions)) {
+            for y in rect.y..rect.y + rect.height {
+                let start = y * dimensions.0 + rect.x;
+                convert::apply_alpha(AlphaMode::Straight, &mut pixels[start..start + rect.width]);
+            }
+        }
+    }
+
+    /// Attach a buffer filled from the pool at `index` with a frame of `layout` and report
+    /// `damage` to the compositor
+    ///
+    /// In blocking mode this waits for the compositor to process the frame, otherwise a frame
+    /// callback is requested and this returns as soon as the requests are sent.
+    fn present(
+        &mut self,
+        index: usize,
+        layout: Layout,
+        buffer: &Proxy<wl_buffer::WlBuffer>,
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        if self.retain_frame {
+            let frame = PresentedFrame::new(layout, self.slots[index].pool.mmap());
+            self.retained = Some(frame);
+        }
+        let dimensions = (layout.width, layout.height);
+        for (i, slot) in self.slots.iter_mut().enumerate() {
+            if i == index {
+                slot.contents = Some(dimensions);
+                slot.stale.clear();
+            } else if slot.stale.len() + damage.len() > MAX_STALE_REGIONS {
+                slot.contents = None;
+                slot.stale.clear();
             } else {
-                pool.write_all(buffer).unwrap();
+                slot.stale.extend_from_slice(damage);
             }
+        }
 
-            pool.flush().unwrap();
-            let new_buffer = pool.buffer(
-                0,
-                dimensions.0 as i32,
-                dimensions.1 as i32,
-                4 * dimensions.0 as i32,
-                wl_shm::Format::Argb8888,
+        // Buffers must divide evenly into surface pixels, others are shown unscaled. Frames
+        // scaled by the viewport are never scaled by the buffer scale as well.
+        let scale = self.scale as usize;
+        let scale = if self.destination.is_none()
+            && dimensions.0.is_multiple_of(scale)
+            && dimensions.1.is_multiple_of(scale)
+        {
+            scale
+        } else {
+            1
+        };
+        // Winit also sets the scale when it changes, but it has to match every buffer
+        self.surface.set_buffer_scale(scale as i32);
+        if let Some(viewport) = &self.viewport {
+            match self.destination {
+                Some((width, height)) => viewport.set_destination(width as i32, height as i32),
+                None => viewport.set_destination(-1, -1),
+            }
+        }
+        self.surface.attach(Some(buffer), 0, 0);
+        let surface_size = self
+            .destination
+            .unwrap_or((dimensions.0 / scale, dimensions.1 / scale));
+        let (width, height) = (dimensions.0.max(1), dimensions.1.max(1));
+        for rect in damage {
+            // Damage is given in surface pixels, rounded outwards
+            let (x, y) = (
+                rect.x * surface_size.0 / width,
+                rect.y * surface_size.1 / height,
             );
-            self.surface.attach(Some(&new_buffer), 0, 0);
-            self.surface.commit();
+            let right = ((rect.x + rect.width) * surface_size.0).div_ceil(width);
+            let bottom = ((rect.y + rect.height) * surface_size.1).div_ceil(height);
             self.surface
-                .damage(0, 0, dimensions.0 as i32, dimensions.1 as i32);
+                .damage(x as i32, y as i32, (right - x) as i32, (bottom - y) as i32);
+        }
+        if self.present_mode == PresentMode::FrameCallback {
+            self.frame_ready.store(false, Ordering::SeqCst);
+            let frame_ready = self.frame_ready.clone();
+            self.surface
+                .frame(|callback| {
+                    callback.implement(
+                        move |event, _| match event {
+                            wl_callback::Event::Done { .. } => {
+                                frame_ready.store(true, Ordering::SeqCst)
+                            }
+                        },
+                        (),
+                    )
+                })
+                .map_err(|()| {
+                    DrawError::CompositorDisconnected(std::io::ErrorKind::NotConnected.into())
+                })?;
+        }
+        self.surface.commit();
+        self.dimensions = Some(dimensions);
+
+        self.display
+            .flush()
+            .map_err(DrawError::CompositorDisconnected)?;
+        match self.present_mode {
+            PresentMode::Blocking => {
+                self.event_queue
+                    .dispatch_pending()
+                    .map_err(DrawError::CompositorDisconnected)?;
+                self.event_queue
+                    .sync_roundtrip()
+                    .map_err(DrawError::CompositorDisconnected)?;
+                Ok(FrameStatus::Presented)
+            }
+            PresentMode::FrameCallback => Ok(FrameStatus::Queued),
+        }
+    }
+}
+
+impl Backend for WaylandBackend {
+    #[inline]
+    fn draw_argb8888(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[[u8; 4]],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        self.draw_argb8888_bytes(dimensions, convert::argb_arrays_as_bytes(buffer))
+    }
+
+    #[inline]
+    fn draw_argb8888_bytes(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u8],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 4), buffer.len())?;
+        // Buffers without pixels are a protocol error, and would show nothing anyway
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return Ok(FrameStatus::Dropped);
+        }
+        let index = match self.free_slot()? {
+            Some(index) => index,
+            None => return Ok(FrameStatus::Dropped),
+        };
+        let slot = &mut self.slots[index];
+        slot.pool
+            .resize(4 * dimensions.0 * dimensions.1)
+            .map_err(DrawError::PoolAllocation)?;
+        convert::argb_bytes_to_argb32(buffer, slot.pixels(dimensions));
+        let damage = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        self.premultiply(index, dimensions, &damage);
+        let format = self.argb_format();
+        let new_buffer = self.slots[index].pool.buffer(
+            0,
+            dimensions.0 as i32,
+            dimensions.1 as i32,
+            4 * dimensions.0 as i32,
+            format,
+        );
+        self.present(index, self.argb_layout(dimensions), &new_buffer, &damage)
+    }
 
-            self.display.flush().unwrap();
-            self.event_queue.dispatch_pending().unwrap();
-            self.event_queue.sync_roundtrip().unwrap();
+    #[inline]
+    fn draw_argb32(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return Ok(FrameStatus::Dropped);
         }
+        let index = match self.free_slot()? {
+            Some(index) => index,
+            None => return Ok(FrameStatus::Dropped),
+        };
+        let slot = &mut self.slots[index];
+        slot.pool
+            .resize(4 * dimensions.0 * dimensions.1)
+            .map_err(DrawError::PoolAllocation)?;
+        slot.pixels(dimensions).copy_from_slice(buffer);
+        let damage = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        self.premultiply(index, dimensions, &damage);
+        let format = self.argb_format();
+        let new_buffer = self.slots[index].pool.buffer(
+            0,
+            dimensions.0 as i32,
+            dimensions.1 as i32,
+            4 * dimensions.0 as i32,
+            format,
+        );
+        self.present(index, self.argb_layout(dimensions), &new_buffer, &damage)
     }
 
     #[inline]
-    pub fn draw_argb32(&mut self, dimensions: (usize, usize), buffer: &[u32]) {
-        if let Some(mut pool) = self.pools.pool() {
-            pool.resize(4 * dimensions.0 * dimensions.1)
-                .expect("Failed to resize the memory pool.");
-            pool.seek(SeekFrom::Start(0)).unwrap();
-            {
-                let mut writer = BufWriter::new(&mut pool);
-                for pixel in buffer {
-                    writer.write_u32::<NativeEndian>(*pixel).unwrap();
+    fn draw_with_layout(
+        &mut self,
+        buffer: &[u8],
+        layout: Layout,
+    ) -> Result<FrameStatus, DrawError> {
+        layout.check(buffer)?;
+        if layout.width == 0 || layout.height == 0 {
+            return Ok(FrameStatus::Dropped);
+        }
+        // Frames with alpha are only presented natively when no alpha conversion is needed
+        let format = match (layout.format, self.alpha_mode) {
+            (PixelFormat::Argb8888, AlphaMode::Opaque) => Some(wl_shm::Format::Xrgb8888),
+            (format, AlphaMode::Premultiplied) => shm_format(format),
+            (format, _) if !format.has_alpha() => shm_format(format),
+            _ => None,
+        };
+        let format = match format {
+            Some(format) if self.formats.contains(&layout.format) => format,
+            _ => {
+                let packed = layout.pack_argb32(buffer);
+                return self.draw_argb32((layout.width, layout.height), &packed);
+            }
+        };
+        let index = match self.free_slot()? {
+            Some(index) => index,
+            None => return Ok(FrameStatus::Dropped),
+        };
+        let pool = &mut self.slots[index].pool;
+        pool.resize(layout.stride * layout.height)
+            .map_err(DrawError::PoolAllocation)?;
+        pool.seek(SeekFrom::Start(0))
+            .map_err(DrawError::PoolAllocation)?;
+        pool.write_all(&buffer[..layout.min_len()])
+            .map_err(DrawError::PoolAllocation)?;
+        pool.flush().map_err(DrawError::PoolAllocation)?;
+        let new_buffer = pool.buffer(
+            0,
+            layout.width as i32,
+            layout.height as i32,
+            layout.stride as i32,
+            format,
+        );
+        let damage = [Rect::new(0, 0, layout.width, layout.height)];
+        // Frames sent as xrgb are retained as such, so their alpha bytes are ignored too
+        let presented = if format == wl_shm::Format::Xrgb8888 {
+            Layout {
+                format: PixelFormat::Xrgb8888,
+                ..layout
+            }
+        } else {
+            layout
+        };
+        let status = self.present(index, presented, &new_buffer, &damage)?;
+        // Only tightly packed argb frames can later be updated in place by damaged draws
+        if layout.format != PixelFormat::Argb8888 || layout.stride != layout.row_bytes() {
+            self.slots[index].contents = None;
+        }
+        Ok(status)
+    }
+
+    fn draw_damaged(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return Ok(FrameStatus::Dropped);
+        }
+        let full = Rect::new(0, 0, dimensions.0, dimensions.1);
+        let damage: Vec<Rect> = if self.dimensions == Some(dimensions) {
+            damage
+                .iter()
+                .filter_map(|rect| rect.clip(dimensions))
+                .collect()
+        } else {
+            vec![full]
+        };
+        let index = match self.free_slot()? {
+            Some(index) => index,
+            None => return Ok(FrameStatus::Dropped),
+        };
+
+        // The pool still holds an older frame, so regions presented from the other pools
+        // since then have to be copied along with the new damage
+        let slot = &mut self.slots[index];
+        let regions: Vec<Rect> = if slot.contents == Some(dimensions) {
+            slot.stale
+                .iter()
+                .filter_map(|rect| rect.clip(dimensions))
+                .chain(damage.iter().cloned())
+                .collect()
+        } else {
+            vec![full]
+        };
+        slot.pool
+            .resize(4 * dimensions.0 * dimensions.1)
+            .map_err(DrawError::PoolAllocation)?;
+        {
+            let mmap = slot.pool.mmap();
+            for rect in &regions {
+                for y in rect.y..rect.y + rect.height {
+                    let start = y * dimensions.0 + rect.x;
+                    let row = &buffer[start..start + rect.width];
+                    let target = &mut mmap[4 * start..4 * (start + rect.width)];
+                    for (pixel, bytes) in row.iter().zip(target.chunks_exact_mut(4)) {
+                        bytes.copy_from_slice(&pixel.to_ne_bytes());
+                    }
                 }
             }
-            pool.flush().unwrap();
-            let new_buffer = pool.buffer(
-                0,
-                dimensions.0 as i32,
-                dimensions.1 as i32,
-                4 * dimensions.0 as i32,
-                wl_shm::Format::Argb8888,
-            );
-            self.surface.attach(Some(&new_buffer), 0, 0);
-            self.surface.commit();
-            self.surface
-                .damage(0, 0, dimensions.0 as i32, dimensions.1 as i32);
+        }
+        self.premultiply(index, dimensions, &regions);
+        let format = self.argb_format();
+        let new_buffer = self.slots[index].pool.buffer(
+            0,
+            dimensions.0 as i32,
+            dimensions.1 as i32,
+            4 * dimensions.0 as i32,
+            format,
+        );
+        self.present(index, self.argb_layout(dimensions), &new_buffer, &damage)
+    }
+
+    fn map_frame(&mut self, dimensions: (usize, usize)) -> Result<bool, DrawError> {
+        self.mapped = None;
+        // Empty frames are drawn into a fallback buffer and dropped when presented
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return Ok(false);
+        }
+        let index = match self.free_slot()? {
+            Some(index) => index,
+            None => return Ok(false),
+        };
+        self.slots[index]
+            .pool
+            .resize(4 * dimensions.0 * dimensions.1)
+            .map_err(DrawError::PoolAllocation)?;
+        self.mapped = Some((index, dimensions));
+        Ok(true)
+    }
 
-            self.display.flush().unwrap();
-            self.event_queue.dispatch_pending().unwrap();
-            self.event_queue.sync_roundtrip().unwrap();
+    fn mapped_pixels(&mut self) -> Option<&mut [u32]> {
+        let (index, dimensions) = self.mapped?;
+        Some(self.slots[index].pixels(dimensions))
+    }
+
+    fn present_mapped(&mut self) -> Result<FrameStatus, DrawError> {
+        let (index, dimensions) = match self.mapped.take() {
+            Some(mapped) => mapped,
+            None => return Ok(FrameStatus::Dropped),
+        };
+        let damage = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        self.premultiply(index, dimensions, &damage);
+        let format = self.argb_format();
+        let new_buffer = self.slots[index].pool.buffer(
+            0,
+            dimensions.0 as i32,
+            dimensions.1 as i32,
+            4 * dimensions.0 as i32,
+            format,
+        );
+        self.present(index, self.argb_layout(dimensions), &new_buffer, &damage)
+    }
+
+    fn set_present_mode(&mut self, mode: PresentMode) {
+        self.present_mode = mode;
+        if mode == PresentMode::Blocking {
+            self.frame_ready.store(true, Ordering::SeqCst);
+        }
+    }
+
+    fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        if mode != self.alpha_mode {
+            // Pools hold pixels converted for the previous mode
+            for slot in &mut self.slots {
+                slot.contents = None;
+            }
+            self.alpha_mode = mode;
+        }
+    }
+
+    fn set_scale_factor(&mut self, scale: f64) {
+        self.scale = (scale.round() as i32).max(1);
+    }
+
+    fn set_destination_size(&mut self, size: Option<(usize, usize)>) -> bool {
+        let viewporter = match &self.viewporter {
+            Some(viewporter) => viewporter,
+            None => return size.is_none(),
+        };
+        if size.is_some() && self.viewport.is_none() {
+            self.viewport = viewporter
+                .get_viewport(&self.surface, |viewport| viewport.implement(|_, _| {}, ()))
+                .ok();
+            if self.viewport.is_none() {
+                return false;
+            }
+        }
+        // The protocol rejects empty destinations
+        self.destination = size.map(|(width, height)| (width.max(1), height.max(1)));
+        true
+    }
+
+    fn set_buffer_count(&mut self, count: usize) -> Result<(), DrawError> {
+        self.buffer_count = count.max(2);
+        while self.slots.len() < self.buffer_count {
+            self.slots
+                .push(Slot::new(&self.env).map_err(DrawError::PoolAllocation)?);
+        }
+        Ok(())
+    }
+
+    fn set_wait_for_buffer(&mut self, wait: bool) {
+        self.wait_for_buffer = wait;
+    }
+
+    fn frame_ready(&mut self) -> Result<bool, DrawError> {
+        self.read_events()?;
+        Ok(self.frame_ready.load(Ordering::SeqCst))
+    }
+
+    fn set_retain_frame(&mut self, retain: bool) {
+        self.retain_frame = retain;
+        if !retain {
+            self.retained = None;
+        }
+    }
+
+    fn last_frame(&self) -> Option<&PresentedFrame> {
+        self.retained.as_ref()
+    }
+
+    fn size(&self) -> Option<(usize, usize)> {
+        self.dimensions
+    }
+
+    fn formats(&self) -> &[PixelFormat] {
+        &self.formats
+    }
+}
+
+impl Drop for WaylandBackend {
+    fn drop(&mut self) {
+        if let Some(viewport) = self.viewport.take() {
+            viewport.destroy();
+        }
+        if let Some(viewporter) = self.viewporter.take() {
+            viewporter.destroy();
         }
     }
 }
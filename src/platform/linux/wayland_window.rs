@@ -0,0 +1,402 @@
+use std::sync::{Arc, Mutex};
+
+use sctk::keyboard::{map_keyboard_auto, Event as KeyboardEvent, KeyState};
+use sctk::reexports::client::protocol::wl_pointer::{self, ButtonState};
+use sctk::reexports::client::protocol::wl_seat::RequestsTrait as SeatRequests;
+use sctk::reexports::client::protocol::{wl_seat, wl_surface};
+use sctk::reexports::client::{Display, Proxy};
+use sctk::window::{ConceptFrame, Event as WindowEvent, Window};
+use sctk::Environment;
+
+use super::wayland::WaylandBackend;
+use crate::{
+    AlphaMode, Backend, Canvas, CreationError, DrawError, FrameBuffer, FrameStatus, Layout,
+    PixelFormat, PresentMode, Rect,
+};
+
+/// Event reported by a `WaylandWindow`
+///
+/// Input comes from the first seat of the compositor. Keys are not repeated while held and
+/// without libxkbcommon no keyboard events are reported at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaylandEvent {
+    /// The compositor resized the window, to a size in logical pixels
+    Resized(usize, usize),
+    /// The window moved to outputs with a different integer scale factor
+    ScaleFactorChanged(i32),
+    /// The user asked to close the window, for example with the close button
+    CloseRequested,
+    /// The window gained or lost the keyboard focus
+    Focused(bool),
+    /// A key was pressed, identified by its xkb keysym and its evdev scancode
+    KeyPressed { keysym: u32, scancode: u32 },
+    /// A key was released, identified by its xkb keysym and its evdev scancode
+    KeyReleased { keysym: u32, scancode: u32 },
+    /// A character was typed, following the `KeyPressed` that typed it
+    ReceivedCharacter(char),
+    /// The pointer entered or moved over the window contents, to a position in logical
+    /// pixels from their top left corner
+    PointerMoved(f64, f64),
+    /// The pointer left the window contents, possibly onto the decorations
+    PointerLeft,
+    /// A pointer button, identified by its evdev code such as `0x110` for the left button,
+    /// was pressed or released over the window contents
+    PointerButton { button: u32, pressed: bool },
+}
+
+/// A Wayland `xdg_toplevel` window opened with smithay-client-toolkit, without winit
+///
+/// The window draws its own decorations and binds the Wayland globals once, on a connection
+/// of its own. Events are read with `poll_events` or `wait_events`, which also keep the
+/// decorations up to date.
+pub struct WaylandWindow {
+    // Dropped before the backend so the toplevel is destroyed on a live connection
+    window: Window<ConceptFrame>,
+    backend: WaylandBackend,
+    /// Size of the window contents in logical pixels
+    size: (usize, usize),
+    scale: i32,
+    /// Events from the window and surface callbacks, waiting to be handed out
+    events: Arc<Mutex<Vec<PendingEvent>>>,
+}
+
+/// An event from a callback, before the window has acted on it
+enum PendingEvent {
+    Window(WindowEvent),
+    Scale(i32),
+    /// Input reported to the application as is
+    Input(WaylandEvent),
+}
+
+impl WaylandWindow {
+    /// Connect to the compositor named by the environment and open a window titled `title`
+    /// with contents of `width` by `height` logical pixels
+    ///
+    /// This returns once the compositor has configured the window, so it can be drawn to
+    /// right away. Translucent windows need `set_alpha_mode`, windows start in
+    /// `AlphaMode::Opaque`.
+    pub fn new(title: &str, width: usize, height: usize) -> Result<Self, CreationError> {
+        let (display, mut event_queue) = Display::connect_to_env()
+            .map_err(|err| CreationError::NoBackendAvailable(Box::new(err)))?;
+        let env = Environment::from_display(&display, &mut event_queue)
+            .map_err(|err| CreationError::OsError(err.to_string()))?;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let surface_events = events.clone();
+        let surface = env.create_surface(move |scale, _| {
+            surface_events
+                .lock()
+                .unwrap()
+                .push(PendingEvent::Scale(scale));
+        });
+        let window_events = events.clone();
+        let mut window = Window::<ConceptFrame>::init_from_env(
+            &env,
+            surface.clone(),
+            (width as u32, height as u32),
+            move |event| {
+                window_events
+                    .lock()
+                    .unwrap()
+                    .push(PendingEvent::Window(event));
+            },
+        )
+        .map_err(|err| CreationError::OsError(err.to_string()))?;
+        window.set_title(title.to_owned());
+        // Decorations need the pointer of a seat to move and resize the window
+        if let Ok(seat) = env
+            .manager
+            .instantiate_auto(|seat| seat.implement(|_, _| {}, ()))
+        {
+            window.new_seat(&seat);
+            listen_to_seat(&seat, window.surface(), &events);
+        }
+        let needs_configure = env.shell.needs_configure();
+
+        let mut backend = WaylandBackend::with_environment(display, event_queue, env, surface)?;
+        backend.set_alpha_mode(AlphaMode::Opaque);
+        let mut wayland = WaylandWindow {
+            window,
+            backend,
+            size: (width, height),
+            scale: 1,
+            events,
+        };
+        // Buffers may only be attached once xdg_shell has configured the window
+        let mut configured = !needs_configure;
+        while !configured {
+            wayland
+                .backend
+                .event_queue
+                .dispatch()
+                .map_err(|err| CreationError::OsError(err.to_string()))?;
+            configured =
+                wayland.events.lock().unwrap().iter().any(|event| {
+                    matches!(event, PendingEvent::Window(WindowEvent::Configure { .. }))
+                });
+        }
+        wayland.window.refresh();
+        Ok(wayland)
+    }
+
+    /// Read the events waiting on the connection and handle them, without blocking
+    pub fn poll_events<F: FnMut(WaylandEvent)>(&mut self, callback: F) -> Result<(), DrawError> {
+        self.backend.read_events()?;
+        self.handle_events(callback);
+        Ok(())
+    }
+
+    /// Wait for at least one message from the compositor, then handle the events that arrived
+    pub fn wait_events<F: FnMut(WaylandEvent)>(&mut self, callback: F) -> Result<(), DrawError> {
+        self.backend
+            .display
+            .flush()
+            .map_err(DrawError::CompositorDisconnected)?;
+        self.backend
+            .event_queue
+            .dispatch()
+            .map_err(DrawError::CompositorDisconnected)?;
+        self.handle_events(callback);
+        Ok(())
+    }
+
+    /// Act on the queued events and report the ones the application cares about
+    ///
+    /// Configures usually come in batches during an interactive resize, only the last size is
+    /// applied.
+    fn handle_events<F: FnMut(WaylandEvent)>(&mut self, mut callback: F) {
+        let events: Vec<PendingEvent> = self.events.lock().unwrap().drain(..).collect();
+        let mut resized = None;
+        let mut refresh = false;
+        for event in events {
+            match event {
+                PendingEvent::Window(WindowEvent::Configure { new_size, .. }) => {
+                    if let Some((width, height)) = new_size {
+                        resized = Some((width as usize, height as usize));
+                    }
+                    refresh = true;
+                }
+                PendingEvent::Window(WindowEvent::Refresh) => refresh = true,
+                PendingEvent::Window(WindowEvent::Close) => callback(WaylandEvent::CloseRequested),
+                PendingEvent::Scale(scale) if scale != self.scale => {
+                    self.scale = scale;
+                    self.backend.set_scale_factor(f64::from(scale));
+                    callback(WaylandEvent::ScaleFactorChanged(scale));
+                }
+                PendingEvent::Scale(_) => {}
+                PendingEvent::Input(event) => callback(event),
+            }
+        }
+        if let Some(size) = resized.filter(|&size| size != self.size) {
+            self.size = size;
+            self.window.resize(size.0 as u32, size.1 as u32);
+            callback(WaylandEvent::Resized(size.0, size.1));
+        }
+        if refresh {
+            self.window.refresh();
+        }
+    }
+
+    /// Set the title shown in the decorations and by the compositor
+    pub fn set_title(&mut self, title: &str) {
+        self.window.set_title(title.to_owned());
+        self.window.refresh();
+    }
+
+    /// Resize the window contents to `width` by `height` logical pixels
+    pub fn set_size(&mut self, width: usize, height: usize) {
+        self.size = (width, height);
+        self.window.resize(width as u32, height as u32);
+        self.window.refresh();
+    }
+
+    /// Allow or forbid the user to resize the window
+    pub fn set_resizable(&self, resizable: bool) {
+        self.window.set_resizable(resizable);
+    }
+
+    /// Size of the window contents in logical pixels
+    pub fn logical_size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    /// Size of the window contents in physical pixels, the size frames are drawn at
+    pub fn physical_size(&self) -> (usize, usize) {
+        let scale = self.scale as usize;
+        (self.size.0 * scale, self.size.1 * scale)
+    }
+
+    /// Number of physical pixels per logical pixel of the window
+    pub fn scale_factor(&self) -> i32 {
+        self.scale
+    }
+
+    /// Get reference to the backend frames are presented through
+    pub fn backend(&self) -> &dyn Backend {
+        &self.backend
+    }
+
+    /// Get mutable reference to the backend frames are presented through
+    pub fn backend_mut(&mut self) -> &mut dyn Backend {
+        &mut self.backend
+    }
+
+    /// Change how presenting frames is paced against the compositor
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        self.backend.set_present_mode(mode);
+    }
+
+    /// Change how the alpha channel of drawn pixels is interpreted
+    pub fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        self.backend.set_alpha_mode(mode);
+    }
+
+    /// Whether the compositor is ready for the next frame
+    pub fn frame_ready(&mut self) -> Result<bool, DrawError> {
+        self.backend.frame_ready()
+    }
+
+    /// Start a frame at the window size that is rendered straight into a shared memory pool
+    pub fn begin_frame(&mut self) -> Result<FrameBuffer<'_>, DrawError> {
+        let dimensions = self.physical_size();
+        FrameBuffer::new(&mut self.backend, dimensions)
+    }
+
+    /// Present a tightly packed frame at the window size in any `PixelFormat`
+    pub fn draw(&mut self, buffer: &[u8], format: PixelFormat) -> Result<FrameStatus, DrawError> {
+        let (width, height) = self.physical_size();
+        self.backend
+            .draw_with_layout(buffer, Layout::packed(width, height, format))
+    }
+
+    pub fn draw_argb8888(&mut self, buffer: &[[u8; 4]]) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.physical_size();
+        self.backend.draw_argb8888(dimensions, buffer)
+    }
+
+    pub fn draw_argb8888_bytes(&mut self, buffer: &[u8]) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.physical_size();
+        self.backend.draw_argb8888_bytes(dimensions, buffer)
+    }
+
+    pub fn draw_argb32(&mut self, buffer: &[u32]) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.physical_size();
+        self.backend.draw_argb32(dimensions, buffer)
+    }
+
+    /// Present the pixels of `canvas`, which should match the size of `physical_size`
+    pub fn draw_canvas(&mut self, canvas: &Canvas) -> Result<FrameStatus, DrawError> {
+        self.draw_argb32(&canvas.packed_pixels())
+    }
+
+    pub fn draw_damaged(
+        &mut self,
+        buffer: &[u32],
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        let dimensions = self.physical_size();
+        self.backend.draw_damaged(dimensions, buffer, damage)
+    }
+
+    pub fn draw_with_layout(
+        &mut self,
+        buffer: &[u8],
+        layout: Layout,
+    ) -> Result<FrameStatus, DrawError> {
+        self.backend.draw_with_layout(buffer, layout)
+    }
+}
+
+/// Queue the keyboard and pointer input of `seat` on the window contents `surface` as
+/// `PendingEvent::Input`
+fn listen_to_seat(
+    seat: &Proxy<wl_seat::WlSeat>,
+    surface: &Proxy<wl_surface::WlSurface>,
+    events: &Arc<Mutex<Vec<PendingEvent>>>,
+) {
+    let keyboard_events = events.clone();
+    // Without libxkbcommon keys cannot be interpreted, the window works without them
+    let _ = map_keyboard_auto(seat, move |event, _| {
+        let event = match event {
+            KeyboardEvent::Enter { .. } => WaylandEvent::Focused(true),
+            KeyboardEvent::Leave { .. } => WaylandEvent::Focused(false),
+            KeyboardEvent::Key {
+                rawkey,
+                keysym,
+                state,
+                utf8,
+                ..
+            } => {
+                let mut events = keyboard_events.lock().unwrap();
+                if state == KeyState::Pressed {
+                    events.push(PendingEvent::Input(WaylandEvent::KeyPressed {
+                        keysym,
+                        scancode: rawkey,
+                    }));
+                    let text = utf8.unwrap_or_default();
+                    events.extend(
+                        text.chars()
+                            .map(|c| PendingEvent::Input(WaylandEvent::ReceivedCharacter(c))),
+                    );
+                } else {
+                    events.push(PendingEvent::Input(WaylandEvent::KeyReleased {
+                        keysym,
+                        scancode: rawkey,
+                    }));
+                }
+                return;
+            }
+            KeyboardEvent::RepeatInfo { .. } | KeyboardEvent::Modifiers { .. } => return,
+        };
+        keyboard_events
+            .lock()
+            .unwrap()
+            .push(PendingEvent::Input(event));
+    });
+
+    let pointer_events = events.clone();
+    let surface = surface.clone();
+    // The pointer also moves over the decorations, which are surfaces of their own
+    let mut over_contents = false;
+    let _ = seat.get_pointer(move |pointer| {
+        pointer.implement(
+            move |event, _| {
+                let event = match event {
+                    wl_pointer::Event::Enter {
+                        surface: entered,
+                        surface_x,
+                        surface_y,
+                        ..
+                    } => {
+                        over_contents = entered == surface;
+                        if !over_contents {
+                            return;
+                        }
+                        WaylandEvent::PointerMoved(surface_x, surface_y)
+                    }
+                    wl_pointer::Event::Leave { .. } if over_contents => {
+                        over_contents = false;
+                        WaylandEvent::PointerLeft
+                    }
+                    wl_pointer::Event::Motion {
+                        surface_x,
+                        surface_y,
+                        ..
+                    } if over_contents => WaylandEvent::PointerMoved(surface_x, surface_y),
+                    wl_pointer::Event::Button { button, state, .. } if over_contents => {
+                        WaylandEvent::PointerButton {
+                            button,
+                            pressed: state == ButtonState::Pressed,
+                        }
+                    }
+                    _ => return,
+                };
+                pointer_events
+                    .lock()
+                    .unwrap()
+                    .push(PendingEvent::Input(event));
+            },
+            (),
+        )
+    });
+}
@@ -1,5 +1,46 @@
-use winit::os::unix::WindowExt;
-use x11_dl::xlib::{CopyFromParent, Display, Visual, Window, XImage, Xlib, ZPixmap, _XGC};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use winit::os::unix::{EventsLoopExt, WindowBuilderExt, WindowExt};
+use winit::{EventsLoop, WindowBuilder};
+use x11_dl::xlib::{
+    Display, LSBFirst, MSBFirst, TrueColor, Visual, Window, XErrorEvent, XImage, XVisualInfo,
+    XWindowAttributes, Xlib, ZPixmap, _XGC,
+};
+use x11_dl::xshm::{XShmSegmentInfo, Xext};
+
+use crate::backend::{check_len, frame_len};
+use crate::convert;
+use crate::{
+    AlphaMode, Backend, CreationError, DrawError, FrameStatus, Layout, PixelFormat, PresentedFrame,
+    Rect,
+};
+
+/// Ask winit to create the window with a 32 bit argb visual so it can be translucent
+///
+/// Winit always uses the default visual, which has no alpha channel. The builder is returned
+/// unchanged when not running on X11 or when the server offers no such visual.
+pub fn with_argb_visual(builder: WindowBuilder, events_loop: &EventsLoop) -> WindowBuilder {
+    let xconn = match events_loop.get_xlib_xconnection() {
+        Some(xconn) => xconn,
+        None => return builder,
+    };
+    let mut info: XVisualInfo = unsafe { std::mem::zeroed() };
+    let found = unsafe {
+        (xconn.xlib.XMatchVisualInfo)(
+            xconn.display,
+            (xconn.xlib.XDefaultScreen)(xconn.display),
+            32,
+            TrueColor,
+            &mut info,
+        )
+    };
+    if found == 0 {
+        builder
+    } else {
+        builder.with_x11_visual(&info as *const XVisualInfo)
+    }
+}
 
 pub struct X11Backend {
     display: *mut Display,
@@ -7,138 +48,672 @@ pub struct X11Backend {
     gc: *mut _XGC,
     xlib: Xlib,
     image: *mut XImage,
+    visual: *mut Visual,
+    /// Depth of the window, 32 when it was created with an argb visual
+    depth: u32,
+    alpha_mode: AlphaMode,
+    /// Loaded when the server supports MIT-SHM
+    xext: Option<Xext>,
+    shm: Option<ShmImage>,
+    retain_frame: bool,
+    /// Copy of the last frame presented while retaining frames
+    retained: Option<PresentedFrame>,
 }
 
 impl X11Backend {
-    pub fn new(window: &winit::Window) -> Option<X11Backend> {
+    /// Present to `window`, or `None` if it is not an X11 window
+    pub fn new(window: &winit::Window) -> Result<Option<X11Backend>, CreationError> {
         let mut x11 = None;
-        let xlib = Xlib::open().unwrap();
-        let dimensions: (u32, u32) = window.get_inner_size().unwrap().to_physical(1.).into();
-
         if let Some(display) = window.get_xlib_display() {
             let display = display as *mut Display;
-            if let Some(window) = window.get_xlib_window() {
-                let window = window as *mut Window;
-                let gc = unsafe { (xlib.XDefaultGC)(display, (xlib.XDefaultScreen)(display)) };
+            if let Some(xlib_window) = window.get_xlib_window() {
+                let xlib =
+                    Xlib::open().map_err(|err| CreationError::NoBackendAvailable(Box::new(err)))?;
+                let dimensions: (u32, u32) = window
+                    .get_inner_size()
+                    .ok_or(CreationError::OsError("window no longer exists".to_owned()))?
+                    .to_physical(window.get_hidpi_factor())
+                    .into();
+                let window = xlib_window as *mut Window;
+                // Images and the graphics context have to match the visual of the window,
+                // which differs from the screen default for translucent windows
+                let mut attributes: XWindowAttributes = unsafe { std::mem::zeroed() };
+                unsafe { (xlib.XGetWindowAttributes)(display, window as u64, &mut attributes) };
+                let (visual, depth) = (attributes.visual, attributes.depth as u32);
+                let gc =
+                    unsafe { (xlib.XCreateGC)(display, window as u64, 0, std::ptr::null_mut()) };
 
                 let image = unsafe {
-                    let depth =
-                        (xlib.XDefaultDepth)(display, (xlib.XDefaultScreen)(display)) as u32;
                     (xlib.XCreateImage)(
                         display,
-                        CopyFromParent as *mut Visual,
+                        visual,
                         depth,
                         ZPixmap,
                         0,
                         std::ptr::null_mut(),
-                        dimensions.0 as u32,
-                        dimensions.1 as u32,
+                        dimensions.0,
+                        dimensions.1,
                         32,
                         dimensions.0 as i32 * 4,
                     )
                 };
 
+                let xext = Xext::open()
+                    .ok()
+                    .filter(|xext| unsafe { (xext.XShmQueryExtension)(display) } != 0);
+
                 x11 = Some(X11Backend {
                     display,
                     window,
                     gc,
                     xlib,
                     image,
+                    visual,
+                    depth,
+                    alpha_mode: AlphaMode::Premultiplied,
+                    xext,
+                    shm: None,
+                    retain_frame: false,
+                    retained: None,
                 })
             }
         }
 
-        x11
+        Ok(x11)
     }
 
-    pub fn draw_argb8888(&mut self, dimensions: (usize, usize), buffer: &[[u8; 4]]) {
-        unsafe {
-            let mut new_buffer = Vec::new();
-
-            for pixel in buffer {
-                new_buffer.push(
-                    u32::from(pixel[0]) << 24
-                        | u32::from(pixel[1]) << 16
-                        | u32::from(pixel[2]) << 8
-                        | u32::from(pixel[3]),
+    /// The alpha conversion argb pixels need before presenting, if any
+    ///
+    /// Only windows with a 32 bit visual use the alpha channel, X compositors expect it
+    /// premultiplied.
+    fn alpha_conversion(&self) -> Option<AlphaMode> {
+        if self.depth == 32 && self.alpha_mode != AlphaMode::Premultiplied {
+            Some(self.alpha_mode)
+        } else {
+            None
+        }
+    }
+
+    /// Present `regions` of the argb frame `buffer` through the X protocol socket, converting
+    /// alpha on a copy when needed
+    fn put_argb32(&mut self, buffer: &[u32], dimensions: (usize, usize), regions: &[Rect]) {
+        match self.alpha_conversion() {
+            Some(mode) => {
+                let mut converted = buffer.to_vec();
+                convert::apply_alpha(mode, &mut converted);
+                self.put(
+                    converted.as_ptr() as *const u8,
+                    dimensions,
+                    dimensions.0 * 4,
+                    regions,
+                );
+            }
+            None => self.put(
+                buffer.as_ptr() as *const u8,
+                dimensions,
+                dimensions.0 * 4,
+                regions,
+            ),
+        }
+    }
+
+    /// Pixels of a shared memory image of `dimensions`, if MIT-SHM is usable
+    ///
+    /// The image is recreated whenever the dimensions change. If attaching a segment fails,
+    /// for example on a remote display, MIT-SHM is disabled for the lifetime of the backend.
+    fn shm_pixels(&mut self, dimensions: (usize, usize)) -> Option<&mut [u32]> {
+        if dimensions.0 == 0 || dimensions.1 == 0 {
+            return None;
+        }
+        let xext = self.xext.as_ref()?;
+        if self.shm.as_ref().map(|shm| shm.dimensions) != Some(dimensions) {
+            if let Some(mut shm) = self.shm.take() {
+                unsafe { shm.destroy(&self.xlib, xext, self.display) };
+            }
+            self.shm = unsafe {
+                ShmImage::new(
+                    &self.xlib,
+                    xext,
+                    self.display,
+                    self.visual,
+                    self.depth,
+                    dimensions,
                 )
+            };
+            if self.shm.is_none() {
+                self.xext = None;
+                return None;
             }
+        }
+        self.shm.as_mut().map(|shm| shm.pixels())
+    }
+
+    /// Copy the frame of `dimensions` at `data`, laid out as for `put`, if frames are retained
+    fn retain(&mut self, data: *const u8, dimensions: (usize, usize), bytes_per_line: usize) {
+        if !self.retain_frame {
+            return;
+        }
+        // Visuals without alpha ignore the top byte
+        let format = if self.depth == 32 {
+            PixelFormat::Argb8888
+        } else {
+            PixelFormat::Xrgb8888
+        };
+        let layout = Layout {
+            width: dimensions.0,
+            height: dimensions.1,
+            stride: bytes_per_line,
+            format,
+        };
+        let data = unsafe { std::slice::from_raw_parts(data, layout.min_len()) };
+        self.retained = Some(PresentedFrame::new(layout, data));
+    }
+
+    /// Present `regions` of the shared memory image filled through `shm_pixels`
+    ///
+    /// Callers present the whole image unless it holds the previous frame in the current
+    /// alpha mode.
+    fn shm_put(&mut self, regions: &[Rect]) {
+        if let Some(shm) = &mut self.shm {
+            shm.stale = false;
+            let (data, dimensions) = (shm.info.shmaddr as *const u8, shm.dimensions);
+            self.retain(data, dimensions, dimensions.0 * 4);
+        }
+        if let (Some(xext), Some(shm)) = (&self.xext, &self.shm) {
+            unsafe {
+                for rect in regions.iter().filter_map(|rect| rect.clip(shm.dimensions)) {
+                    (xext.XShmPutImage)(
+                        self.display,
+                        self.window as u64,
+                        self.gc,
+                        shm.image,
+                        rect.x as i32,
+                        rect.y as i32,
+                        rect.x as i32,
+                        rect.y as i32,
+                        rect.width as u32,
+                        rect.height as u32,
+                        0,
+                    );
+                }
+                (self.xlib.XSync)(self.display, 0);
+            }
+        }
+    }
 
-            (*self.image).data = new_buffer.as_slice().as_ptr() as *mut libc::c_char;
+    /// Present `regions` of `data` through the X protocol socket
+    ///
+    /// `data` must hold `height` rows of `bytes_per_line` bytes, the last row may omit padding.
+    fn put(
+        &mut self,
+        data: *const u8,
+        dimensions: (usize, usize),
+        bytes_per_line: usize,
+        regions: &[Rect],
+    ) {
+        self.retain(data, dimensions, bytes_per_line);
+        unsafe {
+            (*self.image).data = data as *mut libc::c_char;
             (*self.image).width = dimensions.0 as i32;
-            (*self.image).bytes_per_line = dimensions.0 as i32 * 4;
+            (*self.image).bytes_per_line = bytes_per_line as i32;
             (*self.image).height = dimensions.1 as i32;
 
-            (self.xlib.XPutImage)(
-                self.display,
-                self.window as u64,
-                self.gc,
-                self.image,
-                0,
-                0,
-                0,
-                0,
-                dimensions.0 as u32,
-                dimensions.1 as u32,
-            );
+            for rect in regions.iter().filter_map(|rect| rect.clip(dimensions)) {
+                (self.xlib.XPutImage)(
+                    self.display,
+                    self.window as u64,
+                    self.gc,
+                    self.image,
+                    rect.x as i32,
+                    rect.y as i32,
+                    rect.x as i32,
+                    rect.y as i32,
+                    rect.width as u32,
+                    rect.height as u32,
+                );
+            }
             (self.xlib.XSync)(self.display, 0);
         }
     }
+}
 
-    pub fn draw_argb8888_bytes(&mut self, dimensions: (usize, usize), buffer: &[u8]) {
-        unsafe {
-            let mut new_buffer = Vec::new();
-
-            for i in (0..buffer.len()).step_by(4) {
-                new_buffer.push(
-                    u32::from(buffer[i]) << 24
-                        | u32::from(buffer[i + 1]) << 16
-                        | u32::from(buffer[i + 2]) << 8
-                        | u32::from(buffer[i + 3]),
+impl Backend for X11Backend {
+    fn draw_argb8888(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[[u8; 4]],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        let full = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        let conversion = self.alpha_conversion();
+        if let Some(pixels) = self.shm_pixels(dimensions) {
+            convert::argb_bytes_to_argb32(convert::argb_arrays_as_bytes(buffer), pixels);
+            if let Some(mode) = conversion {
+                convert::apply_alpha(mode, pixels);
+            }
+            self.shm_put(&full);
+            return Ok(FrameStatus::Presented);
+        }
+
+        let mut new_buffer = vec![0; buffer.len()];
+        convert::argb_bytes_to_argb32(convert::argb_arrays_as_bytes(buffer), &mut new_buffer);
+        if let Some(mode) = conversion {
+            convert::apply_alpha(mode, &mut new_buffer);
+        }
+        self.put(
+            new_buffer.as_ptr() as *const u8,
+            dimensions,
+            dimensions.0 * 4,
+            &full,
+        );
+        Ok(FrameStatus::Presented)
+    }
+
+    fn draw_argb8888_bytes(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u8],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 4), buffer.len())?;
+        let full = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        let conversion = self.alpha_conversion();
+        if let Some(pixels) = self.shm_pixels(dimensions) {
+            convert::argb_bytes_to_argb32(buffer, pixels);
+            if let Some(mode) = conversion {
+                convert::apply_alpha(mode, pixels);
+            }
+            self.shm_put(&full);
+            return Ok(FrameStatus::Presented);
+        }
+
+        let mut new_buffer = vec![0; buffer.len() / 4];
+        convert::argb_bytes_to_argb32(buffer, &mut new_buffer);
+        if let Some(mode) = conversion {
+            convert::apply_alpha(mode, &mut new_buffer);
+        }
+        self.put(
+            new_buffer.as_ptr() as *const u8,
+            dimensions,
+            dimensions.0 * 4,
+            &full,
+        );
+        Ok(FrameStatus::Presented)
+    }
+
+    fn draw_argb32(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        let full = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        let conversion = self.alpha_conversion();
+        if let Some(pixels) = self.shm_pixels(dimensions) {
+            pixels.copy_from_slice(buffer);
+            if let Some(mode) = conversion {
+                convert::apply_alpha(mode, pixels);
+            }
+            self.shm_put(&full);
+            return Ok(FrameStatus::Presented);
+        }
+
+        self.put_argb32(buffer, dimensions, &full);
+        Ok(FrameStatus::Presented)
+    }
+
+    fn draw_with_layout(
+        &mut self,
+        buffer: &[u8],
+        layout: Layout,
+    ) -> Result<FrameStatus, DrawError> {
+        layout.check(buffer)?;
+        let dimensions = (layout.width, layout.height);
+        let full = [Rect::new(0, 0, layout.width, layout.height)];
+        let conversion = self.alpha_conversion();
+        if let Some(pixels) = self.shm_pixels(dimensions) {
+            for (y, row) in pixels.chunks_exact_mut(layout.width.max(1)).enumerate() {
+                let source = &buffer[y * layout.stride..y * layout.stride + layout.row_bytes()];
+                convert::to_argb32(layout.format, source, row);
+            }
+            if let Some(mode) = conversion {
+                convert::apply_alpha(mode, pixels);
+            }
+            self.shm_put(&full);
+            return Ok(FrameStatus::Presented);
+        }
+
+        if conversion.is_none() && self.formats().contains(&layout.format) {
+            self.put(buffer.as_ptr(), dimensions, layout.stride, &full);
+        } else {
+            let packed = layout.pack_argb32(buffer);
+            self.put_argb32(&packed, dimensions, &full);
+        }
+        Ok(FrameStatus::Presented)
+    }
+
+    fn draw_damaged(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        // A freshly created or stale shared image is presented completely
+        let full = [Rect::new(0, 0, dimensions.0, dimensions.1)];
+        let fresh = self
+            .shm
+            .as_ref()
+            .is_none_or(|shm| shm.stale || shm.dimensions != dimensions);
+        let conversion = self.alpha_conversion();
+        if let Some(pixels) = self.shm_pixels(dimensions) {
+            if fresh {
+                pixels.copy_from_slice(buffer);
+                if let Some(mode) = conversion {
+                    convert::apply_alpha(mode, pixels);
+                }
+            } else {
+                for rect in damage.iter().filter_map(|rect| rect.clip(dimensions)) {
+                    for y in rect.y..rect.y + rect.height {
+                        let start = y * dimensions.0 + rect.x;
+                        let row = &mut pixels[start..start + rect.width];
+                        row.copy_from_slice(&buffer[start..start + rect.width]);
+                        if let Some(mode) = conversion {
+                            convert::apply_alpha(mode, row);
+                        }
+                    }
+                }
+            }
+            self.shm_put(if fresh { &full } else { damage });
+            return Ok(FrameStatus::Presented);
+        }
+
+        self.put_argb32(buffer, dimensions, damage);
+        Ok(FrameStatus::Presented)
+    }
+
+    fn map_frame(&mut self, dimensions: (usize, usize)) -> Result<bool, DrawError> {
+        Ok(self.shm_pixels(dimensions).is_some())
+    }
+
+    fn mapped_pixels(&mut self) -> Option<&mut [u32]> {
+        self.shm.as_mut().map(|shm| shm.pixels())
+    }
+
+    fn present_mapped(&mut self) -> Result<FrameStatus, DrawError> {
+        let conversion = self.alpha_conversion();
+        match self.shm.as_mut() {
+            Some(shm) => {
+                let dimensions = shm.dimensions;
+                if let Some(mode) = conversion {
+                    convert::apply_alpha(mode, shm.pixels());
+                }
+                self.shm_put(&[Rect::new(0, 0, dimensions.0, dimensions.1)]);
+                Ok(FrameStatus::Presented)
+            }
+            None => Ok(FrameStatus::Dropped),
+        }
+    }
+
+    fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        if mode != self.alpha_mode {
+            // Pixels outside the damage of the next frame were converted for the old mode
+            if let Some(shm) = &mut self.shm {
+                shm.stale = true;
+            }
+        }
+        self.alpha_mode = mode;
+    }
+
+    fn set_retain_frame(&mut self, retain: bool) {
+        self.retain_frame = retain;
+        if !retain {
+            self.retained = None;
+        }
+    }
+
+    fn last_frame(&self) -> Option<&PresentedFrame> {
+        self.retained.as_ref()
+    }
+
+    fn read_back(&mut self) -> Option<PresentedFrame> {
+        let (width, height) = self.size()?;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        // Reading a window that is not viewable fails with an error instead of a null image
+        let (image, failed) = unsafe {
+            trap_errors(&self.xlib, self.display, || {
+                (self.xlib.XGetImage)(
+                    self.display,
+                    self.window as u64,
+                    0,
+                    0,
+                    width as u32,
+                    height as u32,
+                    !0,
+                    ZPixmap,
                 )
+            })
+        };
+        if image.is_null() {
+            return None;
+        }
+        if failed {
+            unsafe { (self.xlib.XDestroyImage)(image) };
+            return None;
+        }
+
+        let native = if cfg!(target_endian = "little") {
+            LSBFirst
+        } else {
+            MSBFirst
+        };
+        let mut pixels = vec![0; width * height];
+        unsafe {
+            if (*image).bits_per_pixel == 32 && (*image).byte_order == native {
+                let stride = (*image).bytes_per_line as usize;
+                for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+                    let source = (*image).data.add(y * stride) as *const u32;
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        *pixel = source.add(x).read_unaligned();
+                    }
+                }
+            } else {
+                // Let Xlib unpack unusual layouts, only true color visuals reach this
+                for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+                    for (x, pixel) in row.iter_mut().enumerate() {
+                        *pixel = (self.xlib.XGetPixel)(image, x as i32, y as i32) as u32;
+                    }
+                }
             }
+            (self.xlib.XDestroyImage)(image);
+        }
 
-            (*self.image).data = new_buffer.as_slice().as_ptr() as *mut libc::c_char;
-            (*self.image).width = dimensions.0 as i32;
-            (*self.image).bytes_per_line = dimensions.0 as i32 * 4;
-            (*self.image).height = dimensions.1 as i32;
+        let mut frame = PresentedFrame::from_argb32((width, height), &pixels);
+        if self.depth != 32 {
+            // The top byte of visuals without alpha is undefined
+            frame.layout.format = PixelFormat::Xrgb8888;
+        }
+        Some(frame)
+    }
 
-            (self.xlib.XPutImage)(
+    fn size(&self) -> Option<(usize, usize)> {
+        let (mut root, mut x, mut y) = (0, 0, 0);
+        let (mut width, mut height, mut border, mut depth) = (0, 0, 0, 0);
+        let status = unsafe {
+            (self.xlib.XGetGeometry)(
                 self.display,
                 self.window as u64,
-                self.gc,
-                self.image,
-                0,
-                0,
-                0,
-                0,
-                dimensions.0 as u32,
-                dimensions.1 as u32,
-            );
-            (self.xlib.XSync)(self.display, 0);
+                &mut root,
+                &mut x,
+                &mut y,
+                &mut width,
+                &mut height,
+                &mut border,
+                &mut depth,
+            )
+        };
+        if status == 0 {
+            None
+        } else {
+            Some((width as usize, height as usize))
+        }
+    }
+
+    fn formats(&self) -> &[PixelFormat] {
+        // Visuals without alpha ignore the top byte so both are laid out the way X expects
+        if self.depth == 32 {
+            &[PixelFormat::Argb8888]
+        } else {
+            &[PixelFormat::Argb8888, PixelFormat::Xrgb8888]
         }
     }
+}
 
-    pub fn draw_argb32(&mut self, dimensions: (usize, usize), buffer: &[u32]) {
+impl Drop for X11Backend {
+    fn drop(&mut self) {
+        if let (Some(xext), Some(mut shm)) = (&self.xext, self.shm.take()) {
+            unsafe { shm.destroy(&self.xlib, xext, self.display) };
+        }
         unsafe {
-            (*self.image).data = buffer.as_ptr() as *mut libc::c_char;
-            (*self.image).width = dimensions.0 as i32;
-            (*self.image).bytes_per_line = dimensions.0 as i32 * 4;
-            (*self.image).height = dimensions.1 as i32;
+            if !self.image.is_null() {
+                // The data belongs to the last drawn frame, not to Xlib's allocator
+                (*self.image).data = std::ptr::null_mut();
+                (self.xlib.XDestroyImage)(self.image);
+            }
+            (self.xlib.XFreeGC)(self.display, self.gc);
+        }
+    }
+}
 
-            (self.xlib.XPutImage)(
-                self.display,
-                self.window as u64,
-                self.gc,
-                self.image,
-                0,
-                0,
-                0,
-                0,
-                dimensions.0 as u32,
-                dimensions.1 as u32,
-            );
-            (self.xlib.XSync)(self.display, 0);
+/// Set by `error_handler` when the server rejects a request, such as a shared memory segment
+/// or reading back a window that is not viewable
+static X_ERROR: AtomicBool = AtomicBool::new(false);
+
+/// Held while `error_handler` is installed, the handler is process global
+static ERROR_HANDLER_LOCK: Mutex<()> = Mutex::new(());
+
+unsafe extern "C" fn error_handler(_: *mut Display, _: *mut XErrorEvent) -> libc::c_int {
+    X_ERROR.store(true, Ordering::SeqCst);
+    0
+}
+
+/// Run `request` on `display` with `error_handler` installed and wait for the server to
+/// process it
+///
+/// Returns the result of `request` and whether the server reported an error. Backends on
+/// other threads wait for the previous handler to be restored before installing theirs.
+unsafe fn trap_errors<T>(
+    xlib: &Xlib,
+    display: *mut Display,
+    request: impl FnOnce() -> T,
+) -> (T, bool) {
+    let _lock = ERROR_HANDLER_LOCK
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    X_ERROR.store(false, Ordering::SeqCst);
+    let previous = (xlib.XSetErrorHandler)(Some(error_handler));
+    let result = request();
+    (xlib.XSync)(display, 0);
+    (xlib.XSetErrorHandler)(previous);
+    (result, X_ERROR.load(Ordering::SeqCst))
+}
+
+/// An XImage whose pixels live in a System V shared memory segment attached to the server
+struct ShmImage {
+    image: *mut XImage,
+    // Boxed as the image keeps a pointer to it
+    info: Box<XShmSegmentInfo>,
+    dimensions: (usize, usize),
+    /// Whether the pixels may differ from the last presented frame in the current alpha mode,
+    /// so they have to be presented completely
+    stale: bool,
+}
+
+impl ShmImage {
+    unsafe fn new(
+        xlib: &Xlib,
+        xext: &Xext,
+        display: *mut Display,
+        visual: *mut Visual,
+        depth: u32,
+        dimensions: (usize, usize),
+    ) -> Option<ShmImage> {
+        let mut info = Box::new(XShmSegmentInfo {
+            shmseg: 0,
+            shmid: -1,
+            shmaddr: std::ptr::null_mut(),
+            readOnly: 0,
+        });
+        let image = (xext.XShmCreateImage)(
+            display,
+            visual,
+            depth,
+            ZPixmap,
+            std::ptr::null_mut(),
+            &mut *info,
+            dimensions.0 as u32,
+            dimensions.1 as u32,
+        );
+        if image.is_null() {
+            return None;
+        }
+        if (*image).bits_per_pixel != 32 || (*image).bytes_per_line as usize != dimensions.0 * 4 {
+            (xlib.XDestroyImage)(image);
+            return None;
+        }
+
+        let size = dimensions.0 * dimensions.1 * 4;
+        info.shmid = libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600);
+        if info.shmid < 0 {
+            (xlib.XDestroyImage)(image);
+            return None;
+        }
+        let address = libc::shmat(info.shmid, std::ptr::null(), 0);
+        if address as isize == -1 {
+            libc::shmctl(info.shmid, libc::IPC_RMID, std::ptr::null_mut());
+            (xlib.XDestroyImage)(image);
+            return None;
+        }
+        info.shmaddr = address as *mut libc::c_char;
+        (*image).data = info.shmaddr;
+
+        // Attaching fails asynchronously on remote displays, so trap the error
+        let (attached, failed) =
+            trap_errors(xlib, display, || (xext.XShmAttach)(display, &mut *info));
+        // The segment is freed as soon as both sides have detached
+        libc::shmctl(info.shmid, libc::IPC_RMID, std::ptr::null_mut());
+
+        if attached == 0 || failed {
+            libc::shmdt(address);
+            (*image).data = std::ptr::null_mut();
+            (xlib.XDestroyImage)(image);
+            return None;
+        }
+
+        Some(ShmImage {
+            image,
+            info,
+            dimensions,
+            stale: true,
+        })
+    }
+
+    fn pixels(&mut self) -> &mut [u32] {
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                self.info.shmaddr as *mut u32,
+                self.dimensions.0 * self.dimensions.1,
+            )
         }
     }
+
+    unsafe fn destroy(&mut self, xlib: &Xlib, xext: &Xext, display: *mut Display) {
+        (xext.XShmDetach)(display, &mut *self.info);
+        (xlib.XSync)(display, 0);
+        libc::shmdt(self.info.shmaddr as *const libc::c_void);
+        // The data belongs to the segment, not to Xlib's allocator
+        (*self.image).data = std::ptr::null_mut();
+        (xlib.XDestroyImage)(self.image);
+    }
 }
@@ -0,0 +1,104 @@
+use std::convert::TryFrom;
+use std::io;
+
+use winapi::shared::windef::{HDC, HGDIOBJ, HWND, RECT};
+use winapi::um::{wingdi, winuser};
+use winit::os::windows::WindowExt;
+
+use crate::backend::{check_len, frame_len};
+use crate::{Backend, DrawError, FrameStatus, PixelFormat};
+
+pub struct GdiBackend {
+    hwnd: HWND,
+    hdc: HDC,
+}
+
+impl GdiBackend {
+    pub fn new(window: &winit::Window) -> GdiBackend {
+        let hwnd = window.get_hwnd() as HWND;
+        let hdc = unsafe { winuser::GetDC(hwnd) };
+
+        GdiBackend { hwnd, hdc }
+    }
+
+    /// Copy the argb frame `pixels` of `dimensions` to the window through a temporary bitmap
+    fn blit(
+        &mut self,
+        dimensions: (usize, usize),
+        pixels: &[u32],
+    ) -> Result<FrameStatus, DrawError> {
+        let (width, height) = match (i32::try_from(dimensions.0), i32::try_from(dimensions.1)) {
+            (Ok(width), Ok(height)) => (width, height),
+            _ => {
+                return Err(DrawError::PoolAllocation(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "frame is too large for a GDI bitmap",
+                )))
+            }
+        };
+        unsafe {
+            let map = wingdi::CreateBitmap(
+                width,
+                height,
+                1,
+                32,
+                pixels.as_ptr() as *const std::ffi::c_void,
+            );
+            if map.is_null() {
+                return Err(DrawError::PoolAllocation(io::Error::last_os_error()));
+            }
+            let src = wingdi::CreateCompatibleDC(self.hdc);
+            if src.is_null() {
+                let err = io::Error::last_os_error();
+                wingdi::DeleteObject(map as HGDIOBJ);
+                return Err(DrawError::PoolAllocation(err));
+            }
+            let previous = wingdi::SelectObject(src, map as HGDIOBJ);
+
+            wingdi::BitBlt(self.hdc, 0, 0, width, height, src, 0, 0, wingdi::SRCCOPY);
+
+            // The bitmap can only be deleted once no device context has it selected
+            wingdi::SelectObject(src, previous);
+            wingdi::DeleteDC(src);
+            wingdi::DeleteObject(map as HGDIOBJ);
+        }
+        Ok(FrameStatus::Presented)
+    }
+}
+
+impl Backend for GdiBackend {
+    fn draw_argb32(
+        &mut self,
+        dimensions: (usize, usize),
+        buffer: &[u32],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(dimensions, 1), buffer.len())?;
+        self.blit(dimensions, buffer)
+    }
+
+    fn size(&self) -> Option<(usize, usize)> {
+        let mut rect = RECT {
+            left: 0,
+            top: 0,
+            right: 0,
+            bottom: 0,
+        };
+        if unsafe { winuser::GetClientRect(self.hwnd, &mut rect) } == 0 {
+            None
+        } else {
+            let width = usize::try_from(rect.right - rect.left).ok()?;
+            let height = usize::try_from(rect.bottom - rect.top).ok()?;
+            Some((width, height))
+        }
+    }
+
+    fn formats(&self) -> &[PixelFormat] {
+        &[PixelFormat::Argb8888]
+    }
+}
+
+impl Drop for GdiBackend {
+    fn drop(&mut self) {
+        unsafe { winuser::ReleaseDC(self.hwnd, self.hdc) };
+    }
+}
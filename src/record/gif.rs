@@ -0,0 +1,709 @@
+use std::collections::HashMap;
+use std::io::Write;
+
+use super::RecordedFrame;
+use crate::{ImageError, Rect};
+
+/// Shortest delay between frames in hundredths of a second, viewers slow down shorter ones
+const MIN_DELAY: u64 = 2;
+
+/// Colors of a frame palette, one more index is kept for transparency
+const MAX_COLORS: usize = 255;
+
+/// Largest code of the LZW compression
+const MAX_CODE: u16 = 4095;
+
+/// Encode `frames` as an endlessly looping animated GIF
+pub(crate) fn encode<W: Write>(frames: &[RecordedFrame], mut writer: W) -> Result<(), ImageError> {
+    let width = frames.iter().map(|frame| frame.width).max().unwrap_or(0);
+    let height = frames.iter().map(|frame| frame.height).max().unwrap_or(0);
+    if width > 0xFFFF || height > 0xFFFF {
+        return Err(ImageError::Unsupported(
+            "GIF frames larger than 65535 pixels",
+        ));
+    }
+
+    writer.write_all(b"GIF89a")?;
+    // Logical screen without a global color table
+    writer.write_all(&(width as u16).to_le_bytes())?;
+    writer.write_all(&(height as u16).to_le_bytes())?;
+    writer.write_all(&[0, 0, 0])?;
+    // Loop forever
+    writer.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+    let shown = shown_frames(frames);
+    let mut previous: Option<Vec<u32>> = None;
+    for (i, &(index, time)) in shown.iter().enumerate() {
+        let delay = match (shown.get(i + 1), i.checked_sub(1).map(|i| shown[i].1)) {
+            (Some(&(_, next)), _) => next - time,
+            // The last frame lasts as long as the one before it
+            (None, Some(before)) => time - before,
+            (None, None) => 10,
+        };
+        let screen = screen(&frames[index], width, height);
+        write_frame(&mut writer, &screen, previous.as_deref(), width, delay)?;
+        previous = Some(screen);
+    }
+    writer.write_all(&[0x3B])?;
+    Ok(())
+}
+
+/// Indices of the frames worth showing with the time they are shown at in hundredths of a
+/// second, leaving out frames identical to the one before
+///
+/// A frame following the last shown one too soon takes its place, keeping its time, so the
+/// latest of frames drawn in quick succession is shown.
+fn shown_frames(frames: &[RecordedFrame]) -> Vec<(usize, u64)> {
+    let mut shown: Vec<(usize, u64)> = Vec::new();
+    for (index, frame) in frames.iter().enumerate() {
+        let time = (frame.time.as_millis() as u64 + 5) / 10;
+        let (last, last_time) = match shown.last() {
+            Some(&last) => last,
+            None => {
+                shown.push((index, time));
+                continue;
+            }
+        };
+        if identical(&frames[last], frame) {
+            continue;
+        }
+        if time >= last_time + MIN_DELAY {
+            shown.push((index, time));
+            continue;
+        }
+        shown.pop();
+        // The replacement may show the same as the frame before it, which then lasts longer
+        match shown.last() {
+            Some(&(before, _)) if identical(&frames[before], frame) => {}
+            _ => shown.push((index, last_time)),
+        }
+    }
+    shown
+}
+
+/// Whether `a` and `b` have the same size and colors, ignoring alpha
+fn identical(a: &RecordedFrame, b: &RecordedFrame) -> bool {
+    a.width == b.width
+        && a.height == b.height
+        && a.pixels
+            .iter()
+            .zip(&b.pixels)
+            .all(|(a, b)| (a ^ b) & 0x00FF_FFFF == 0)
+}
+
+/// The rgb pixels of `frame` on a black screen of `width` by `height`
+fn screen(frame: &RecordedFrame, width: usize, height: usize) -> Vec<u32> {
+    let mut screen = vec![0; width * height];
+    if frame.width > 0 {
+        for (target, row) in screen
+            .chunks_exact_mut(width)
+            .zip(frame.pixels.chunks(frame.width))
+        {
+            for (target, pixel) in target.iter_mut().zip(row) {
+                *target = pixel & 0x00FF_FFFF;
+            }
+        }
+    }
+    screen
+}
+
+/// Write the part of `screen` that differs from `previous`, with pixels that did not change
+/// left transparent
+fn write_frame<W: Write>(
+    writer: &mut W,
+    screen: &[u32],
+    previous: Option<&[u32]>,
+    width: usize,
+    delay: u64,
+) -> Result<(), ImageError> {
+    let changed = |i: usize| previous.is_none_or(|previous| previous[i] != screen[i]);
+    let bounds = changed_bounds(screen.len(), width, changed);
+    // GIF frames cannot be empty, a frame without changes still needs a pixel
+    let bounds = bounds.unwrap_or(Rect::new(0, 0, 1.min(width), 1.min(screen.len())));
+    let rows = (bounds.y..bounds.y + bounds.height).map(|y| y * width + bounds.x);
+    let region = rows.flat_map(|start| start..start + bounds.width);
+
+    let colors: Vec<u32> = region
+        .clone()
+        .filter(|&i| changed(i))
+        .map(|i| screen[i])
+        .collect();
+    let (palette, index_of) = quantize(&colors);
+    let transparent = palette.len() as u8;
+    let indices: Vec<u8> = region
+        .map(|i| {
+            if changed(i) {
+                index_of(screen[i])
+            } else {
+                transparent
+            }
+        })
+        .collect();
+    let uses_transparency = indices.contains(&transparent);
+
+    // Graphic control extension, frames are left in place for the next frame to draw over
+    let flags = 1 << 2 | u8::from(uses_transparency);
+    writer.write_all(&[0x21, 0xF9, 0x04, flags])?;
+    writer.write_all(&(delay.min(0xFFFF) as u16).to_le_bytes())?;
+    writer.write_all(&[transparent, 0])?;
+
+    // Image descriptor with a local color table of 2^bits entries
+    let entries = palette.len() + usize::from(uses_transparency);
+    let bits = (usize::BITS - entries.saturating_sub(1).leading_zeros()).max(1);
+    writer.write_all(&[0x2C])?;
+    for value in &[bounds.x, bounds.y, bounds.width, bounds.height] {
+        writer.write_all(&(*value as u16).to_le_bytes())?;
+    }
+    writer.write_all(&[0x80 | (bits - 1) as u8])?;
+    let mut table = vec![0; 3 << bits];
+    for (entry, color) in table.chunks_exact_mut(3).zip(&palette) {
+        entry.copy_from_slice(&color.to_be_bytes()[1..]);
+    }
+    writer.write_all(&table)?;
+
+    let min_code_size = bits.max(2) as u8;
+    writer.write_all(&[min_code_size])?;
+    for block in compress(&indices, min_code_size).chunks(255) {
+        writer.write_all(&[block.len() as u8])?;
+        writer.write_all(block)?;
+    }
+    writer.write_all(&[0])?;
+    Ok(())
+}
+
+/// Smallest rectangle holding every pixel for which `changed` is true
+fn changed_bounds<F: Fn(usize) -> bool>(len: usize, width: usize, changed: F) -> Option<Rect> {
+    let (mut left, mut top, mut right, mut bottom) = (usize::MAX, usize::MAX, 0, 0);
+    for i in (0..len).filter(|&i| changed(i)) {
+        let (x, y) = (i % width, i / width);
+        left = left.min(x);
+        right = right.max(x + 1);
+        top = top.min(y);
+        bottom = bottom.max(y + 1);
+    }
+    if left == usize::MAX {
+        None
+    } else {
+        Some(Rect::new(left, top, right - left, bottom - top))
+    }
+}
+
+/// A palette of at most `MAX_COLORS` for `colors`, together with a function mapping every
+/// one of `colors` to its palette index
+///
+/// Colors are used as they are when there are few enough of them, otherwise they are reduced
+/// to 5 bits per channel and split by median cut.
+fn quantize(colors: &[u32]) -> (Vec<u32>, Box<dyn Fn(u32) -> u8>) {
+    let mut exact = HashMap::new();
+    for &color in colors {
+        let next = exact.len();
+        exact.entry(color).or_insert(next);
+        if exact.len() > MAX_COLORS {
+            break;
+        }
+    }
+    if exact.len() <= MAX_COLORS {
+        let mut palette = vec![0; exact.len()];
+        for (&color, &index) in &exact {
+            palette[index] = color;
+        }
+        return (palette, Box::new(move |color| exact[&color] as u8));
+    }
+
+    let mut bins = vec![Bin::default(); 1 << 15];
+    for &color in colors {
+        let bin = &mut bins[key(color)];
+        bin.count += 1;
+        for (sum, shift) in bin.sums.iter_mut().zip(&[16, 8, 0]) {
+            *sum += u64::from((color >> shift) & 0xFF);
+        }
+    }
+    let mut used: Vec<usize> = (0..bins.len()).filter(|&key| bins[key].count > 0).collect();
+    let mut boxes = Vec::with_capacity(MAX_COLORS);
+    boxes.push(0..used.len());
+    while boxes.len() < MAX_COLORS {
+        // Split the box whose widest channel spans the most pixels
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.len() > 1)
+            .map(|(i, range)| {
+                let (channel, extent) = widest_channel(&used[range.clone()]);
+                let count: u64 = used[range.clone()].iter().map(|&key| bins[key].count).sum();
+                (i, channel, extent as u64 * count)
+            })
+            .max_by_key(|&(_, _, score)| score);
+        let (i, channel, _) = match widest {
+            Some(widest) => widest,
+            None => break,
+        };
+        let range = boxes[i].clone();
+        let keys = &mut used[range.clone()];
+        keys.sort_unstable_by_key(|&key| (key >> (10 - 5 * channel)) & 0x1F);
+        // Split at the median pixel, keeping both halves non-empty
+        let total: u64 = keys.iter().map(|&key| bins[key].count).sum();
+        let mut count = 0;
+        let mut split = 1;
+        for (j, &key) in keys.iter().enumerate() {
+            count += bins[key].count;
+            if count * 2 >= total {
+                split = (j + 1).min(keys.len() - 1).max(1);
+                break;
+            }
+        }
+        boxes[i] = range.start..range.start + split;
+        boxes.push(range.start + split..range.end);
+    }
+
+    let mut lookup = vec![0u8; 1 << 15];
+    let palette = boxes
+        .iter()
+        .enumerate()
+        .map(|(index, range)| {
+            let (mut count, mut sums) = (0, [0; 3]);
+            for &key in &used[range.clone()] {
+                lookup[key] = index as u8;
+                count += bins[key].count;
+                for (sum, bin_sum) in sums.iter_mut().zip(&bins[key].sums) {
+                    *sum += bin_sum;
+                }
+            }
+            let channel = |sum: u64| ((sum + count / 2) / count) as u32;
+            channel(sums[0]) << 16 | channel(sums[1]) << 8 | channel(sums[2])
+        })
+        .collect();
+    (palette, Box::new(move |color| lookup[key(color)]))
+}
+
+/// Number of pixels and sums of their red, green and blue channels in a color bin
+#[derive(Debug, Clone, Copy, Default)]
+struct Bin {
+    count: u64,
+    sums: [u64; 3],
+}
+
+/// Bin of `color` with 5 bits per channel, red in the most significant bits
+fn key(color: u32) -> usize {
+    (((color >> 19) & 0x1F) << 10 | ((color >> 11) & 0x1F) << 5 | ((color >> 3) & 0x1F)) as usize
+}
+
+/// The channel, 0 for red to 2 for blue, whose values spread furthest across `keys` and how far
+fn widest_channel(keys: &[usize]) -> (usize, usize) {
+    (0..3)
+        .map(|channel| {
+            let values = keys.iter().map(|&key| (key >> (10 - 5 * channel)) & 0x1F);
+            let extent = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, extent)
+        })
+        .max_by_key(|&(_, extent)| extent)
+        .unwrap_or((0, 0))
+}
+
+/// Compress `indices` with the variable code length LZW of GIF
+fn compress(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut output = BitWriter::default();
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut width = u32::from(min_code_size) + 1;
+    let mut next = end + 1;
+    output.write(clear, width);
+
+    let mut indices = indices.iter();
+    let mut prefix = match indices.next() {
+        Some(&index) => u16::from(index),
+        None => {
+            output.write(end, width);
+            return output.finish();
+        }
+    };
+    for &index in indices {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        output.write(prefix, width);
+        if next <= MAX_CODE {
+            // Codes grow a bit once the next code no longer fits, as decoders expect
+            if next == 1 << width && width < 12 {
+                width += 1;
+            }
+            table.insert((prefix, index), next);
+            next += 1;
+        } else {
+            output.write(clear, width);
+            table.clear();
+            width = u32::from(min_code_size) + 1;
+            next = end + 1;
+        }
+        prefix = u16::from(index);
+    }
+    output.write(prefix, width);
+    if next == 1 << width && width < 12 {
+        width += 1;
+    }
+    output.write(end, width);
+    output.finish()
+}
+
+/// Packs codes into bytes starting from the least significant bit
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, width: u32) {
+        self.buffer |= u32::from(code) << self.bits;
+        self.bits += width;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A 1 by 1 frame of `color` recorded after `millis`
+    fn frame(millis: u64, color: u32) -> RecordedFrame {
+        RecordedFrame {
+            time: Duration::from_millis(millis),
+            width: 1,
+            height: 1,
+            pixels: vec![color],
+        }
+    }
+
+    /// Logical screen descriptor and looping extension `encode` starts a file with
+    fn header(width: u16, height: u16) -> Vec<u8> {
+        let mut data = b"GIF89a".to_vec();
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&[0, 0, 0]);
+        data.extend_from_slice(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00");
+        data
+    }
+
+    /// A frame of a decoded GIF
+    struct Decoded {
+        delay: u16,
+        /// Left, top, width and height of the image descriptor
+        bounds: (usize, usize, usize, usize),
+        /// Colors in the local color table
+        colors: usize,
+        transparent: Option<u8>,
+        /// The screen after the frame is drawn onto it
+        screen: Vec<u32>,
+    }
+
+    /// Decode a file as written by `encode`, the way a viewer would
+    fn decode(data: &[u8]) -> Vec<Decoded> {
+        assert_eq!(&data[..6], b"GIF89a");
+        let u16_at = |i: usize| usize::from(u16::from_le_bytes([data[i], data[i + 1]]));
+        let width = u16_at(6);
+        let mut screen = vec![0; width * u16_at(8)];
+        let mut frames = Vec::new();
+        let (mut delay, mut transparent) = (0, None);
+        let mut i = 32;
+        loop {
+            match data[i] {
+                0x21 => {
+                    assert_eq!(&data[i + 1..i + 3], &[0xF9, 0x04]);
+                    // Frames are left in place
+                    assert_eq!(data[i + 3] >> 2 & 7, 1);
+                    delay = u16_at(i + 4) as u16;
+                    transparent = Some(data[i + 6]).filter(|_| data[i + 3] & 1 == 1);
+                    i += 8;
+                }
+                0x2C => {
+                    let (x, y, w, h) = (u16_at(i + 1), u16_at(i + 3), u16_at(i + 5), u16_at(i + 7));
+                    assert_eq!(data[i + 9] & 0x80, 0x80);
+                    let colors = 1 << ((data[i + 9] & 7) + 1);
+                    let table = &data[i + 10..i + 10 + colors * 3];
+                    i += 10 + colors * 3;
+                    let min_code_size = data[i];
+                    let mut compressed = Vec::new();
+                    i += 1;
+                    while data[i] != 0 {
+                        let len = usize::from(data[i]);
+                        compressed.extend_from_slice(&data[i + 1..i + 1 + len]);
+                        i += 1 + len;
+                    }
+                    i += 1;
+                    let indices = decompress(&compressed, min_code_size);
+                    assert_eq!(indices.len(), w * h);
+                    for (j, &index) in indices.iter().enumerate() {
+                        if Some(index) != transparent {
+                            let color = &table[usize::from(index) * 3..][..3];
+                            screen[(y + j / w) * width + x + j % w] = u32::from(color[0]) << 16
+                                | u32::from(color[1]) << 8
+                                | u32::from(color[2]);
+                        }
+                    }
+                    frames.push(Decoded {
+                        delay,
+                        bounds: (x, y, w, h),
+                        colors,
+                        transparent,
+                        screen: screen.clone(),
+                    });
+                }
+                0x3B => break,
+                block => panic!("unexpected block {:#X}", block),
+            }
+        }
+        assert_eq!(i + 1, data.len());
+        frames
+    }
+
+    /// Decompress the variable code length LZW of GIF, independently of `compress`
+    fn decompress(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let initial: Vec<Vec<u8>> = (0..=end).map(|code| vec![code as u8]).collect();
+        let mut table = initial.clone();
+        let mut width = u32::from(min_code_size) + 1;
+        let mut previous: Option<usize> = None;
+        let mut output = Vec::new();
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0, data.iter());
+        loop {
+            while bits < width {
+                buffer |= u32::from(*bytes.next().expect("data ends with the end code")) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << width) - 1)) as usize;
+            buffer >>= width;
+            bits -= width;
+            if code == clear {
+                table = initial.clone();
+                width = u32::from(min_code_size) + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let entry = match table.get(code) {
+                Some(entry) => entry.clone(),
+                None => {
+                    // The code being defined by this very read
+                    assert_eq!(code, table.len());
+                    let mut entry = table[previous.expect("a code before")].clone();
+                    entry.push(entry[0]);
+                    entry
+                }
+            };
+            if let Some(previous) = previous {
+                if table.len() <= usize::from(MAX_CODE) {
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                }
+            }
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+            if table.len() == 1 << width && width < 12 {
+                width += 1;
+            }
+        }
+        assert!(bytes.next().is_none());
+        output
+    }
+
+    #[test]
+    fn skips_identical_frames() {
+        let frames = [
+            frame(0, 0xFF11_2233),
+            // Alpha is ignored
+            frame(100, 0x0011_2233),
+            frame(200, 0xFF44_5566),
+        ];
+        assert_eq!(shown_frames(&frames), [(0, 0), (2, 20)]);
+
+        let mut larger = frame(100, 0xFF11_2233);
+        larger.width = 2;
+        larger.pixels.push(0xFF11_2233);
+        assert_eq!(
+            shown_frames(&[frame(0, 0xFF11_2233), larger]),
+            [(0, 0), (1, 10)]
+        );
+    }
+
+    #[test]
+    fn replaces_frames_following_too_soon() {
+        // The latest frame is shown at the time of the one it replaces
+        let frames = [frame(0, 1), frame(14, 2), frame(100, 3), frame(105, 4)];
+        assert_eq!(shown_frames(&frames), [(1, 0), (3, 10)]);
+        let frames = [frame(0, 1), frame(100, 2), frame(110, 3), frame(114, 4)];
+        assert_eq!(shown_frames(&frames), [(0, 0), (3, 10)]);
+        // Frames spaced by exactly the minimum delay are kept
+        let frames = [frame(0, 1), frame(20, 2), frame(40, 3)];
+        assert_eq!(shown_frames(&frames), [(0, 0), (1, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn drops_replacements_identical_to_the_frame_before() {
+        let frames = [frame(0, 1), frame(100, 2), frame(110, 1), frame(300, 3)];
+        assert_eq!(shown_frames(&frames), [(0, 0), (3, 30)]);
+    }
+
+    #[test]
+    fn round_trips_frames() {
+        let colors = [0x00_0000, 0xFF_0000, 0x00_FF00, 0x12_3456];
+        let frames: Vec<RecordedFrame> = (0..3)
+            .map(|i| RecordedFrame {
+                time: Duration::from_millis([0, 100, 250][i]),
+                width: 3,
+                height: 2,
+                pixels: (0..6).map(|j| 0xFF00_0000 | colors[(i + j) % 4]).collect(),
+            })
+            .collect();
+        let mut data = Vec::new();
+        encode(&frames, &mut data).unwrap();
+        assert_eq!(&data[..32], &header(3, 2)[..]);
+        let decoded = decode(&data);
+        assert_eq!(decoded.len(), 3);
+        for (decoded, frame) in decoded.iter().zip(&frames) {
+            let pixels: Vec<u32> = frame.pixels.iter().map(|pixel| pixel & 0xFF_FFFF).collect();
+            assert_eq!(decoded.screen, pixels);
+        }
+        // The last frame lasts as long as the one before it
+        let delays: Vec<u16> = decoded.iter().map(|frame| frame.delay).collect();
+        assert_eq!(delays, [10, 15, 15]);
+    }
+
+    #[test]
+    fn draws_smaller_frames_on_a_black_screen() {
+        let mut small = frame(100, 0xFF12_3456);
+        small.time = Duration::from_millis(0);
+        let mut large = RecordedFrame {
+            time: Duration::from_millis(100),
+            width: 2,
+            height: 2,
+            pixels: vec![0xFF12_3456; 4],
+        };
+        large.pixels[3] = 0xFF65_4321;
+        let mut data = Vec::new();
+        encode(&[small, large], &mut data).unwrap();
+        let decoded = decode(&data);
+        assert_eq!(decoded[0].screen, [0x12_3456, 0, 0, 0]);
+        assert_eq!(
+            decoded[1].screen,
+            [0x12_3456, 0x12_3456, 0x12_3456, 0x65_4321]
+        );
+    }
+
+    #[test]
+    fn stores_the_changed_region() {
+        let previous = vec![0x10_1010; 20];
+        let mut screen = previous.clone();
+        screen[6] = 0x20_2020;
+        screen[13] = 0x30_3030;
+        let changed = |i: usize| previous[i] != screen[i];
+        assert_eq!(changed_bounds(20, 5, changed), Some(Rect::new(1, 1, 3, 2)));
+        assert_eq!(changed_bounds(20, 5, |_| false), None);
+
+        let mut data = header(5, 4);
+        write_frame(&mut data, &previous, None, 5, 10).unwrap();
+        write_frame(&mut data, &screen, Some(&previous), 5, 10).unwrap();
+        // A frame without changes still stores a transparent pixel
+        write_frame(&mut data, &screen, Some(&screen), 5, 10).unwrap();
+        data.push(0x3B);
+        let decoded = decode(&data);
+        assert_eq!(decoded[0].bounds, (0, 0, 5, 4));
+        assert_eq!(decoded[0].transparent, None);
+        assert_eq!(decoded[1].bounds, (1, 1, 3, 2));
+        assert_eq!(decoded[1].transparent, Some(2));
+        assert_eq!(decoded[1].screen, screen);
+        assert_eq!(decoded[2].bounds, (0, 0, 1, 1));
+        assert_eq!(decoded[2].transparent, Some(0));
+        assert_eq!(decoded[2].screen, screen);
+    }
+
+    #[test]
+    fn keeps_few_colors_exact() {
+        let colors: Vec<u32> = (0..MAX_COLORS as u32).map(|i| i * 0x01_0305).collect();
+        let (palette, index_of) = quantize(&colors);
+        assert_eq!(palette.len(), MAX_COLORS);
+        for &color in &colors {
+            assert_eq!(palette[usize::from(index_of(color))], color);
+        }
+    }
+
+    #[test]
+    fn reduces_many_colors_by_median_cut() {
+        // Every combination of 16 levels per channel
+        let colors: Vec<u32> = (0..4096u32)
+            .map(|i| {
+                let level = |shift: u32| ((i >> shift) & 15) * 0x11;
+                (level(8) << 16) | (level(4) << 8) | level(0)
+            })
+            .collect();
+        let (palette, index_of) = quantize(&colors);
+        assert!(palette.len() <= MAX_COLORS);
+        for &color in &colors {
+            let index = usize::from(index_of(color));
+            assert!(index < palette.len());
+            let error = (0..3)
+                .map(|channel| {
+                    let shift = channel * 8;
+                    ((color >> shift & 0xFF) as i32 - (palette[index] >> shift & 0xFF) as i32).abs()
+                })
+                .max()
+                .unwrap();
+            assert!(error <= 40, "{:06X} drawn as {:06X}", color, palette[index]);
+        }
+    }
+
+    #[test]
+    fn leaves_an_index_for_transparency() {
+        let previous: Vec<u32> = (0..32 * 32).map(|i| i * 0x0F_0301).collect();
+        let mut screen: Vec<u32> = previous.iter().map(|color| color ^ 0x80_8080).collect();
+        screen[0] = previous[0];
+        let mut data = header(32, 32);
+        write_frame(&mut data, &screen, Some(&previous), 32, 10).unwrap();
+        data.push(0x3B);
+        let decoded = decode(&data);
+        assert_eq!(decoded[0].colors, 256);
+        assert_eq!(decoded[0].transparent, Some(MAX_COLORS as u8));
+    }
+
+    #[test]
+    fn compresses_to_known_codes() {
+        // Clear, end
+        assert_eq!(compress(&[], 2), [0x2C]);
+        // Clear, 0, the code for 0 0, 0 and end, growing to 4 bits for the last
+        assert_eq!(compress(&[0; 4], 2), [0x84, 0x51]);
+    }
+
+    #[test]
+    fn compresses_losslessly() {
+        let mut state = 1u32;
+        let noise: Vec<u8> = (0..20_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        // Enough distinct strings to fill the table and clear it
+        assert_eq!(decompress(&compress(&noise, 8), 8), noise);
+        let low: Vec<u8> = noise.iter().map(|index| index & 3).collect();
+        assert_eq!(decompress(&compress(&low, 2), 2), low);
+        let run = vec![1; 100_000];
+        assert_eq!(decompress(&compress(&run, 2), 2), run);
+    }
+}
@@ -0,0 +1,155 @@
+//! Recording presented frames to animated GIFs and image sequences
+
+mod gif;
+
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::backend::{check_len, frame_len};
+use crate::{DrawError, Image, ImageError, ImageFormat};
+
+/// A frame captured by a `Recorder`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// Time since the first recorded frame
+    pub time: Duration,
+    pub width: usize,
+    pub height: usize,
+    /// Native endian argb pixels as they were drawn, row by row
+    pub pixels: Vec<u32>,
+}
+
+impl RecordedFrame {
+    /// The frame as an opaque image, the alpha channel is ignored
+    pub fn to_image(&self) -> Image {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|pixel| pixel | 0xFF00_0000)
+            .collect();
+        Image::new(self.width, self.height, pixels).expect("Recorded frames hold every pixel")
+    }
+}
+
+/// Captures frames with their timestamps to write them out as an animated GIF or a
+/// numbered image sequence
+///
+/// Attach it to a window with `YuxaWindow::set_recorder` or feed it frames with `record`.
+/// Frames are kept in memory uncompressed until the recorder is dropped or cleared, use frame
+/// skipping and a maximum duration to bound long recordings.
+#[derive(Debug, Clone, Default)]
+pub struct Recorder {
+    frames: Vec<RecordedFrame>,
+    /// Time the first frame was recorded
+    start: Option<Instant>,
+    frame_skip: usize,
+    /// Frames skipped since the last recorded frame
+    skipped: usize,
+    max_duration: Option<Duration>,
+    finished: bool,
+}
+
+impl Recorder {
+    pub fn new() -> Recorder {
+        Recorder::default()
+    }
+
+    /// Skip `skip` frames after every recorded frame, for example 1 to record every second frame
+    pub fn set_frame_skip(&mut self, skip: usize) {
+        self.frame_skip = skip;
+    }
+
+    /// Stop recording once `duration` has passed since the first recorded frame, or record
+    /// until the recorder is detached with `None`
+    pub fn set_max_duration(&mut self, duration: Option<Duration>) {
+        self.max_duration = duration;
+    }
+
+    /// Whether the maximum duration has passed, after which frames are no longer recorded
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Capture a frame of `dimensions` argb `pixels`, timestamped now
+    ///
+    /// Returns whether the frame was kept, frames are dropped by frame skipping and after the
+    /// maximum duration.
+    pub fn record(
+        &mut self,
+        dimensions: (usize, usize),
+        pixels: &[u32],
+    ) -> Result<bool, DrawError> {
+        check_len(frame_len(dimensions, 1), pixels.len())?;
+        let now = Instant::now();
+        let time = match self.start {
+            Some(start) => now.duration_since(start),
+            None => Duration::from_secs(0),
+        };
+        if self.max_duration.is_some_and(|max| time > max) {
+            self.finished = true;
+        }
+        if self.finished {
+            return Ok(false);
+        }
+        if !self.frames.is_empty() && self.skipped < self.frame_skip {
+            self.skipped += 1;
+            return Ok(false);
+        }
+        self.start.get_or_insert(now);
+        self.skipped = 0;
+        self.frames.push(RecordedFrame {
+            time,
+            width: dimensions.0,
+            height: dimensions.1,
+            pixels: pixels.to_vec(),
+        });
+        Ok(true)
+    }
+
+    /// Every recorded frame, oldest first
+    pub fn frames(&self) -> &[RecordedFrame] {
+        &self.frames
+    }
+
+    /// Drop every recorded frame and start a new recording
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.start = None;
+        self.skipped = 0;
+        self.finished = false;
+    }
+
+    /// Write the recording as an endlessly looping animated GIF to a file at `path`
+    pub fn save_gif<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_gif(&mut file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Write the recording as an endlessly looping animated GIF into `writer`
+    ///
+    /// Every frame gets a palette of up to 255 colors chosen by median cut, unless it has
+    /// fewer colors. Only the region that changed since the previous frame is stored, and
+    /// frames too close together for GIF's hundredth of a second timing are merged.
+    pub fn write_gif<W: Write>(&self, writer: W) -> Result<(), ImageError> {
+        gif::encode(&self.frames, writer)
+    }
+
+    /// Save every frame as an image file in `directory`, named `frame_00000` onwards with the
+    /// extension of `format`
+    pub fn save_sequence<P: AsRef<Path>>(
+        &self,
+        directory: P,
+        format: ImageFormat,
+    ) -> Result<(), ImageError> {
+        let directory = directory.as_ref();
+        std::fs::create_dir_all(directory)?;
+        for (index, frame) in self.frames.iter().enumerate() {
+            let name = format!("frame_{:05}.{}", index, format.extension());
+            frame.to_image().save(directory.join(name), format)?;
+        }
+        Ok(())
+    }
+}
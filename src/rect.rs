@@ -0,0 +1,30 @@
+/// An axis aligned rectangle in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// The part of the rectangle that lies within a frame of `dimensions`, if any
+    pub fn clip(&self, dimensions: (usize, usize)) -> Option<Rect> {
+        let right = (self.x.saturating_add(self.width)).min(dimensions.0);
+        let bottom = (self.y.saturating_add(self.height)).min(dimensions.1);
+        if self.x >= right || self.y >= bottom {
+            None
+        } else {
+            Some(Rect::new(self.x, self.y, right - self.x, bottom - self.y))
+        }
+    }
+}
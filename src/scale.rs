@@ -0,0 +1,387 @@
+use crate::backend::{check_len, frame_len};
+use crate::{Backend, DrawError, FrameBuffer, FrameStatus};
+
+/// How a frame of a fixed size is fit into a target of any size
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalePolicy {
+    /// Stretch the frame over the whole target, ignoring its aspect ratio
+    Stretch,
+    /// Scale by the largest whole factor that fits so every pixel stays crisp, filling the
+    /// rest with `border`
+    ///
+    /// Targets smaller than the frame fall back to `Fit`.
+    Integer { border: u32 },
+    /// Scale by the largest factor that fits while keeping the aspect ratio, filling the rest
+    /// with `border`
+    Fit { border: u32 },
+    /// Scale by the smallest factor that covers the target while keeping the aspect ratio,
+    /// cropping what overflows
+    Fill,
+}
+
+/// Where the scaled frame lands in the target, it may extend past the edges when cropped
+#[derive(Debug, Clone, Copy)]
+struct Placement {
+    x: isize,
+    y: isize,
+    width: usize,
+    height: usize,
+}
+
+/// Scales frames of a fixed size into targets of any size with nearest neighbour sampling
+///
+/// Set on a `YuxaWindow` with `YuxaWindow::set_scaler` to draw low resolution frames that
+/// are shown crisply at any window size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scaler {
+    size: (usize, usize),
+    policy: ScalePolicy,
+}
+
+impl Scaler {
+    /// Scale frames of `width` by `height` pixels according to `policy`
+    pub fn new(width: usize, height: usize, policy: ScalePolicy) -> Scaler {
+        Scaler {
+            size: (width, height),
+            policy,
+        }
+    }
+
+    /// Size of the frames being scaled
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    pub fn policy(&self) -> ScalePolicy {
+        self.policy
+    }
+
+    /// Scale the argb `source` frame into the `target` frame of `target_size`
+    pub fn scale(
+        &self,
+        source: &[u32],
+        target: &mut [u32],
+        target_size: (usize, usize),
+    ) -> Result<(), DrawError> {
+        check_len(frame_len(self.size, 1), source.len())?;
+        check_len(frame_len(target_size, 1), target.len())?;
+        let placement = self.placement(target_size);
+        let border = match self.policy {
+            ScalePolicy::Integer { border } | ScalePolicy::Fit { border } => border,
+            ScalePolicy::Stretch | ScalePolicy::Fill => 0,
+        };
+
+        // Source column of every target column, `None` in the border
+        let columns: Vec<Option<usize>> = (0..target_size.0)
+            .map(|x| source_offset(x, placement.x, placement.width, self.size.0))
+            .collect();
+        for (y, row) in target.chunks_exact_mut(target_size.0.max(1)).enumerate() {
+            match source_offset(y, placement.y, placement.height, self.size.1) {
+                Some(source_y) => {
+                    let source_row = &source[source_y * self.size.0..][..self.size.0];
+                    for (pixel, column) in row.iter_mut().zip(&columns) {
+                        *pixel = column.map_or(border, |source_x| source_row[source_x]);
+                    }
+                }
+                None => {
+                    for pixel in row {
+                        *pixel = border;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The source pixel shown at `position` of a target of `target_size`, `None` in the border
+    ///
+    /// Use this to map mouse coordinates back into the frame.
+    pub fn to_source(
+        &self,
+        target_size: (usize, usize),
+        position: (f64, f64),
+    ) -> Option<(usize, usize)> {
+        let placement = self.placement(target_size);
+        let x = position.0 - placement.x as f64;
+        let y = position.1 - placement.y as f64;
+        if x < 0. || y < 0. || x >= placement.width as f64 || y >= placement.height as f64 {
+            return None;
+        }
+        let x = (x * self.size.0 as f64 / placement.width as f64) as usize;
+        let y = (y * self.size.1 as f64 / placement.height as f64) as usize;
+        Some((x.min(self.size.0 - 1), y.min(self.size.1 - 1)))
+    }
+
+    /// Scale the argb `source` frame and present it through `backend` at `target_size`
+    pub(crate) fn present(
+        &self,
+        backend: &mut dyn Backend,
+        target_size: (usize, usize),
+        source: &[u32],
+    ) -> Result<FrameStatus, DrawError> {
+        check_len(frame_len(self.size, 1), source.len())?;
+        let mut frame = FrameBuffer::new(backend, target_size)?;
+        self.scale(source, frame.pixels(), target_size)?;
+        frame.present()
+    }
+
+    fn placement(&self, target_size: (usize, usize)) -> Placement {
+        let (width, height) = self.size;
+        let (target_width, target_height) = target_size;
+        if width == 0 || height == 0 {
+            return centered(target_size, (0, 0));
+        }
+        // Comparing cross products avoids rounding when comparing aspect ratios
+        let wider = target_width * height >= target_height * width;
+        let fit = if wider {
+            (width * target_height / height, target_height)
+        } else {
+            (target_width, height * target_width / width)
+        };
+        match self.policy {
+            ScalePolicy::Stretch => centered(target_size, target_size),
+            ScalePolicy::Integer { .. } => {
+                let factor = (target_width / width).min(target_height / height);
+                if factor == 0 {
+                    centered(target_size, fit)
+                } else {
+                    centered(target_size, (width * factor, height * factor))
+                }
+            }
+            ScalePolicy::Fit { .. } => centered(target_size, fit),
+            ScalePolicy::Fill => {
+                let fill = if wider {
+                    (target_width, height * target_width / width)
+                } else {
+                    (width * target_height / height, target_height)
+                };
+                centered(target_size, fill)
+            }
+        }
+    }
+}
+
+fn centered(target_size: (usize, usize), size: (usize, usize)) -> Placement {
+    Placement {
+        x: (target_size.0 as isize - size.0 as isize) / 2,
+        y: (target_size.1 as isize - size.1 as isize) / 2,
+        width: size.0,
+        height: size.1,
+    }
+}
+
+/// Source coordinate sampled at target coordinate `target` along one axis
+fn source_offset(
+    target: usize,
+    origin: isize,
+    extent: usize,
+    source_extent: usize,
+) -> Option<usize> {
+    let offset = target as isize - origin;
+    if offset < 0 || offset as usize >= extent {
+        None
+    } else {
+        Some(offset as usize * source_extent / extent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BORDER: u32 = 9;
+
+    /// Scale `source` of `size` into a target of `target_size` with `policy`
+    fn scale(
+        source: &[u32],
+        size: (usize, usize),
+        policy: ScalePolicy,
+        target_size: (usize, usize),
+    ) -> Vec<u32> {
+        let mut target = vec![0; target_size.0 * target_size.1];
+        Scaler::new(size.0, size.1, policy)
+            .scale(source, &mut target, target_size)
+            .unwrap();
+        target
+    }
+
+    #[test]
+    fn stretches_over_the_target() {
+        let target = scale(&[1, 2, 3, 4], (2, 2), ScalePolicy::Stretch, (3, 1));
+        assert_eq!(target, [1, 1, 2]);
+        let target = scale(&[1, 2], (2, 1), ScalePolicy::Stretch, (1, 2));
+        assert_eq!(target, [1, 1]);
+    }
+
+    #[test]
+    fn scales_by_whole_factors() {
+        let integer = ScalePolicy::Integer { border: BORDER };
+        let target = scale(&[1, 2, 3, 4], (2, 2), integer, (5, 5));
+        #[rustfmt::skip]
+        assert_eq!(target, [
+            1, 1, 2, 2, 9,
+            1, 1, 2, 2, 9,
+            3, 3, 4, 4, 9,
+            3, 3, 4, 4, 9,
+            9, 9, 9, 9, 9,
+        ]);
+        let target = scale(&[1, 2, 3, 4], (2, 2), integer, (7, 4));
+        #[rustfmt::skip]
+        assert_eq!(target, [
+            9, 1, 1, 2, 2, 9, 9,
+            9, 1, 1, 2, 2, 9, 9,
+            9, 3, 3, 4, 4, 9, 9,
+            9, 3, 3, 4, 4, 9, 9,
+        ]);
+    }
+
+    #[test]
+    fn falls_back_to_fit_in_small_targets() {
+        let source = [1, 2, 3, 4, 5, 6, 7, 8];
+        for &target_size in &[(3, 3), (4, 1), (1, 1), (2, 5)] {
+            assert_eq!(
+                scale(
+                    &source,
+                    (4, 2),
+                    ScalePolicy::Integer { border: BORDER },
+                    target_size
+                ),
+                scale(
+                    &source,
+                    (4, 2),
+                    ScalePolicy::Fit { border: BORDER },
+                    target_size
+                ),
+                "{:?}",
+                target_size
+            );
+        }
+        #[rustfmt::skip]
+        assert_eq!(
+            scale(&source, (4, 2), ScalePolicy::Integer { border: BORDER }, (3, 3)),
+            [
+                9, 9, 9,
+                1, 2, 3,
+                9, 9, 9,
+            ]
+        );
+    }
+
+    #[test]
+    fn letterboxes_with_the_border() {
+        let fit = ScalePolicy::Fit { border: BORDER };
+        let target = scale(&[1, 2, 3, 4], (2, 2), fit, (6, 4));
+        #[rustfmt::skip]
+        assert_eq!(target, [
+            9, 1, 1, 2, 2, 9,
+            9, 1, 1, 2, 2, 9,
+            9, 3, 3, 4, 4, 9,
+            9, 3, 3, 4, 4, 9,
+        ]);
+        let target = scale(&[1, 2, 3, 4], (2, 2), fit, (3, 7));
+        #[rustfmt::skip]
+        assert_eq!(target, [
+            9, 9, 9,
+            9, 9, 9,
+            1, 1, 2,
+            1, 1, 2,
+            3, 3, 4,
+            9, 9, 9,
+            9, 9, 9,
+        ]);
+        // Empty frames leave only the border
+        assert_eq!(scale(&[], (0, 3), fit, (2, 2)), [BORDER; 4]);
+        assert_eq!(scale(&[1], (1, 1), fit, (0, 0)), []);
+    }
+
+    #[test]
+    fn crops_the_overflow_evenly() {
+        // The frame is twice as wide as the target once scaled, a quarter is cropped each side
+        let target = scale(&[1, 2, 3, 4], (4, 1), ScalePolicy::Fill, (2, 2));
+        assert_eq!(target, [2, 3, 2, 3]);
+        let target = scale(&[1, 2, 3, 4], (1, 4), ScalePolicy::Fill, (2, 2));
+        assert_eq!(target, [2, 2, 3, 3]);
+        // Odd overflows crop the extra pixel at the end
+        let target = scale(&[1, 2, 3], (3, 1), ScalePolicy::Fill, (2, 1));
+        assert_eq!(target, [1, 2]);
+        let target = scale(&[1, 2, 3, 4], (2, 2), ScalePolicy::Fill, (4, 2));
+        assert_eq!(target, [1, 1, 2, 2, 3, 3, 4, 4]);
+    }
+
+    #[test]
+    fn rejects_wrong_lengths() {
+        let scaler = Scaler::new(2, 2, ScalePolicy::Stretch);
+        let mut target = vec![0; 4];
+        assert!(matches!(
+            scaler.scale(&[0; 3], &mut target, (2, 2)),
+            Err(DrawError::BufferSizeMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+        assert!(matches!(
+            scaler.scale(&[0; 4], &mut target, (3, 2)),
+            Err(DrawError::BufferSizeMismatch {
+                expected: 6,
+                actual: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn maps_positions_back_to_the_source() {
+        // Placed at x 1 to 5 of the target
+        let scaler = Scaler::new(2, 2, ScalePolicy::Integer { border: BORDER });
+        let to_source = |x, y| scaler.to_source((7, 4), (x, y));
+        assert_eq!(to_source(0.99, 0.), None);
+        assert_eq!(to_source(1., 0.), Some((0, 0)));
+        assert_eq!(to_source(2.99, 1.99), Some((0, 0)));
+        assert_eq!(to_source(3., 2.), Some((1, 1)));
+        assert_eq!(to_source(4.99, 3.99), Some((1, 1)));
+        assert_eq!(to_source(5., 0.), None);
+        assert_eq!(to_source(1., 4.), None);
+        assert_eq!(to_source(-1., -1.), None);
+
+        // Cropped edges map to the visible part of the frame
+        let scaler = Scaler::new(4, 1, ScalePolicy::Fill);
+        assert_eq!(scaler.to_source((2, 2), (0., 0.)), Some((1, 0)));
+        assert_eq!(scaler.to_source((2, 2), (1.99, 1.99)), Some((2, 0)));
+
+        let scaler = Scaler::new(2, 2, ScalePolicy::Stretch);
+        assert_eq!(scaler.to_source((3, 3), (2.999, 2.999)), Some((1, 1)));
+        assert_eq!(scaler.to_source((3, 3), (3., 0.)), None);
+        assert_eq!(scaler.to_source((0, 0), (0., 0.)), None);
+        assert_eq!(
+            Scaler::new(0, 0, ScalePolicy::Stretch).to_source((3, 3), (0., 0.)),
+            None
+        );
+    }
+
+    #[test]
+    fn agrees_with_the_scaled_frame() {
+        let source: Vec<u32> = (0..15).collect();
+        let policies = [
+            ScalePolicy::Stretch,
+            ScalePolicy::Integer { border: 99 },
+            ScalePolicy::Fit { border: 99 },
+            ScalePolicy::Fill,
+        ];
+        for &policy in &policies {
+            for &target_size in &[(5, 3), (11, 7), (4, 9), (16, 6), (2, 1)] {
+                let scaler = Scaler::new(5, 3, policy);
+                let target = scale(&source, (5, 3), policy, target_size);
+                for (i, &pixel) in target.iter().enumerate() {
+                    let position = ((i % target_size.0) as f64, (i / target_size.0) as f64);
+                    let expected = scaler
+                        .to_source(target_size, position)
+                        .map_or(99, |(x, y)| source[y * 5 + x]);
+                    assert_eq!(
+                        pixel, expected,
+                        "{:?} {:?} {:?}",
+                        policy, target_size, position
+                    );
+                }
+            }
+        }
+    }
+}
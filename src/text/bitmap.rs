@@ -0,0 +1,276 @@
+use crate::Canvas;
+
+/// Horizontal distance between the starts of two characters, in unscaled pixels
+pub const ADVANCE: usize = 6;
+/// Vertical distance between the tops of two lines, in unscaled pixels
+pub const LINE_HEIGHT: usize = 10;
+
+/// Width in pixels of every glyph, the rest of the advance is spacing
+const GLYPH_WIDTH: usize = 5;
+
+/// Size in pixels of `text` drawn with the built-in font at `scale`, saturating at
+/// `usize::MAX`
+pub fn measure(text: &str, scale: u32) -> (usize, usize) {
+    let scale = scale as usize;
+    let mut lines: usize = 0;
+    let mut widest = 0;
+    for line in text.split('\n') {
+        lines += 1;
+        widest = widest.max(line.chars().count());
+    }
+    let width = if widest == 0 {
+        0
+    } else {
+        (widest.saturating_mul(ADVANCE) - (ADVANCE - GLYPH_WIDTH)).saturating_mul(scale)
+    };
+    (
+        width,
+        lines.saturating_mul(LINE_HEIGHT).saturating_mul(scale),
+    )
+}
+
+impl<'a> Canvas<'a> {
+    /// Draw `text` with the built-in font, the top left corner of the first character at
+    /// `position`
+    ///
+    /// Every pixel of the font becomes a square of `scale` pixels. Lines are separated by
+    /// `'\n'`, and characters outside of printable ASCII are drawn as `'?'`.
+    pub fn draw_text(&mut self, position: (i32, i32), text: &str, scale: u32, color: u32) {
+        // Larger squares would reach past the end of any canvas anyway
+        let scale = scale.min(i32::MAX as u32) as i32;
+        // Offsets by `units` font pixels, anything beyond `i32` is past the canvas anyway
+        let step = |start: i32, units: usize| {
+            let position = i64::from(start) + units as i64 * i64::from(scale);
+            position.max(i64::from(i32::MIN)).min(i64::from(i32::MAX)) as i32
+        };
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let (mut x, mut y) = position;
+        for character in text.chars() {
+            if character == '\n' {
+                x = position.0;
+                y = step(y, LINE_HEIGHT);
+                continue;
+            }
+            // Once the pen is past the canvas, so is the rest of the line or the text
+            if i64::from(y) >= height {
+                break;
+            }
+            if i64::from(x) >= width {
+                continue;
+            }
+            for (row, &bits) in glyph(character).iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> column) != 0 {
+                        self.fill_rect(step(x, column), step(y, row), scale, scale, color);
+                    }
+                }
+            }
+            x = step(x, ADVANCE);
+        }
+    }
+}
+
+fn glyph(character: char) -> &'static [u8; 8] {
+    let index = match character {
+        ' '..='~' => character as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &GLYPHS[index]
+}
+
+/// Rows of the printable ASCII characters, the most significant of the five bits is the
+/// leftmost pixel. Capitals take the first seven rows, descenders reach into the eighth.
+#[rustfmt::skip]
+const GLYPHS: [[u8; 8]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04, 0x00], // '!'
+    [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00], // '"'
+    [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A, 0x00], // '#'
+    [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04, 0x00], // '$'
+    [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03, 0x00], // '%'
+    [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D, 0x00], // '&'
+    [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00], // '\''
+    [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02, 0x00], // '('
+    [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08, 0x00], // ')'
+    [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00, 0x00], // '*'
+    [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00, 0x00], // '+'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08], // ','
+    [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00, 0x00], // '-'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C, 0x00], // '.'
+    [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00, 0x00], // '/'
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E, 0x00], // '0'
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // '1'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F, 0x00], // '2'
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E, 0x00], // '3'
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02, 0x00], // '4'
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E, 0x00], // '5'
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E, 0x00], // '6'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08, 0x00], // '7'
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E, 0x00], // '8'
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C, 0x00], // '9'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00, 0x00], // ':'
+    [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08, 0x00], // ';'
+    [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02, 0x00], // '<'
+    [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00, 0x00], // '='
+    [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08, 0x00], // '>'
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04, 0x00], // '?'
+    [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E, 0x00], // '@'
+    [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00], // 'A'
+    [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E, 0x00], // 'B'
+    [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E, 0x00], // 'C'
+    [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C, 0x00], // 'D'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F, 0x00], // 'E'
+    [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10, 0x00], // 'F'
+    [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F, 0x00], // 'G'
+    [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11, 0x00], // 'H'
+    [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // 'I'
+    [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C, 0x00], // 'J'
+    [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11, 0x00], // 'K'
+    [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F, 0x00], // 'L'
+    [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11, 0x00], // 'M'
+    [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11, 0x00], // 'N'
+    [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // 'O'
+    [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10, 0x00], // 'P'
+    [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D, 0x00], // 'Q'
+    [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11, 0x00], // 'R'
+    [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E, 0x00], // 'S'
+    [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // 'T'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E, 0x00], // 'U'
+    [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // 'V'
+    [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A, 0x00], // 'W'
+    [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11, 0x00], // 'X'
+    [0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x04, 0x00], // 'Y'
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F, 0x00], // 'Z'
+    [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E, 0x00], // '['
+    [0x00, 0x10, 0x08, 0x04, 0x02, 0x01, 0x00, 0x00], // '\\'
+    [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E, 0x00], // ']'
+    [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00, 0x00], // '^'
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F], // '_'
+    [0x08, 0x04, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00], // '`'
+    [0x00, 0x00, 0x0E, 0x01, 0x0F, 0x11, 0x0F, 0x00], // 'a'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x1E, 0x00], // 'b'
+    [0x00, 0x00, 0x0E, 0x10, 0x10, 0x11, 0x0E, 0x00], // 'c'
+    [0x01, 0x01, 0x0D, 0x13, 0x11, 0x11, 0x0F, 0x00], // 'd'
+    [0x00, 0x00, 0x0E, 0x11, 0x1F, 0x10, 0x0E, 0x00], // 'e'
+    [0x06, 0x09, 0x08, 0x1C, 0x08, 0x08, 0x08, 0x00], // 'f'
+    [0x00, 0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 'g'
+    [0x10, 0x10, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // 'h'
+    [0x04, 0x00, 0x0C, 0x04, 0x04, 0x04, 0x0E, 0x00], // 'i'
+    [0x02, 0x00, 0x06, 0x02, 0x02, 0x02, 0x12, 0x0C], // 'j'
+    [0x10, 0x10, 0x12, 0x14, 0x18, 0x14, 0x12, 0x00], // 'k'
+    [0x0C, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E, 0x00], // 'l'
+    [0x00, 0x00, 0x1A, 0x15, 0x15, 0x15, 0x15, 0x00], // 'm'
+    [0x00, 0x00, 0x16, 0x19, 0x11, 0x11, 0x11, 0x00], // 'n'
+    [0x00, 0x00, 0x0E, 0x11, 0x11, 0x11, 0x0E, 0x00], // 'o'
+    [0x00, 0x00, 0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10], // 'p'
+    [0x00, 0x00, 0x0F, 0x11, 0x11, 0x0F, 0x01, 0x01], // 'q'
+    [0x00, 0x00, 0x16, 0x19, 0x10, 0x10, 0x10, 0x00], // 'r'
+    [0x00, 0x00, 0x0E, 0x10, 0x0E, 0x01, 0x1E, 0x00], // 's'
+    [0x08, 0x08, 0x1C, 0x08, 0x08, 0x09, 0x06, 0x00], // 't'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x13, 0x0D, 0x00], // 'u'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0A, 0x04, 0x00], // 'v'
+    [0x00, 0x00, 0x11, 0x11, 0x15, 0x15, 0x0A, 0x00], // 'w'
+    [0x00, 0x00, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x00], // 'x'
+    [0x00, 0x00, 0x11, 0x11, 0x11, 0x0F, 0x01, 0x0E], // 'y'
+    [0x00, 0x00, 0x1F, 0x02, 0x04, 0x08, 0x1F, 0x00], // 'z'
+    [0x02, 0x04, 0x04, 0x08, 0x04, 0x04, 0x02, 0x00], // '{'
+    [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x00], // '|'
+    [0x08, 0x04, 0x04, 0x02, 0x04, 0x04, 0x08, 0x00], // '}'
+    [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00, 0x00], // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLOR: u32 = 0xFFFF_FFFF;
+
+    /// Rows of a `width` by `height` canvas after `draw`, with drawn pixels as `#`
+    fn render<F: FnOnce(&mut Canvas)>((width, height): (usize, usize), draw: F) -> Vec<String> {
+        let mut pixels = vec![0; width * height];
+        draw(&mut Canvas::new(&mut pixels, width, height).unwrap());
+        pixels
+            .chunks(width)
+            .map(|row| {
+                row.iter()
+                    .map(|&pixel| if pixel == COLOR { '#' } else { '.' })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn draws_glyphs() {
+        let rows = render((12, 8), |canvas| canvas.draw_text((0, 0), "Ai", 1, COLOR));
+        assert_eq!(
+            rows,
+            [
+                ".###....#...",
+                "#...#.......",
+                "#...#..##...",
+                "#####...#...",
+                "#...#...#...",
+                "#...#...#...",
+                "#...#..###..",
+                "............",
+            ]
+        );
+    }
+
+    #[test]
+    fn draws_lines_and_unknown_characters() {
+        let rows = render((6, 20), |canvas| {
+            canvas.draw_text((0, 0), "T\n\u{E9}", 1, COLOR)
+        });
+        assert_eq!(rows[0], "#####.");
+        assert_eq!(rows[1], "..#...");
+        // Drawn as '?' on the next line
+        assert_eq!(rows[10], ".###..");
+        assert_eq!(rows[11], "#...#.");
+        assert_eq!(rows[16], "..#...");
+    }
+
+    #[test]
+    fn scales_glyphs() {
+        let rows = render((10, 4), |canvas| canvas.draw_text((-2, -2), "T", 2, COLOR));
+        assert_eq!(
+            rows,
+            ["..##......", "..##......", "..##......", "..##......"]
+        );
+        let rows = render((8, 1), |canvas| canvas.draw_text((-8, 0), "T", 2, COLOR));
+        assert_eq!(rows, ["##......"]);
+    }
+
+    #[test]
+    fn draws_at_extreme_scales_and_positions() {
+        // The first pixel of 'T' covers the canvas, the rest of the text is past it
+        let rows = render((3, 2), |canvas| {
+            canvas.draw_text((0, 0), &"T\nT".repeat(100_000), u32::MAX, COLOR);
+        });
+        assert_eq!(rows, ["###", "###"]);
+        let rows = render((3, 2), |canvas| {
+            canvas.draw_text((i32::MAX, i32::MAX), "TT\nTT", 1000, COLOR);
+            canvas.draw_text((i32::MIN, i32::MIN), "TT\nTT", u32::MAX, COLOR);
+            canvas.draw_text((0, 0), "TTT", 0, COLOR);
+        });
+        assert_eq!(rows, ["...", "..."]);
+    }
+
+    #[test]
+    fn measures_text() {
+        assert_eq!(measure("", 1), (0, LINE_HEIGHT));
+        assert_eq!(measure("Ai", 1), (11, 10));
+        assert_eq!(measure("ab\ncde\n", 2), (34, 60));
+        // Non-ASCII characters take a single glyph
+        assert_eq!(measure("\u{E9}", 3), (15, 30));
+        // Sizes saturate instead of wrapping
+        let scale = u32::MAX as usize;
+        assert_eq!(
+            measure(&"a".repeat(1000), u32::MAX),
+            (
+                5999usize.saturating_mul(scale),
+                10usize.saturating_mul(scale)
+            )
+        );
+    }
+}
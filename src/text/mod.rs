@@ -0,0 +1,14 @@
+//! Text rendering onto a `Canvas`
+//!
+//! A small bitmap font covering printable ASCII is built in and drawn with
+//! `Canvas::draw_text`, which is enough for debug text and simple HUDs. With the `truetype`
+//! feature enabled, any TrueType or OpenType `Font` can be drawn with anti-aliasing and
+//! kerning through `Canvas::draw_font_text`.
+
+mod bitmap;
+#[cfg(feature = "truetype")]
+mod truetype;
+
+pub use self::bitmap::{measure, ADVANCE, LINE_HEIGHT};
+#[cfg(feature = "truetype")]
+pub use self::truetype::Font;
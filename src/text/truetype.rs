@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use rusttype::{point, GlyphId, Scale};
+
+use crate::Canvas;
+
+/// Fractions of a pixel glyphs are positioned at horizontally
+const SUBPIXEL_STEPS: u32 = 4;
+
+/// Coverage of a rasterized glyph relative to its pen position
+struct CachedGlyph {
+    left: i32,
+    top: i32,
+    width: usize,
+    coverage: Vec<u8>,
+}
+
+/// A TrueType or OpenType font with a cache of rasterized glyphs
+///
+/// Glyphs are rasterized once for every size and quarter pixel offset they are drawn at.
+pub struct Font {
+    font: rusttype::Font<'static>,
+    cache: HashMap<(GlyphId, u32, u32), CachedGlyph>,
+}
+
+impl Font {
+    /// Load a font from the contents of a font file, `None` if it is not a valid font
+    pub fn from_bytes(data: Vec<u8>) -> Option<Font> {
+        Some(Font {
+            font: rusttype::Font::try_from_vec(data)?,
+            cache: HashMap::new(),
+        })
+    }
+
+    /// Distance from the top of a line to its baseline, for text `size` pixels high
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.font.v_metrics(Scale::uniform(size)).ascent
+    }
+
+    /// Distance between the tops of two lines, for text `size` pixels high
+    pub fn line_height(&self, size: f32) -> f32 {
+        let metrics = self.font.v_metrics(Scale::uniform(size));
+        metrics.ascent - metrics.descent + metrics.line_gap
+    }
+
+    /// Width in pixels of the widest line of `text` at `size`, including kerning
+    pub fn measure(&self, size: f32, text: &str) -> f32 {
+        text.split('\n')
+            .map(|line| {
+                let mut width = 0.;
+                self.layout(size, line, |_, x| width = x);
+                width
+            })
+            .fold(0., f32::max)
+    }
+
+    /// Drop every cached glyph
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Call `place` with every glyph of `line` and its pen position, and finally with `None`
+    /// and the width of the line
+    fn layout<F: FnMut(Option<GlyphId>, f32)>(&self, size: f32, line: &str, mut place: F) {
+        let scale = Scale::uniform(size);
+        let mut x = 0.;
+        let mut previous = None;
+        for character in line.chars() {
+            let glyph = self.font.glyph(character).scaled(scale);
+            let id = glyph.id();
+            if let Some(previous) = previous {
+                x += self.font.pair_kerning(scale, previous, id);
+            }
+            place(Some(id), x);
+            x += glyph.h_metrics().advance_width;
+            previous = Some(id);
+        }
+        place(None, x);
+    }
+
+    fn rasterize(&mut self, id: GlyphId, size: f32, subpixel: u32) -> &CachedGlyph {
+        let font = &self.font;
+        self.cache
+            .entry((id, size.to_bits(), subpixel))
+            .or_insert_with(|| {
+                let offset = subpixel as f32 / SUBPIXEL_STEPS as f32;
+                let glyph = font
+                    .glyph(id)
+                    .scaled(Scale::uniform(size))
+                    .positioned(point(offset, 0.));
+                match glyph.pixel_bounding_box() {
+                    Some(bounds) => {
+                        let width = bounds.width() as usize;
+                        let mut coverage = vec![0; width * bounds.height() as usize];
+                        glyph.draw(|x, y, value| {
+                            coverage[y as usize * width + x as usize] =
+                                (value.min(1.) * 255. + 0.5) as u8;
+                        });
+                        CachedGlyph {
+                            left: bounds.min.x,
+                            top: bounds.min.y,
+                            width,
+                            coverage,
+                        }
+                    }
+                    // Glyphs like spaces have no outline
+                    None => CachedGlyph {
+                        left: 0,
+                        top: 0,
+                        width: 0,
+                        coverage: Vec::new(),
+                    },
+                }
+            })
+    }
+}
+
+impl<'a> Canvas<'a> {
+    /// Draw `text` with `font` at `size` pixels high, the top left corner of the first line
+    /// at `position`
+    ///
+    /// Lines are separated by `'\n'`. The glyphs are anti-aliased and composited like
+    /// paths.
+    pub fn draw_font_text(
+        &mut self,
+        font: &mut Font,
+        size: f32,
+        position: (f32, f32),
+        text: &str,
+        color: u32,
+    ) {
+        let line_height = font.line_height(size);
+        let mut baseline = position.1 + font.ascent(size);
+        for line in text.split('\n') {
+            let mut glyphs = Vec::new();
+            font.layout(size, line, |id, x| glyphs.extend(id.map(|id| (id, x))));
+            for (id, x) in glyphs {
+                let x = position.0 + x;
+                // Round to the nearest subpixel step, carrying into whole pixels
+                let steps = (x * SUBPIXEL_STEPS as f32).round();
+                let whole = (steps / SUBPIXEL_STEPS as f32).floor();
+                let subpixel = (steps - whole * SUBPIXEL_STEPS as f32) as u32;
+                let glyph = font.rasterize(id, size, subpixel);
+                if glyph.width > 0 {
+                    self.blend_mask(
+                        (
+                            whole as i32 + glyph.left,
+                            baseline.round() as i32 + glyph.top,
+                        ),
+                        &glyph.coverage,
+                        glyph.width,
+                        color,
+                    );
+                }
+            }
+            baseline += line_height;
+        }
+    }
+}
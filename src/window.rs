@@ -0,0 +1,434 @@
+use std::path::Path;
+
+use winit::dpi::LogicalPosition;
+use winit::{EventsLoop, Window, WindowBuilder};
+
+use crate::backend::{check_len, frame_len};
+use crate::convert;
+use crate::{
+    platform, AlphaMode, Backend, Canvas, CreationError, DrawError, FrameBuffer, FrameStatus,
+    ImageError, ImageFormat, Layout, PixelFormat, PresentMode, PresentedFrame, Recorder, Rect,
+    Scaler,
+};
+
+pub struct YuxaWindow {
+    // Dropped before the window so backends can release resources on a live connection
+    backend: Box<dyn Backend>,
+    window: Window,
+    /// Scale factor last reported by `scale_factor_changed`
+    scale_factor: f64,
+    /// Scale factor last handed to the backend
+    backend_scale_factor: f64,
+    /// Size frames are rendered at when the backend scales them to fill the window
+    render_size: Option<(usize, usize)>,
+    /// Scales frames of a fixed size to the window
+    scaler: Option<Scaler>,
+    /// Captures presented argb frames
+    recorder: Option<Recorder>,
+}
+
+impl YuxaWindow {
+    /// Build a window and attach the default backend for the current platform
+    ///
+    /// Windows built with `WindowBuilder::with_transparency` start in
+    /// `AlphaMode::Premultiplied`, all others in `AlphaMode::Opaque`.
+    pub fn new(
+        window_builder: WindowBuilder,
+        events_loop: &EventsLoop,
+    ) -> Result<Self, CreationError> {
+        let transparent = window_builder.window.transparent;
+        let window = platform::prepare_builder(window_builder, events_loop).build(events_loop)?;
+        let mut backend = platform::default_backend(&window)?;
+        backend.set_alpha_mode(if transparent {
+            AlphaMode::Premultiplied
+        } else {
+            AlphaMode::Opaque
+        });
+
+        Ok(YuxaWindow::with_backend(window, backend))
+    }
+
+    /// Wrap an existing winit window, presenting frames through a custom backend
+    pub fn with_backend(window: Window, mut backend: Box<dyn Backend>) -> Self {
+        let scale_factor = window.get_hidpi_factor();
+        backend.set_scale_factor(scale_factor);
+        YuxaWindow {
+            window,
+            backend,
+            scale_factor,
+            backend_scale_factor: scale_factor,
+            render_size: None,
+            scaler: None,
+            recorder: None,
+        }
+    }
+
+    /// Get reference to the inner winit window
+    pub fn window(&self) -> &Window {
+        &self.window
+    }
+
+    /// Get mutable reference to the inner winit window
+    pub fn window_mut(&mut self) -> &mut Window {
+        &mut self.window
+    }
+
+    /// Get reference to the backend frames are presented through
+    pub fn backend(&self) -> &dyn Backend {
+        &*self.backend
+    }
+
+    /// Get mutable reference to the backend frames are presented through
+    pub fn backend_mut(&mut self) -> &mut dyn Backend {
+        &mut *self.backend
+    }
+
+    /// Change how presenting frames is paced against the display server
+    pub fn set_present_mode(&mut self, mode: PresentMode) {
+        self.backend.set_present_mode(mode);
+    }
+
+    /// Number of physical pixels per logical pixel of the window
+    pub fn scale_factor(&self) -> f64 {
+        self.window.get_hidpi_factor()
+    }
+
+    /// Size of the window in physical pixels, the size frames are drawn at
+    ///
+    /// Returns `None` if the window no longer exists.
+    pub fn physical_size(&self) -> Option<(usize, usize)> {
+        let size = self.window.get_inner_size()?;
+        let (width, height): (u32, u32) = size.to_physical(self.scale_factor()).into();
+        Some((width as usize, height as usize))
+    }
+
+    /// Size frames drawn at the window size have
+    ///
+    /// This is the size of the scaler if one is set, otherwise the render size if one is set
+    /// and the physical size of the window otherwise.
+    pub fn frame_size(&self) -> Option<(usize, usize)> {
+        match self.scaler {
+            Some(scaler) => Some(scaler.size()),
+            None => self.output_size(),
+        }
+    }
+
+    /// Draw frames of a fixed size that yuxa scales to the window, or frames at the window
+    /// size again with `None`
+    ///
+    /// Frames given to `draw_with_layout` are not scaled.
+    pub fn set_scaler(&mut self, scaler: Option<Scaler>) {
+        self.scaler = scaler;
+    }
+
+    /// The pixel of the frame under `position`, a position within the window like the ones
+    /// reported by `WindowEvent::CursorMoved`
+    ///
+    /// Returns `None` when the position lies outside of the frame, for example in the border
+    /// drawn by a scaler.
+    pub fn frame_position(&self, position: LogicalPosition) -> Option<(usize, usize)> {
+        let physical = position.to_physical(self.scale_factor());
+        let (physical_width, physical_height) = self.physical_size()?;
+        let output = self.output_size()?;
+        // Frames rendered at a render size are stretched over the whole window
+        let position = (
+            physical.x * output.0 as f64 / physical_width.max(1) as f64,
+            physical.y * output.1 as f64 / physical_height.max(1) as f64,
+        );
+        match self.scaler {
+            Some(scaler) => scaler.to_source(output, position),
+            None if position.0 >= 0.
+                && position.1 >= 0.
+                && position.0 < output.0 as f64
+                && position.1 < output.1 as f64 =>
+            {
+                Some((position.0 as usize, position.1 as usize))
+            }
+            None => None,
+        }
+    }
+
+    /// Render frames at `size` pixels and have the display server scale them to fill the
+    /// window, or at the physical size of the window again with `None`
+    ///
+    /// This allows fractional scale factors, and rendering at a lower resolution to save
+    /// time. Frames given to `draw_with_layout` are scaled to the window as well. Returns false,
+    /// keeping the previous render size, when the backend cannot scale frames.
+    pub fn set_render_size(&mut self, size: Option<(usize, usize)>) -> bool {
+        let destination = match size {
+            Some(_) => match self.logical_size() {
+                Some(logical) => Some(logical),
+                None => return false,
+            },
+            None => None,
+        };
+        if !self.backend.set_destination_size(destination) {
+            return false;
+        }
+        self.render_size = size;
+        true
+    }
+
+    /// Check whether the scale factor changed since the last call, for example because the
+    /// window moved to a monitor with a different DPI
+    ///
+    /// Returns the new factor so the next frame can be rendered at the new physical size.
+    /// Winit reports the same change as `WindowEvent::HiDpiFactorChanged`.
+    pub fn scale_factor_changed(&mut self) -> Option<f64> {
+        let scale_factor = self.sync_scale_factor();
+        if scale_factor == self.scale_factor {
+            return None;
+        }
+        self.scale_factor = scale_factor;
+        Some(scale_factor)
+    }
+
+    /// Hand the current scale factor to the backend if it changed, leaving the change pending
+    /// for `scale_factor_changed`
+    fn sync_scale_factor(&mut self) -> f64 {
+        let scale_factor = self.scale_factor();
+        if scale_factor != self.backend_scale_factor {
+            self.backend_scale_factor = scale_factor;
+            self.backend.set_scale_factor(scale_factor);
+        }
+        scale_factor
+    }
+
+    /// Change how the alpha channel of drawn pixels is interpreted
+    ///
+    /// Translucency needs a window built with `WindowBuilder::with_transparency`, straight
+    /// alpha is premultiplied before the frame is presented.
+    pub fn set_alpha_mode(&mut self, mode: AlphaMode) {
+        self.backend.set_alpha_mode(mode);
+    }
+
+    /// Change how many buffers frames are cycled through, for example 3 for triple buffering
+    pub fn set_buffer_count(&mut self, count: usize) -> Result<(), DrawError> {
+        self.backend.set_buffer_count(count)
+    }
+
+    /// Wait for a buffer to be released instead of returning `FrameStatus::Dropped` when the
+    /// display server still holds every buffer
+    pub fn set_wait_for_buffer(&mut self, wait: bool) {
+        self.backend.set_wait_for_buffer(wait);
+    }
+
+    /// Keep a copy of every presented frame exactly as it was handed to the display server,
+    /// after scaling and alpha conversion, so it can be inspected with `last_frame` or saved
+    /// with `save_frame`
+    ///
+    /// Retaining costs a copy of every frame. Backends that cannot retain frames ignore this.
+    pub fn set_retain_frame(&mut self, retain: bool) {
+        self.backend.set_retain_frame(retain);
+    }
+
+    /// The last frame presented since retaining frames was turned on
+    pub fn last_frame(&self) -> Option<&PresentedFrame> {
+        self.backend.last_frame()
+    }
+
+    /// Read what the window currently shows back from the display server
+    ///
+    /// On X11 this uses `XGetImage`, so the window has to be mapped, and regions covered by
+    /// other windows may hold their contents unless a compositor is running. Returns `None`
+    /// when the backend cannot read back, like on Wayland, or the window is not viewable.
+    pub fn read_back(&mut self) -> Option<PresentedFrame> {
+        self.backend.read_back()
+    }
+
+    /// Save the last retained frame as an image file at `path`
+    ///
+    /// Returns false, writing nothing, when no frame has been retained.
+    pub fn save_frame<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ImageFormat,
+    ) -> Result<bool, ImageError> {
+        match self.last_frame() {
+            Some(frame) => frame.save(path, format).map(|()| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Capture every frame presented through `draw_argb32`, `draw_argb8888`,
+    /// `draw_argb8888_bytes`, `draw_damaged` and `draw_canvas` with `recorder`, or stop
+    /// recording with `None`
+    ///
+    /// Frames are recorded at the size they are drawn at, before scaling. Returns the recorder
+    /// attached before, holding the frames it recorded.
+    pub fn set_recorder(&mut self, recorder: Option<Recorder>) -> Option<Recorder> {
+        std::mem::replace(&mut self.recorder, recorder)
+    }
+
+    /// The recorder capturing presented frames, if one is attached
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Get mutable reference to the recorder capturing presented frames, if one is attached
+    pub fn recorder_mut(&mut self) -> Option<&mut Recorder> {
+        self.recorder.as_mut()
+    }
+
+    /// Whether the display server is ready for the next frame
+    ///
+    /// With `PresentMode::FrameCallback` this becomes true once the compositor asks for a new
+    /// frame, poll it after handling window events to draw at the display rate.
+    pub fn frame_ready(&mut self) -> Result<bool, DrawError> {
+        self.backend.frame_ready()
+    }
+
+    /// Start a frame at the current window size that is rendered straight into the memory
+    /// the backend presents from, avoiding a copy where the backend supports it
+    pub fn begin_frame(&mut self) -> Result<FrameBuffer<'_>, DrawError> {
+        let output = self.sync_output_size()?;
+        match self.scaler {
+            Some(scaler) => Ok(FrameBuffer::scaled(&mut *self.backend, scaler, output)),
+            None => FrameBuffer::new(&mut *self.backend, output),
+        }
+    }
+
+    /// Present a tightly packed frame at the current window size in any `PixelFormat`
+    ///
+    /// The frame is converted only when the backend cannot present `format` natively.
+    pub fn draw(&mut self, buffer: &[u8], format: PixelFormat) -> Result<FrameStatus, DrawError> {
+        let output = self.sync_output_size()?;
+        if let Some(scaler) = self.scaler {
+            let (width, height) = scaler.size();
+            let layout = Layout::packed(width, height, format);
+            check_len(layout.min_len(), buffer.len())?;
+            let pixels = layout.pack_argb32(buffer);
+            return scaler.present(&mut *self.backend, output, &pixels);
+        }
+        self.backend
+            .draw_with_layout(buffer, Layout::packed(output.0, output.1, format))
+    }
+
+    pub fn draw_argb8888(&mut self, buffer: &[[u8; 4]]) -> Result<FrameStatus, DrawError> {
+        if self.scaler.is_some() {
+            return self.draw_argb8888_bytes(convert::argb_arrays_as_bytes(buffer));
+        }
+        let output = self.sync_output_size()?;
+        let status = self.backend.draw_argb8888(output, buffer)?;
+        self.record_argb8888_bytes(status, output, convert::argb_arrays_as_bytes(buffer));
+        Ok(status)
+    }
+
+    pub fn draw_argb8888_bytes(&mut self, buffer: &[u8]) -> Result<FrameStatus, DrawError> {
+        let output = self.sync_output_size()?;
+        if let Some(scaler) = self.scaler {
+            let (width, height) = scaler.size();
+            check_len(frame_len((width, height), 4), buffer.len())?;
+            let mut pixels = vec![0; width * height];
+            convert::argb_bytes_to_argb32(buffer, &mut pixels);
+            let status = scaler.present(&mut *self.backend, output, &pixels)?;
+            self.record(status, (width, height), &pixels);
+            return Ok(status);
+        }
+        let status = self.backend.draw_argb8888_bytes(output, buffer)?;
+        self.record_argb8888_bytes(status, output, buffer);
+        Ok(status)
+    }
+
+    pub fn draw_argb32(&mut self, buffer: &[u32]) -> Result<FrameStatus, DrawError> {
+        let output = self.sync_output_size()?;
+        let (status, dimensions) = match self.scaler {
+            Some(scaler) => (
+                scaler.present(&mut *self.backend, output, buffer)?,
+                scaler.size(),
+            ),
+            None => (self.backend.draw_argb32(output, buffer)?, output),
+        };
+        self.record(status, dimensions, buffer);
+        Ok(status)
+    }
+
+    /// Present the pixels of `canvas`, which should match the size of `frame_size`
+    pub fn draw_canvas(&mut self, canvas: &Canvas) -> Result<FrameStatus, DrawError> {
+        self.draw_argb32(&canvas.packed_pixels())
+    }
+
+    /// Present an argb32 frame where only the regions in `damage` changed since the last frame
+    ///
+    /// Scaled frames are always presented in full.
+    pub fn draw_damaged(
+        &mut self,
+        buffer: &[u32],
+        damage: &[Rect],
+    ) -> Result<FrameStatus, DrawError> {
+        let output = self.sync_output_size()?;
+        let (status, dimensions) = match self.scaler {
+            Some(scaler) => (
+                scaler.present(&mut *self.backend, output, buffer)?,
+                scaler.size(),
+            ),
+            None => (self.backend.draw_damaged(output, buffer, damage)?, output),
+        };
+        self.record(status, dimensions, buffer);
+        Ok(status)
+    }
+
+    /// Present a frame described by `layout`, independent of the current window size
+    pub fn draw_with_layout(
+        &mut self,
+        buffer: &[u8],
+        layout: Layout,
+    ) -> Result<FrameStatus, DrawError> {
+        self.sync_output_size()?;
+        self.backend.draw_with_layout(buffer, layout)
+    }
+
+    /// Hand a frame of `dimensions` to the recorder unless it was dropped
+    fn record(&mut self, status: FrameStatus, dimensions: (usize, usize), buffer: &[u32]) {
+        if let Some(recorder) = &mut self.recorder {
+            if status != FrameStatus::Dropped {
+                // Presenting already checked the length of the frame
+                let _ = recorder.record(dimensions, buffer);
+            }
+        }
+    }
+
+    /// `record` for frames given as the bytes `a, r, g, b`
+    fn record_argb8888_bytes(
+        &mut self,
+        status: FrameStatus,
+        dimensions: (usize, usize),
+        buffer: &[u8],
+    ) {
+        if self.recorder.is_some() && status != FrameStatus::Dropped {
+            let mut pixels = vec![0; dimensions.0 * dimensions.1];
+            convert::argb_bytes_to_argb32(buffer, &mut pixels);
+            self.record(status, dimensions, &pixels);
+        }
+    }
+
+    /// Size of the window in logical pixels, rounded to whole pixels
+    fn logical_size(&self) -> Option<(usize, usize)> {
+        let size = self.window.get_inner_size()?;
+        Some((size.width.round() as usize, size.height.round() as usize))
+    }
+
+    /// Size of the frames handed to the backend, the render size if one is set and the
+    /// physical size of the window otherwise
+    fn output_size(&self) -> Option<(usize, usize)> {
+        self.render_size.or_else(|| self.physical_size())
+    }
+
+    /// `output_size`, keeping the backend's scale factor and destination size up to date
+    fn sync_output_size(&mut self) -> Result<(usize, usize), DrawError> {
+        self.sync_scale_factor();
+        if self.render_size.is_some() {
+            // Follow resizes of the window
+            let logical = self.logical_size().ok_or(DrawError::WindowGone)?;
+            self.backend.set_destination_size(Some(logical));
+        }
+        self.output_size().ok_or(DrawError::WindowGone)
+    }
+}
+
+impl std::ops::Deref for YuxaWindow {
+    type Target = Window;
+    fn deref(&self) -> &Self::Target {
+        self.window()
+    }
+}